@@ -0,0 +1,981 @@
+use crate::transitionsolver::SolverSettings;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+#[cfg(feature = "z3")]
+use z3::ast::Ast;
+
+// The interface between the timeline encoding and an SMT solver. The encoding
+// builds its terms through the backend, so it does not depend on a particular
+// solver's API.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckResult {
+    Sat,
+    Unsat,
+    Unknown,
+}
+
+pub trait Backend {
+    type Bool: Clone + Eq + Hash + Debug;
+    type Real: Clone + Debug;
+
+    fn fresh_bool(&self, prefix: &str) -> Self::Bool;
+    fn fresh_real(&self, prefix: &str) -> Self::Real;
+    fn bool_const(&self, value: bool) -> Self::Bool;
+    fn real_const(&self, num: i64, den: i64) -> Self::Real;
+
+    fn not(&self, a: &Self::Bool) -> Self::Bool;
+    fn and(&self, args: &[&Self::Bool]) -> Self::Bool;
+    fn or(&self, args: &[&Self::Bool]) -> Self::Bool;
+    fn implies(&self, a: &Self::Bool, b: &Self::Bool) -> Self::Bool;
+    // The sum of the weights of the true literals is at most `k`.
+    fn pb_le(&self, args: &[(&Self::Bool, i32)], k: i32) -> Self::Bool;
+
+    fn add(&self, args: &[&Self::Real]) -> Self::Real;
+    fn sub(&self, args: &[&Self::Real]) -> Self::Real;
+    fn mul(&self, args: &[&Self::Real]) -> Self::Real;
+    fn ite(&self, c: &Self::Bool, a: &Self::Real, b: &Self::Real) -> Self::Real;
+    fn le(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool;
+    fn lt(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool;
+    fn ge(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool;
+    fn eq(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool;
+
+    fn assert(&self, a: &Self::Bool);
+    fn check(&self, assumptions: &[Self::Bool]) -> CheckResult;
+    // A subset of the assumptions of the last (unsatisfiable) check.
+    fn unsat_core(&self) -> Vec<Self::Bool>;
+    // Values in the model of the last (satisfiable) check.
+    fn bool_value(&self, a: &Self::Bool) -> bool;
+    fn real_value(&self, a: &Self::Real) -> (i64, i64);
+
+    // Limits for the following checks, or the limits the backend was configured with.
+    fn set_budget(&self, _timeout_ms: Option<u32>, _max_conflicts: Option<u32>) {}
+    // Why the last check was unknown or gave an empty core, other than a timeout
+    // or an interrupt.
+    fn error(&self) -> Option<String> {
+        None
+    }
+    // Whether the backend can prefer values for literals without assuming them.
+    fn supports_phase(&self) -> bool {
+        false
+    }
+    // Try the given literals as true first in the following checks.
+    fn prefer(&self, _lits: &[Self::Bool]) {}
+
+    // The names of fresh variables, or none for other terms.
+    fn bool_name(&self, a: &Self::Bool) -> Option<String>;
+    fn real_name(&self, a: &Self::Real) -> Option<String>;
+    // A term in SMT-LIB2 syntax.
+    fn display(&self, a: &Self::Bool) -> String;
+    // The declarations and assertions so far, in SMT-LIB2.
+    fn formula(&self) -> String;
+    // The solver parameters as SMT-LIB2 commands, for replaying the formula.
+    fn options(&self) -> String {
+        String::new()
+    }
+    fn describe(&self) -> String;
+}
+
+// Run `f`, calling `interrupt` from a watcher thread if the settings' cancel
+// flag is set in the meantime.
+pub(crate) fn run_interruptible<T>(
+    settings: &SolverSettings,
+    interrupt: impl Fn() + Send + Sync,
+    f: impl FnOnce() -> T,
+) -> T {
+    let cancel = match settings.cancel.as_ref() {
+        Some(cancel) => cancel,
+        None => return f(),
+    };
+
+    let done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        let done = &done;
+        let interrupt = &interrupt;
+        scope.spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                if cancel.load(Ordering::SeqCst) {
+                    interrupt();
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+        });
+
+        let result = f();
+        done.store(true, Ordering::SeqCst);
+        result
+    })
+}
+
+//
+// Z3
+//
+
+#[cfg(feature = "z3")]
+pub struct Z3Backend<'ctx> {
+    ctx: &'ctx z3::Context,
+    solver: z3::Solver<'ctx>,
+    params: String,
+    // The parameters as given, in the order they were set.
+    options: Vec<(String, String)>,
+    // The timeout and conflict limit set in the Z3 parameters, which apply
+    // again when a budget is lifted.
+    default_budget: (Option<u32>, Option<u32>),
+    // Fetched from the solver when the first value is asked for.
+    model: RefCell<Option<z3::Model<'ctx>>>,
+}
+
+#[cfg(feature = "z3")]
+fn set_z3_param(params: &mut z3::Params, key: &str, value: &str) {
+    if let Ok(b) = value.parse::<bool>() {
+        params.set_bool(key, b);
+    } else if let Ok(n) = value.parse::<u32>() {
+        params.set_u32(key, n);
+    } else if let Ok(x) = value.parse::<f64>() {
+        params.set_f64(key, x);
+    } else {
+        params.set_symbol(key, value);
+    }
+}
+
+#[cfg(feature = "z3")]
+impl<'ctx> Z3Backend<'ctx> {
+    pub fn new(ctx: &'ctx z3::Context, settings: &SolverSettings) -> Z3Backend<'ctx> {
+        let solver = z3::Solver::new(ctx);
+        let mut options = vec![
+            ("auto_config".to_string(), settings.auto_config.to_string()),
+            (
+                "smt.arith.solver".to_string(),
+                settings.arith_solver.to_string(),
+            ),
+        ];
+        if let Some(seed) = settings.random_seed.or(if settings.deterministic {
+            Some(0)
+        } else {
+            None
+        }) {
+            options.push(("random_seed".to_string(), seed.to_string()));
+        }
+        options.extend(settings.z3_params.iter().cloned());
+        let mut params = z3::Params::new(ctx);
+        for (key, value) in options.iter() {
+            set_z3_param(&mut params, key, value);
+        }
+        solver.set_params(&params);
+
+        let param = |key: &str| {
+            settings
+                .z3_params
+                .iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .and_then(|(_, v)| v.parse::<u32>().ok())
+        };
+        Z3Backend {
+            ctx,
+            solver,
+            params: params.to_string(),
+            options,
+            default_budget: (param("timeout"), param("max_conflicts")),
+            model: RefCell::new(None),
+        }
+    }
+
+    fn eval<T: Ast<'ctx>>(&self, a: &T) -> T {
+        let mut model = self.model.borrow_mut();
+        let model = model.get_or_insert_with(|| self.solver.get_model().unwrap());
+        model.eval(a, true).unwrap()
+    }
+}
+
+#[cfg(feature = "z3")]
+fn z3_name<'ctx>(a: &(impl Ast<'ctx> + std::fmt::Display)) -> Option<String> {
+    (a.is_const() && a.decl().kind() == z3::DeclKind::UNINTERPRETED).then(|| a.to_string())
+}
+
+#[cfg(feature = "z3")]
+impl<'ctx> Backend for Z3Backend<'ctx> {
+    type Bool = z3::ast::Bool<'ctx>;
+    type Real = z3::ast::Real<'ctx>;
+
+    fn fresh_bool(&self, prefix: &str) -> Self::Bool {
+        z3::ast::Bool::fresh_const(self.ctx, prefix)
+    }
+
+    fn fresh_real(&self, prefix: &str) -> Self::Real {
+        z3::ast::Real::fresh_const(self.ctx, prefix)
+    }
+
+    fn bool_const(&self, value: bool) -> Self::Bool {
+        z3::ast::Bool::from_bool(self.ctx, value)
+    }
+
+    fn real_const(&self, num: i64, den: i64) -> Self::Real {
+        if num.unsigned_abs() <= i32::MAX as u64 && den <= i32::MAX as i64 {
+            z3::ast::Real::from_real(self.ctx, num as i32, den as i32)
+        } else {
+            z3::ast::Real::from_real_str(self.ctx, &num.to_string(), &den.to_string()).unwrap()
+        }
+    }
+
+    fn not(&self, a: &Self::Bool) -> Self::Bool {
+        a.not()
+    }
+
+    fn and(&self, args: &[&Self::Bool]) -> Self::Bool {
+        z3::ast::Bool::and(self.ctx, args)
+    }
+
+    fn or(&self, args: &[&Self::Bool]) -> Self::Bool {
+        z3::ast::Bool::or(self.ctx, args)
+    }
+
+    fn implies(&self, a: &Self::Bool, b: &Self::Bool) -> Self::Bool {
+        a.implies(b)
+    }
+
+    fn pb_le(&self, args: &[(&Self::Bool, i32)], k: i32) -> Self::Bool {
+        z3::ast::Bool::pb_le(self.ctx, args, k)
+    }
+
+    fn add(&self, args: &[&Self::Real]) -> Self::Real {
+        z3::ast::Real::add(self.ctx, args)
+    }
+
+    fn sub(&self, args: &[&Self::Real]) -> Self::Real {
+        z3::ast::Real::sub(self.ctx, args)
+    }
+
+    fn mul(&self, args: &[&Self::Real]) -> Self::Real {
+        z3::ast::Real::mul(self.ctx, args)
+    }
+
+    fn ite(&self, c: &Self::Bool, a: &Self::Real, b: &Self::Real) -> Self::Real {
+        c.ite(a, b)
+    }
+
+    fn le(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool {
+        a.le(b)
+    }
+
+    fn lt(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool {
+        a.lt(b)
+    }
+
+    fn ge(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool {
+        a.ge(b)
+    }
+
+    fn eq(&self, a: &Self::Real, b: &Self::Real) -> Self::Bool {
+        a._eq(b)
+    }
+
+    fn assert(&self, a: &Self::Bool) {
+        self.solver.assert(a);
+    }
+
+    fn check(&self, assumptions: &[Self::Bool]) -> CheckResult {
+        *self.model.borrow_mut() = None;
+        match self.solver.check_assumptions(assumptions) {
+            z3::SatResult::Sat => CheckResult::Sat,
+            z3::SatResult::Unsat => CheckResult::Unsat,
+            z3::SatResult::Unknown => CheckResult::Unknown,
+        }
+    }
+
+    fn unsat_core(&self) -> Vec<Self::Bool> {
+        self.solver.get_unsat_core()
+    }
+
+    fn bool_value(&self, a: &Self::Bool) -> bool {
+        self.eval(a).as_bool().unwrap()
+    }
+
+    fn real_value(&self, a: &Self::Real) -> (i64, i64) {
+        self.eval(a).as_real().unwrap()
+    }
+
+    fn set_budget(&self, timeout_ms: Option<u32>, max_conflicts: Option<u32>) {
+        let mut params = z3::Params::new(self.ctx);
+        let (default_timeout_ms, default_max_conflicts) = self.default_budget;
+        params.set_u32(
+            "timeout",
+            timeout_ms.or(default_timeout_ms).unwrap_or(u32::MAX),
+        );
+        params.set_u32(
+            "max_conflicts",
+            max_conflicts.or(default_max_conflicts).unwrap_or(u32::MAX),
+        );
+        self.solver.set_params(&params);
+    }
+
+    fn bool_name(&self, a: &Self::Bool) -> Option<String> {
+        z3_name(a)
+    }
+
+    fn real_name(&self, a: &Self::Real) -> Option<String> {
+        z3_name(a)
+    }
+
+    fn display(&self, a: &Self::Bool) -> String {
+        a.to_string()
+    }
+
+    fn formula(&self) -> String {
+        self.solver.to_string()
+    }
+
+    fn options(&self) -> String {
+        self.options
+            .iter()
+            .map(|(key, value)| format!("(set-option :{} {})\n", key, value))
+            .collect()
+    }
+
+    fn describe(&self) -> String {
+        format!("Z3 params: {}", self.params)
+    }
+}
+
+//
+// SMT-LIB2 solver process
+//
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SmtLibBool(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SmtLibReal(usize);
+
+// Each term is either a fresh variable, a constant, or a compound term that is
+// named by a `define-fun`, so that shared subterms are only sent once.
+struct SmtLibTerm {
+    reference: String,
+    text: String,
+    fresh: bool,
+}
+
+pub struct SmtLibProcess {
+    child: Arc<Mutex<Child>>,
+    state: RefCell<SmtLibState>,
+}
+
+struct SmtLibState {
+    command: String,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    script: String,
+    terms: Vec<SmtLibTerm>,
+    n_fresh: usize,
+    // Assumption literals must be variables or their negations.
+    negations: HashMap<usize, usize>,
+    proxies: HashMap<usize, String>,
+    last_assumptions: Vec<(String, SmtLibBool)>,
+    // The option that limits the time of each check.
+    timeout_option: &'static str,
+    // The first error reply, or the process exiting. The replies that follow
+    // can not be matched to their commands, so all later checks are unknown.
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SExpr {
+    Atom(String),
+    List(Vec<SExpr>),
+}
+
+fn parse_sexprs(text: &str) -> Vec<SExpr> {
+    let mut stack: Vec<Vec<SExpr>> = vec![Vec::new()];
+    let mut atom = String::new();
+    let mut quoted = None;
+    for c in text.chars() {
+        if let Some(q) = quoted {
+            atom.push(c);
+            if c == q {
+                quoted = None;
+            }
+            continue;
+        }
+        if c == '(' || c == ')' || c.is_whitespace() {
+            if !atom.is_empty() {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .push(SExpr::Atom(std::mem::take(&mut atom)));
+            }
+            if c == '(' {
+                stack.push(Vec::new());
+            } else if c == ')' && stack.len() > 1 {
+                let list = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(SExpr::List(list));
+            }
+        } else {
+            if c == '|' || c == '"' {
+                quoted = Some(c);
+            }
+            atom.push(c);
+        }
+    }
+    if !atom.is_empty() {
+        stack.last_mut().unwrap().push(SExpr::Atom(atom));
+    }
+    stack.swap_remove(0)
+}
+
+// The nesting depth at the end of the text, not counting the parentheses in
+// string literals and `|quoted|` symbols. An escaped quote `""` in a string
+// ends and starts the literal again.
+fn paren_depth(text: &str) -> isize {
+    let mut depth = 0;
+    let mut quoted = None;
+    for c in text.chars() {
+        match quoted {
+            Some(q) if c == q => quoted = None,
+            Some(_) => {}
+            None if c == '|' || c == '"' => quoted = Some(c),
+            None if c == '(' => depth += 1,
+            None if c == ')' => depth -= 1,
+            None => {}
+        }
+    }
+    depth
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs().max(1)
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// None if the value is not a rational number that fits in i64.
+fn parse_rational(value: &SExpr) -> Option<(i64, i64)> {
+    let (num, den) = match value {
+        SExpr::Atom(a) => {
+            let (int, frac) = a.split_once('.').unwrap_or((a, ""));
+            let den = 10_i64.checked_pow(frac.len() as u32)?;
+            let num = format!("{}{}", int, frac).parse::<i64>().ok()?;
+            (num, den)
+        }
+        SExpr::List(l) => match (l.first(), l.len()) {
+            (Some(SExpr::Atom(op)), 2) if op == "-" => {
+                let (num, den) = parse_rational(&l[1])?;
+                (num.checked_neg()?, den)
+            }
+            (Some(SExpr::Atom(op)), 3) if op == "/" => {
+                let (n1, d1) = parse_rational(&l[1])?;
+                let (n2, d2) = parse_rational(&l[2])?;
+                let (num, den) = (n1.checked_mul(d2)?, d1.checked_mul(n2)?);
+                if den < 0 {
+                    (num.checked_neg()?, -den)
+                } else {
+                    (num, den)
+                }
+            }
+            _ => return None,
+        },
+    };
+    let d = gcd(num, den);
+    Some((num / d, den / d))
+}
+
+pub(crate) fn real_literal(num: i64, den: i64) -> String {
+    let abs = |x: i64| format!("{}.0", x.abs());
+    let num_text = if num < 0 {
+        format!("(- {})", abs(num))
+    } else {
+        abs(num)
+    };
+    if den == 1 {
+        num_text
+    } else {
+        format!("(/ {} {})", num_text, abs(den))
+    }
+}
+
+fn symbol(name: &str) -> String {
+    let simple = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/".contains(c));
+    if simple {
+        name.to_string()
+    } else {
+        format!("|{}|", name.replace('|', "_"))
+    }
+}
+
+impl SmtLibProcess {
+    pub fn new(command: &[String]) -> std::io::Result<SmtLibProcess> {
+        if command.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Empty SMT-LIB2 solver command",
+            ));
+        }
+        let mut child = Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        // `timeout` is Z3's option, and CVC4 and cvc5 call it `tlimit-per`.
+        let program = Path::new(&command[0])
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let timeout_option = if program.starts_with("cvc") {
+            "tlimit-per"
+        } else {
+            "timeout"
+        };
+        let mut state = SmtLibState {
+            command: command.join(" "),
+            stdin,
+            stdout,
+            script: String::new(),
+            terms: Vec::new(),
+            n_fresh: 0,
+            negations: HashMap::new(),
+            proxies: HashMap::new(),
+            last_assumptions: Vec::new(),
+            timeout_option,
+            error: None,
+        };
+        state.send("(set-option :produce-models true)");
+        state.send("(set-option :produce-unsat-assumptions true)");
+        state.send("(set-logic QF_LRA)");
+        Ok(SmtLibProcess {
+            child: Arc::new(Mutex::new(child)),
+            state: RefCell::new(state),
+        })
+    }
+
+    // Stops a running check by killing the solver process.
+    pub fn interrupter(&self) -> impl Fn() + Send + Sync {
+        let child = self.child.clone();
+        move || {
+            let _ = child.lock().unwrap().kill();
+        }
+    }
+
+    fn apply(&self, sort: &str, op: &str, args: &[usize]) -> usize {
+        self.state.borrow_mut().apply(sort, op, args)
+    }
+}
+
+impl SmtLibState {
+    fn send(&mut self, command: &str) {
+        self.script.push_str(command);
+        self.script.push('\n');
+        // A failed write means that the process has stopped, which is
+        // reported by the next check.
+        let _ = writeln!(self.stdin, "{}", command);
+    }
+
+    // Read one complete s-expression or atom from the solver's output. Errors
+    // are recorded in `error`, and `unsupported` replies to options are skipped.
+    fn read_response(&mut self) -> Option<SExpr> {
+        if self.error.is_some() {
+            return None;
+        }
+        loop {
+            let mut text = String::new();
+            loop {
+                let mut line = String::new();
+                let n = self
+                    .stdin
+                    .flush()
+                    .and_then(|_| self.stdout.read_line(&mut line));
+                if !matches!(n, Ok(n) if n > 0) {
+                    self.error = Some(format!("{} exited", self.command));
+                    return None;
+                }
+                text.push_str(&line);
+                if paren_depth(&text) <= 0 && !text.trim().is_empty() {
+                    break;
+                }
+            }
+            let response = parse_sexprs(&text).pop()?;
+            match &response {
+                SExpr::List(l) if l.first() == Some(&SExpr::Atom("error".to_string())) => {
+                    self.error = Some(format!("Error from {}: {}", self.command, text.trim()));
+                    return None;
+                }
+                SExpr::Atom(a) if a == "unsupported" => continue,
+                _ => return Some(response),
+            }
+        }
+    }
+
+    fn fresh(&mut self, prefix: &str, sort: &str) -> usize {
+        let name = symbol(&format!("{}!{}", prefix, self.n_fresh));
+        self.n_fresh += 1;
+        self.send(&format!("(declare-fun {} () {})", name, sort));
+        self.terms.push(SmtLibTerm {
+            reference: name.clone(),
+            text: name,
+            fresh: true,
+        });
+        self.terms.len() - 1
+    }
+
+    fn constant(&mut self, text: String) -> usize {
+        self.terms.push(SmtLibTerm {
+            reference: text.clone(),
+            text,
+            fresh: false,
+        });
+        self.terms.len() - 1
+    }
+
+    fn apply(&mut self, sort: &str, op: &str, args: &[usize]) -> usize {
+        let args = args
+            .iter()
+            .map(|a| self.terms[*a].reference.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let text = format!("({} {})", op, args);
+        let name = format!("_t{}", self.terms.len());
+        self.send(&format!("(define-fun {} () {} {})", name, sort, text));
+        self.terms.push(SmtLibTerm {
+            reference: name,
+            text,
+            fresh: false,
+        });
+        self.terms.len() - 1
+    }
+
+    fn assumption_literal(&mut self, a: usize) -> String {
+        if self.terms[a].fresh {
+            return self.terms[a].reference.clone();
+        }
+        if let Some(v) = self.negations.get(&a) {
+            if self.terms[*v].fresh {
+                return format!("(not {})", self.terms[*v].reference);
+            }
+        }
+        if let Some(proxy) = self.proxies.get(&a) {
+            return proxy.clone();
+        }
+        let proxy = self.fresh("assumption", "Bool");
+        let definition = format!(
+            "(assert (= {} {}))",
+            self.terms[proxy].reference, self.terms[a].reference
+        );
+        self.send(&definition);
+        let proxy = self.terms[proxy].reference.clone();
+        self.proxies.insert(a, proxy.clone());
+        proxy
+    }
+
+    // Records the first error, so that callers can give up after checking `error`.
+    fn fail(&mut self, message: String) {
+        self.error.get_or_insert(message);
+    }
+
+    fn value(&mut self, term: usize) -> Option<SExpr> {
+        let reference = self.terms[term].reference.clone();
+        self.send(&format!("(get-value ({}))", reference));
+        let response = self.read_response()?;
+        if let SExpr::List(pairs) = &response {
+            if let [SExpr::List(pair)] = &pairs[..] {
+                if let [_, value] = &pair[..] {
+                    return Some(value.clone());
+                }
+            }
+        }
+        self.fail(format!(
+            "Unexpected value from {}: {:?}",
+            self.command, response
+        ));
+        None
+    }
+
+    fn name(&self, term: usize) -> Option<String> {
+        self.terms[term]
+            .fresh
+            .then(|| self.terms[term].text.clone())
+    }
+}
+
+impl Drop for SmtLibProcess {
+    fn drop(&mut self) {
+        let state = self.state.get_mut();
+        state.send("(exit)");
+        let _ = state.stdin.flush();
+        let mut child = self.child.lock().unwrap();
+        if !matches!(child.try_wait(), Ok(Some(_))) {
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+    }
+}
+
+fn handles<T>(args: &[&T], index: impl Fn(&T) -> usize) -> Vec<usize> {
+    args.iter().map(|a| index(a)).collect()
+}
+
+impl Backend for SmtLibProcess {
+    type Bool = SmtLibBool;
+    type Real = SmtLibReal;
+
+    fn fresh_bool(&self, prefix: &str) -> SmtLibBool {
+        SmtLibBool(self.state.borrow_mut().fresh(prefix, "Bool"))
+    }
+
+    fn fresh_real(&self, prefix: &str) -> SmtLibReal {
+        SmtLibReal(self.state.borrow_mut().fresh(prefix, "Real"))
+    }
+
+    fn bool_const(&self, value: bool) -> SmtLibBool {
+        SmtLibBool(self.state.borrow_mut().constant(value.to_string()))
+    }
+
+    fn real_const(&self, num: i64, den: i64) -> SmtLibReal {
+        SmtLibReal(self.state.borrow_mut().constant(real_literal(num, den)))
+    }
+
+    fn not(&self, a: &SmtLibBool) -> SmtLibBool {
+        let t = self.apply("Bool", "not", &[a.0]);
+        self.state.borrow_mut().negations.insert(t, a.0);
+        SmtLibBool(t)
+    }
+
+    fn and(&self, args: &[&SmtLibBool]) -> SmtLibBool {
+        match args.len() {
+            0 => self.bool_const(true),
+            1 => *args[0],
+            _ => SmtLibBool(self.apply("Bool", "and", &handles(args, |a| a.0))),
+        }
+    }
+
+    fn or(&self, args: &[&SmtLibBool]) -> SmtLibBool {
+        match args.len() {
+            0 => self.bool_const(false),
+            1 => *args[0],
+            _ => SmtLibBool(self.apply("Bool", "or", &handles(args, |a| a.0))),
+        }
+    }
+
+    fn implies(&self, a: &SmtLibBool, b: &SmtLibBool) -> SmtLibBool {
+        SmtLibBool(self.apply("Bool", "=>", &[a.0, b.0]))
+    }
+
+    fn pb_le(&self, args: &[(&SmtLibBool, i32)], k: i32) -> SmtLibBool {
+        // Weighted sum of 0/1 terms.
+        let zero = self.real_const(0, 1);
+        let terms = args
+            .iter()
+            .map(|(a, w)| self.ite(a, &self.real_const(*w as i64, 1), &zero))
+            .collect::<Vec<_>>();
+        let sum = self.add(&terms.iter().collect::<Vec<_>>());
+        self.le(&sum, &self.real_const(k as i64, 1))
+    }
+
+    fn add(&self, args: &[&SmtLibReal]) -> SmtLibReal {
+        match args.len() {
+            0 => self.real_const(0, 1),
+            1 => *args[0],
+            _ => SmtLibReal(self.apply("Real", "+", &handles(args, |a| a.0))),
+        }
+    }
+
+    fn sub(&self, args: &[&SmtLibReal]) -> SmtLibReal {
+        match args.len() {
+            1 => *args[0],
+            _ => SmtLibReal(self.apply("Real", "-", &handles(args, |a| a.0))),
+        }
+    }
+
+    fn mul(&self, args: &[&SmtLibReal]) -> SmtLibReal {
+        match args.len() {
+            0 => self.real_const(1, 1),
+            1 => *args[0],
+            _ => SmtLibReal(self.apply("Real", "*", &handles(args, |a| a.0))),
+        }
+    }
+
+    fn ite(&self, c: &SmtLibBool, a: &SmtLibReal, b: &SmtLibReal) -> SmtLibReal {
+        SmtLibReal(self.apply("Real", "ite", &[c.0, a.0, b.0]))
+    }
+
+    fn le(&self, a: &SmtLibReal, b: &SmtLibReal) -> SmtLibBool {
+        SmtLibBool(self.apply("Bool", "<=", &[a.0, b.0]))
+    }
+
+    fn lt(&self, a: &SmtLibReal, b: &SmtLibReal) -> SmtLibBool {
+        SmtLibBool(self.apply("Bool", "<", &[a.0, b.0]))
+    }
+
+    fn ge(&self, a: &SmtLibReal, b: &SmtLibReal) -> SmtLibBool {
+        SmtLibBool(self.apply("Bool", ">=", &[a.0, b.0]))
+    }
+
+    fn eq(&self, a: &SmtLibReal, b: &SmtLibReal) -> SmtLibBool {
+        SmtLibBool(self.apply("Bool", "=", &[a.0, b.0]))
+    }
+
+    fn assert(&self, a: &SmtLibBool) {
+        let mut state = self.state.borrow_mut();
+        let command = format!("(assert {})", state.terms[a.0].reference);
+        state.send(&command);
+    }
+
+    fn check(&self, assumptions: &[SmtLibBool]) -> CheckResult {
+        let mut state = self.state.borrow_mut();
+        let literals = assumptions
+            .iter()
+            .map(|a| (state.assumption_literal(a.0), *a))
+            .collect();
+        state.last_assumptions = literals;
+        let literals = state
+            .last_assumptions
+            .iter()
+            .map(|(l, _)| l.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        state.send(&format!("(check-sat-assuming ({}))", literals));
+        match state.read_response() {
+            Some(SExpr::Atom(a)) if a == "sat" => CheckResult::Sat,
+            Some(SExpr::Atom(a)) if a == "unsat" => CheckResult::Unsat,
+            Some(SExpr::Atom(a)) if a == "unknown" => CheckResult::Unknown,
+            None => CheckResult::Unknown,
+            Some(other) => {
+                state.error = Some(format!(
+                    "Unexpected result from {}: {:?}",
+                    state.command, other
+                ));
+                CheckResult::Unknown
+            }
+        }
+    }
+
+    fn unsat_core(&self) -> Vec<SmtLibBool> {
+        let mut state = self.state.borrow_mut();
+        state.send("(get-unsat-assumptions)");
+        let core = match state.read_response() {
+            Some(SExpr::List(core)) => core,
+            None => return Vec::new(),
+            Some(other) => {
+                let message = format!("Unexpected unsat core from {}: {:?}", state.command, other);
+                state.fail(message);
+                return Vec::new();
+            }
+        };
+        let render = |e: &SExpr| match e {
+            SExpr::Atom(a) => a.clone(),
+            SExpr::List(l) => format!(
+                "({})",
+                l.iter()
+                    .map(|x| match x {
+                        SExpr::Atom(a) => a.clone(),
+                        SExpr::List(_) => String::new(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        };
+        let mut assumptions = Vec::new();
+        for c in core.iter() {
+            let literal = render(c);
+            match state.last_assumptions.iter().find(|(l, _)| *l == literal) {
+                Some((_, a)) => assumptions.push(*a),
+                None => {
+                    let message =
+                        format!("Unknown core literal from {}: {}", state.command, literal);
+                    state.fail(message);
+                    return Vec::new();
+                }
+            }
+        }
+        assumptions
+    }
+
+    fn bool_value(&self, a: &SmtLibBool) -> bool {
+        let mut state = self.state.borrow_mut();
+        match state.value(a.0) {
+            Some(SExpr::Atom(v)) if v == "true" => true,
+            Some(SExpr::Atom(v)) if v == "false" => false,
+            None => false,
+            Some(other) => {
+                let message = format!("Unexpected value from {}: {:?}", state.command, other);
+                state.fail(message);
+                false
+            }
+        }
+    }
+
+    fn real_value(&self, a: &SmtLibReal) -> (i64, i64) {
+        let mut state = self.state.borrow_mut();
+        let value = match state.value(a.0) {
+            Some(value) => value,
+            None => return (0, 1),
+        };
+        match parse_rational(&value) {
+            Some(rational) => rational,
+            None => {
+                let message = format!("Unexpected value from {}: {:?}", state.command, value);
+                state.fail(message);
+                (0, 1)
+            }
+        }
+    }
+
+    // CVC4 and cvc5 have no conflict limit.
+    fn set_budget(&self, timeout_ms: Option<u32>, _max_conflicts: Option<u32>) {
+        let mut state = self.state.borrow_mut();
+        let timeout = match (state.timeout_option, timeout_ms) {
+            (_, Some(ms)) => ms,
+            ("timeout", None) => u32::MAX,
+            (_, None) => 0,
+        };
+        let command = format!("(set-option :{} {})", state.timeout_option, timeout);
+        state.send(&command);
+    }
+
+    fn error(&self) -> Option<String> {
+        self.state.borrow().error.clone()
+    }
+
+    fn bool_name(&self, a: &SmtLibBool) -> Option<String> {
+        self.state.borrow().name(a.0)
+    }
+
+    fn real_name(&self, a: &SmtLibReal) -> Option<String> {
+        self.state.borrow().name(a.0)
+    }
+
+    fn display(&self, a: &SmtLibBool) -> String {
+        self.state.borrow().terms[a.0].text.clone()
+    }
+
+    fn formula(&self) -> String {
+        self.state
+            .borrow()
+            .script
+            .lines()
+            .filter(|l| !l.starts_with("(check-sat") && !l.starts_with("(get-"))
+            .map(|l| format!("{}\n", l))
+            .collect()
+    }
+
+    fn describe(&self) -> String {
+        format!("SMT-LIB2 process: {}", self.state.borrow().command)
+    }
+}
@@ -0,0 +1,240 @@
+use crate::problem::{Problem, TemporalRelationship, TokenTime};
+use crate::transitiongraph::{build_transition_graphs, TransitionGraph};
+use std::collections::HashMap;
+
+// Indexed form of a `Problem`, compiled once before solving.
+// Timelines and values are interned as indices, so that the solver does not need
+// to compare strings, and conditions are collected in one table with
+// back-references from the (timeline, value) pairs that they refer to.
+
+pub type TimelineId = usize;
+pub type ValueId = usize;
+pub type ConditionId = usize;
+
+#[derive(Debug)]
+pub struct CompiledProblem {
+    pub timelines: Vec<CompiledTimeline>,
+    pub timelines_by_name: HashMap<String, TimelineId>,
+    pub conditions: Vec<CompiledCondition>,
+    // Conditions referring to each (timeline, value) pair.
+    pub conditions_by_target: Vec<Vec<Vec<ConditionId>>>,
+    // Indexed by timeline.
+    pub transitions: Vec<TransitionGraph>,
+}
+
+#[derive(Debug)]
+pub struct CompiledTimeline {
+    pub name: String,
+    pub values: Vec<String>,
+    pub values_by_name: HashMap<String, ValueId>,
+    // Indexed by value. Values that are only used in static tokens or
+    // referenced by conditions have no token type.
+    pub token_types: Vec<Option<CompiledTokenType>>,
+    pub facts: Vec<CompiledStaticToken>,
+    pub goals: Vec<ValueId>,
+    pub facts_only: bool,
+}
+
+#[derive(Debug)]
+pub struct CompiledTokenType {
+    pub value: ValueId,
+    pub duration_limits: (usize, Option<usize>),
+    pub conditions: Vec<Vec<ConditionId>>,
+    pub capacity: u32,
+}
+
+#[derive(Debug)]
+pub struct CompiledStaticToken {
+    pub value: ValueId,
+    pub start_time: Option<usize>,
+    pub end_time: Option<usize>,
+    pub capacity: u32,
+    pub conditions: Vec<Vec<ConditionId>>,
+}
+
+#[derive(Debug)]
+pub struct CompiledCondition {
+    // The timeline that has the token type or static token with this condition.
+    pub owner: TimelineId,
+    pub timeline: TimelineId,
+    pub value: ValueId,
+    pub temporal_relationship: TemporalRelationship,
+    pub amount: u32,
+}
+
+impl CompiledCondition {
+    pub fn is_timeline_transition_from(&self, timeline: TimelineId) -> Option<ValueId> {
+        (matches!(
+            self.temporal_relationship,
+            TemporalRelationship::MetBy | TemporalRelationship::MetByTransitionFrom
+        ) && self.timeline == timeline)
+            .then_some(self.value)
+    }
+
+    pub fn is_timeline_transition_to(&self, timeline: TimelineId) -> Option<ValueId> {
+        (matches!(self.temporal_relationship, TemporalRelationship::Meets)
+            && self.timeline == timeline)
+            .then_some(self.value)
+    }
+}
+
+impl CompiledTimeline {
+    pub fn value_name(&self, value: ValueId) -> &str {
+        &self.values[value]
+    }
+
+    fn intern(&mut self, value: &str) -> ValueId {
+        if let Some(id) = self.values_by_name.get(value) {
+            return *id;
+        }
+        let id = self.values.len();
+        self.values.push(value.to_string());
+        self.values_by_name.insert(value.to_string(), id);
+        self.token_types.push(None);
+        id
+    }
+}
+
+impl CompiledProblem {
+    pub fn compile(problem: &Problem) -> CompiledProblem {
+        let _p = hprof::enter("compile");
+        let timelines_by_name = problem
+            .timelines
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.name.clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut timelines = problem
+            .timelines
+            .iter()
+            .map(|tl| CompiledTimeline {
+                name: tl.name.clone(),
+                values: Vec::new(),
+                values_by_name: HashMap::new(),
+                token_types: Vec::new(),
+                facts: Vec::new(),
+                goals: Vec::new(),
+                facts_only: tl.token_types.is_empty(),
+            })
+            .collect::<Vec<_>>();
+
+        // Intern the token type values first, so that their ids follow the order in the problem.
+        for (tl, tl_spec) in timelines.iter_mut().zip(problem.timelines.iter()) {
+            for token_type in tl_spec.token_types.iter() {
+                tl.intern(&token_type.value);
+            }
+            for token in tl_spec.static_tokens.iter() {
+                tl.intern(&token.value);
+            }
+        }
+
+        let mut conditions = Vec::new();
+        let mut compile_conditions =
+            |owner: TimelineId, alternatives: &[Vec<crate::problem::Condition>]| {
+                alternatives
+                    .iter()
+                    .map(|alternatives| {
+                        alternatives
+                            .iter()
+                            .map(|c| {
+                                let timeline = *timelines_by_name
+                                    .get(c.timeline_ref.as_str())
+                                    .unwrap_or_else(|| {
+                                        panic!(
+                                            "Condition in timeline {} refers to unknown timeline {}",
+                                            problem.timelines[owner].name, c.timeline_ref
+                                        )
+                                    });
+                                let value = timelines[timeline].intern(&c.value);
+                                conditions.push(CompiledCondition {
+                                    owner,
+                                    timeline,
+                                    value,
+                                    temporal_relationship: c.temporal_relationship.clone(),
+                                    amount: c.amount,
+                                });
+                                conditions.len() - 1
+                            })
+                            .collect()
+                    })
+                    .collect::<Vec<Vec<ConditionId>>>()
+            };
+
+        let mut compiled_token_types = Vec::new();
+        let mut compiled_static_tokens = Vec::new();
+        for (tl_idx, tl_spec) in problem.timelines.iter().enumerate() {
+            for token_type in tl_spec.token_types.iter() {
+                compiled_token_types.push((
+                    tl_idx,
+                    token_type,
+                    compile_conditions(tl_idx, &token_type.conditions),
+                ));
+            }
+            for token in tl_spec.static_tokens.iter() {
+                compiled_static_tokens.push((
+                    tl_idx,
+                    token,
+                    compile_conditions(tl_idx, &token.conditions),
+                ));
+            }
+        }
+
+        for (tl_idx, token_type, conds) in compiled_token_types {
+            let value = timelines[tl_idx].values_by_name[token_type.value.as_str()];
+            // The first token type with a given value is the one that is used.
+            if timelines[tl_idx].token_types[value].is_none() {
+                timelines[tl_idx].token_types[value] = Some(CompiledTokenType {
+                    value,
+                    duration_limits: token_type.duration_limits,
+                    conditions: conds,
+                    capacity: token_type.capacity,
+                });
+            }
+        }
+
+        for (tl_idx, token, conds) in compiled_static_tokens {
+            let value = timelines[tl_idx].values_by_name[token.value.as_str()];
+            match token.const_time {
+                TokenTime::Fact(start_time, end_time) => {
+                    timelines[tl_idx].facts.push(CompiledStaticToken {
+                        value,
+                        start_time,
+                        end_time,
+                        capacity: token.capacity,
+                        conditions: conds,
+                    });
+                }
+                TokenTime::Goal => {
+                    timelines[tl_idx].goals.push(value);
+                }
+            }
+        }
+
+        let mut conditions_by_target = timelines
+            .iter()
+            .map(|tl| vec![Vec::new(); tl.values.len()])
+            .collect::<Vec<_>>();
+        for (cond_idx, cond) in conditions.iter().enumerate() {
+            conditions_by_target[cond.timeline][cond.value].push(cond_idx);
+        }
+
+        let mut compiled = CompiledProblem {
+            timelines,
+            timelines_by_name,
+            conditions,
+            conditions_by_target,
+            transitions: Vec::new(),
+        };
+        compiled.transitions = build_transition_graphs(&compiled);
+        compiled
+    }
+
+    // For encodings where the transition conditions may be disabled, the
+    // transition graphs can not rule out any sequence of values.
+    pub fn unrestrict_transitions(&mut self) {
+        self.transitions = (0..self.timelines.len())
+            .map(|tl_idx| TransitionGraph::unrestricted(self, tl_idx))
+            .collect();
+    }
+}
@@ -1,5 +1,7 @@
+use crate::backend::{Backend, CheckResult};
+use crate::SolverError;
 
-pub fn minimize_core<'ctx>(core: &mut Vec<z3::ast::Bool<'ctx>>, solver: &z3::Solver<'ctx>, print :impl Fn(&str)) {
+pub fn minimize_core<B: Backend>(core: &mut Vec<B::Bool>, backend: &B, print :impl Fn(&str)) {
     print("Starting core minimization.");
     let mut i = 0;
     'minimize_loop: loop {
@@ -14,9 +16,9 @@ pub fn minimize_core<'ctx>(core: &mut Vec<z3::ast::Bool<'ctx>>, solver: &z3::Sol
                 assumptions.len(),
                 remove_idx
             ));
-            let result = solver.check_assumptions(&assumptions);
-            if matches!(result, z3::SatResult::Unsat) {
-                *core = solver.get_unsat_core();
+            let result = backend.check(&assumptions);
+            if matches!(result, CheckResult::Unsat) {
+                *core = backend.unsat_core();
                 print(&format!("Minimized {}->{}", last_core_size, core.len()));
                 continue 'minimize_loop;
             }
@@ -27,18 +29,291 @@ pub fn minimize_core<'ctx>(core: &mut Vec<z3::ast::Bool<'ctx>>, solver: &z3::Sol
     }
 }
 
-pub fn trim_core<'ctx>(core: &mut Vec<z3::ast::Bool<'ctx>>, solver: &z3::Solver<'ctx>, print :impl Fn(&str)) {
+pub fn trim_core<B: Backend>(core: &mut Vec<B::Bool>, backend: &B, print :impl Fn(&str)) {
     print("Starting core trim.");
     loop {
         let last_core_size = core.len();
         // Try to trim the core.
-        let result = solver.check_assumptions(&*core);
-        assert!(matches!(result, z3::SatResult::Unsat));
-        *core = solver.get_unsat_core();
+        let result = backend.check(&*core);
+        if matches!(result, CheckResult::Unknown) {
+            // The solver was interrupted.
+            break;
+        }
+        assert!(matches!(result, CheckResult::Unsat));
+        *core = backend.unsat_core();
         if core.len() == last_core_size {
             break;
         } else {
             print(&format!("Trimmed {}->{}", last_core_size, core.len()));
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoreMinimization {
+    // Trim the core to a fixpoint, then remove one literal at a time.
+    #[default]
+    LinearDeletion,
+    QuickXplain,
+    // Remove chunks of literals, halving the chunk size down to single literals.
+    DivideAndConquer,
+    // Find the necessary literals by exponential and binary search over prefixes of the core.
+    Progression,
+}
+
+impl std::str::FromStr for CoreMinimization {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(CoreMinimization::LinearDeletion),
+            "quickxplain" => Ok(CoreMinimization::QuickXplain),
+            "dnc" => Ok(CoreMinimization::DivideAndConquer),
+            "progression" => Ok(CoreMinimization::Progression),
+            _ => Err(format!("Unknown core minimization \"{}\"", s)),
+        }
+    }
+}
+
+// Limits for each solver call made while minimizing a core. A call that runs out
+// of budget counts as satisfiable, so the result is still a core, but it might not be minimal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CoreBudget {
+    pub timeout_ms: Option<u32>,
+    pub max_conflicts: Option<u32>,
+}
+
+pub fn minimize_core_with<B: Backend>(
+    core: &mut Vec<B::Bool>,
+    backend: &B,
+    algorithm: CoreMinimization,
+    budget: &CoreBudget,
+    print: impl Fn(&str),
+) {
+    backend.set_budget(budget.timeout_ms, budget.max_conflicts);
+    let size_before = core.len();
+    match algorithm {
+        CoreMinimization::LinearDeletion => {
+            trim_core(core, backend, &print);
+            minimize_core(core, backend, &print);
+        }
+        CoreMinimization::QuickXplain => {
+            if !core.is_empty() {
+                *core = quickxplain(backend, &[], false, core);
+            }
+        }
+        CoreMinimization::DivideAndConquer => divide_and_conquer(core, backend),
+        CoreMinimization::Progression => *core = progression(backend, core),
+    }
+    print(&format!(
+        "Minimized core {}->{} with {:?}",
+        size_before,
+        core.len(),
+        algorithm
+    ));
+    backend.set_budget(None, None);
+}
+
+fn is_unsat<B: Backend>(backend: &B, assumptions: &[B::Bool]) -> bool {
+    matches!(backend.check(assumptions), CheckResult::Unsat)
+}
+
+// Returns a minimal subset of `constraints` that is unsatisfiable together with `background`,
+// given that all of them together are unsatisfiable.
+fn quickxplain<B: Backend>(
+    backend: &B,
+    background: &[B::Bool],
+    background_changed: bool,
+    constraints: &[B::Bool],
+) -> Vec<B::Bool> {
+    if background_changed && is_unsat(backend, background) {
+        return Vec::new();
+    }
+    if constraints.len() == 1 {
+        return constraints.to_vec();
+    }
+
+    let (c1, c2) = constraints.split_at(constraints.len() / 2);
+    let d2 = quickxplain(backend, &[background, c1].concat(), !c1.is_empty(), c2);
+    let d1 = quickxplain(backend, &[background, &d2].concat(), !d2.is_empty(), c1);
+    [d1, d2].concat()
+}
+
+fn divide_and_conquer<B: Backend>(core: &mut Vec<B::Bool>, backend: &B) {
+    let mut chunk = (core.len() / 2).max(1);
+    loop {
+        let mut i = 0;
+        while i < core.len() {
+            let candidate = core[..i]
+                .iter()
+                .chain(core[(i + chunk).min(core.len())..].iter())
+                .cloned()
+                .collect::<Vec<_>>();
+            if is_unsat(backend, &candidate) {
+                // The backend's core can be even smaller. Keep the order of the
+                // candidate, so that the literals before `i` are not tested again.
+                let solver_core = backend.unsat_core();
+                *core = candidate
+                    .into_iter()
+                    .filter(|c| solver_core.contains(c))
+                    .collect();
+            } else {
+                i += chunk;
+            }
+        }
+
+        if chunk == 1 {
+            break;
+        }
+        chunk /= 2;
+    }
+}
+
+fn progression<B: Backend>(backend: &B, core: &[B::Bool]) -> Vec<B::Bool> {
+    let mut necessary = Vec::new();
+    let mut remaining = core.to_vec();
+
+    // Invariant: `necessary` and `remaining` together are unsatisfiable.
+    while !remaining.is_empty() && !is_unsat(backend, &necessary) {
+        let with_prefix = |k: usize| [&necessary[..], &remaining[..k]].concat();
+
+        // Find the shortest unsatisfiable prefix of the remaining literals.
+        let mut hi = 1;
+        while hi < remaining.len() && !is_unsat(backend, &with_prefix(hi)) {
+            hi *= 2;
+        }
+        let mut lo = hi / 2;
+        hi = hi.min(remaining.len());
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if is_unsat(backend, &with_prefix(mid)) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+
+        // The last literal of the prefix is needed.
+        necessary.push(remaining[hi - 1].clone());
+        remaining.truncate(hi - 1);
+    }
+
+    necessary
+}
+
+#[derive(Debug)]
+pub struct MusEnumeration {
+    pub muses: Vec<Vec<usize>>,
+    // All minimal unsatisfiable subsets were found.
+    pub complete: bool,
+}
+
+// MUS enumeration (MARCO). The constraints are numbered 0..n, and the oracle tells
+// whether a sorted subset of them is satisfiable, or None if it could not be decided.
+// A map solver keeps track of the subsets that have not been explored yet.
+// The map solver is Z3 or the native solver, so builds without either fail here.
+pub fn enumerate_muses(
+    n: usize,
+    max_muses: usize,
+    satisfiable: impl FnMut(&[usize]) -> Option<bool>,
+) -> Result<MusEnumeration, SolverError> {
+    #[cfg(feature = "z3")]
+    {
+        let ctx = z3::Context::new(&z3::Config::new());
+        let map = crate::backend::Z3Backend::new(&ctx, &crate::transitionsolver::SolverSettings::default());
+        Ok(enumerate_muses_with(&map, n, max_muses, satisfiable))
+    }
+    #[cfg(all(feature = "native", not(feature = "z3")))]
+    {
+        let map = crate::native::NativeBackend::new();
+        Ok(enumerate_muses_with(&map, n, max_muses, satisfiable))
+    }
+    #[cfg(not(any(feature = "z3", feature = "native")))]
+    {
+        let _ = (n, max_muses, satisfiable);
+        Err(SolverError::Backend(
+            "MUS enumeration needs the z3 or native feature".to_string(),
+        ))
+    }
+}
+
+pub fn enumerate_muses_with<B: Backend>(
+    map: &B,
+    n: usize,
+    max_muses: usize,
+    mut satisfiable: impl FnMut(&[usize]) -> Option<bool>,
+) -> MusEnumeration {
+    // The map's variables tell which constraints are excluded from the seed, so
+    // that constraints which are not fixed by the map are included.
+    let excluded = (0..n).map(|_| map.fresh_bool("m")).collect::<Vec<_>>();
+    let mut muses = Vec::new();
+    let incomplete = |muses| MusEnumeration {
+        muses,
+        complete: false,
+    };
+
+    loop {
+        if muses.len() >= max_muses {
+            return incomplete(muses);
+        }
+
+        match map.check(&[]) {
+            CheckResult::Unsat => {
+                return MusEnumeration {
+                    muses,
+                    complete: true,
+                }
+            }
+            CheckResult::Unknown => return incomplete(muses),
+            CheckResult::Sat => {}
+        }
+
+        let seed = (0..n)
+            .filter(|i| !map.bool_value(&excluded[*i]))
+            .collect::<Vec<_>>();
+
+        match satisfiable(&seed) {
+            None => return incomplete(muses),
+            Some(true) => {
+                // Grow to a maximal satisfiable subset, and block all its subsets.
+                let mut mss = seed;
+                for j in 0..n {
+                    if mss.contains(&j) {
+                        continue;
+                    }
+                    let mut candidate = mss.clone();
+                    candidate.push(j);
+                    candidate.sort_unstable();
+                    match satisfiable(&candidate) {
+                        None => return incomplete(muses),
+                        Some(true) => mss = candidate,
+                        Some(false) => {}
+                    }
+                }
+
+                let included = (0..n)
+                    .filter(|j| !mss.contains(j))
+                    .map(|j| map.not(&excluded[j]))
+                    .collect::<Vec<_>>();
+                map.assert(&map.or(&included.iter().collect::<Vec<_>>()));
+            }
+            Some(false) => {
+                // Shrink to a minimal unsatisfiable subset, and block all its supersets.
+                let mut mus = seed;
+                let mut i = 0;
+                while i < mus.len() {
+                    let mut candidate = mus.clone();
+                    candidate.remove(i);
+                    match satisfiable(&candidate) {
+                        None => return incomplete(muses),
+                        Some(true) => i += 1,
+                        Some(false) => mus = candidate,
+                    }
+                }
+
+                let clause = mus.iter().map(|i| &excluded[*i]).collect::<Vec<_>>();
+                map.assert(&map.or(&clause));
+                println!("Found MUS #{}: {:?}", muses.len() + 1, mus);
+                muses.push(mus);
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    problem::{Problem, Solution},
+    transitionsolver::{self, SolverSettings},
+    validate::{time_eq, time_le, validate_solution},
+    SolverError,
+};
+use std::collections::HashMap;
+
+// Decomposition of a problem into groups of timelines that do not refer to each
+// other through conditions. The groups can be solved independently, and the
+// solutions merged with a common end of time. A component that ends earlier is
+// extended to the common end of time, or solved again with it if the last states
+// of its timelines can not last that long.
+
+#[derive(Debug)]
+pub struct Decomposition {
+    // Timeline indices in the original problem, in increasing order.
+    pub components: Vec<Vec<usize>>,
+}
+
+impl Decomposition {
+    pub fn analyze(problem: &Problem) -> Decomposition {
+        let timelines_by_name = problem
+            .timelines
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.name.as_str(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut parent = (0..problem.timelines.len()).collect::<Vec<_>>();
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        for (tl_idx, timeline) in problem.timelines.iter().enumerate() {
+            let conditions = timeline
+                .token_types
+                .iter()
+                .flat_map(|tt| tt.conditions.iter())
+                .chain(
+                    timeline
+                        .static_tokens
+                        .iter()
+                        .flat_map(|t| t.conditions.iter()),
+                )
+                .flat_map(|alternatives| alternatives.iter());
+
+            for cond in conditions {
+                if let Some(other) = timelines_by_name.get(cond.timeline_ref.as_str()) {
+                    let (a, b) = (find(&mut parent, tl_idx), find(&mut parent, *other));
+                    parent[a.max(b)] = a.min(b);
+                }
+            }
+        }
+
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        let mut component_of_root = HashMap::new();
+        for tl_idx in 0..problem.timelines.len() {
+            let root = find(&mut parent, tl_idx);
+            let component = *component_of_root.entry(root).or_insert_with(|| {
+                components.push(Vec::new());
+                components.len() - 1
+            });
+            components[component].push(tl_idx);
+        }
+
+        Decomposition { components }
+    }
+
+    pub fn subproblem(&self, problem: &Problem, component: usize) -> Problem {
+        Problem {
+            timelines: self.components[component]
+                .iter()
+                .map(|t| problem.timelines[*t].clone())
+                .collect(),
+        }
+    }
+
+    // Combine the solutions of the components, given in the same order as the
+    // components, and all with the same end of time.
+    pub fn merge_solutions(&self, problem: &Problem, solutions: Vec<Solution>) -> Solution {
+        assert!(solutions.len() == self.components.len());
+        let mut timelines = vec![None; problem.timelines.len()];
+        let mut component_of = vec![0; problem.timelines.len()];
+        let mut end_of_time: f32 = 0.0;
+        for (c, (component, solution)) in self.components.iter().zip(solutions).enumerate() {
+            assert!(c == 0 || time_eq(end_of_time, solution.end_of_time));
+            end_of_time = end_of_time.max(solution.end_of_time);
+            for (tl_idx, timeline) in component.iter().zip(solution.timelines) {
+                assert!(timeline.name == problem.timelines[*tl_idx].name);
+                timelines[*tl_idx] = Some(timeline);
+                component_of[*tl_idx] = c;
+            }
+        }
+
+        // Token ids are renumbered in the order of the original timelines.
+        let mut timelines = timelines
+            .into_iter()
+            .map(|t| t.unwrap())
+            .collect::<Vec<_>>();
+        let mut new_ids = HashMap::new();
+        for (tl_idx, timeline) in timelines.iter_mut().enumerate() {
+            for token in timeline.tokens.iter_mut() {
+                new_ids.insert((component_of[tl_idx], token.id), new_ids.len());
+                token.id = new_ids.len() - 1;
+            }
+        }
+        for (tl_idx, timeline) in timelines.iter_mut().enumerate() {
+            for token in timeline.tokens.iter_mut() {
+                for satisfaction in token.conditions.iter_mut() {
+                    satisfaction.target = new_ids[&(component_of[tl_idx], satisfaction.target)];
+                }
+            }
+        }
+
+        Solution {
+            timelines,
+            end_of_time,
+        }
+    }
+}
+
+// Let the last state on each timeline of a component's solution last until a
+// later end of time. None if a fact's end time is fixed, a state would last
+// longer than it may, or the longer states break a condition.
+pub fn extend_solution(
+    problem: &Problem,
+    solution: &Solution,
+    end_of_time: f32,
+) -> Option<Solution> {
+    let mut extended = solution.clone();
+    extended.end_of_time = end_of_time;
+    for timeline in extended.timelines.iter_mut() {
+        let spec = problem.timelines.iter().find(|t| t.name == timeline.name)?;
+        // Facts on timelines without token types do not last until the end of time.
+        if spec.token_types.is_empty() {
+            continue;
+        }
+        let pos = match timeline.tokens.len() {
+            0 => continue,
+            n => n - 1,
+        };
+        let last = &mut timeline.tokens[pos];
+        let token_spec = spec.token_spec(pos, &last.value)?;
+        if let Some((_, Some(_))) = token_spec.fact {
+            return None;
+        }
+        if let Some(max) = token_spec.duration_limits.1 {
+            if !time_le(end_of_time - last.start_time, max as f32) {
+                return None;
+            }
+        }
+        last.end_time = end_of_time;
+    }
+    validate_solution(problem, &extended)
+        .is_empty()
+        .then_some(extended)
+}
+
+pub fn solve_decomposed(
+    problem: &Problem,
+    settings: &SolverSettings,
+    parallel: bool,
+) -> Result<Solution, SolverError> {
+    let decomposition = Decomposition::analyze(problem);
+    println!(
+        "Decomposed problem into {} components of sizes {:?}",
+        decomposition.components.len(),
+        decomposition
+            .components
+            .iter()
+            .map(|c| c.len())
+            .collect::<Vec<_>>()
+    );
+
+    let subproblems = (0..decomposition.components.len())
+        .map(|c| decomposition.subproblem(problem, c))
+        .collect::<Vec<_>>();
+
+    let results = if parallel {
+        std::thread::scope(|scope| {
+            let threads = subproblems
+                .iter()
+                .map(|subproblem| {
+                    scope.spawn(move || transitionsolver::solve(subproblem, settings))
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        })
+    } else {
+        subproblems
+            .iter()
+            .map(|subproblem| transitionsolver::solve(subproblem, settings))
+            .collect::<Vec<_>>()
+    };
+
+    let mut solutions = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    let end_of_time = solutions.iter().map(|s| s.end_of_time).fold(0.0, f32::max);
+    for (subproblem, solution) in subproblems.iter().zip(solutions.iter_mut()) {
+        if time_eq(solution.end_of_time, end_of_time) {
+            continue;
+        }
+        *solution = match extend_solution(subproblem, solution, end_of_time) {
+            Some(extended) => extended,
+            None => {
+                let settings = SolverSettings {
+                    end_of_time: Some(end_of_time),
+                    ..settings.clone()
+                };
+                transitionsolver::solve(subproblem, &settings)?
+            }
+        };
+    }
+    Ok(decomposition.merge_solutions(problem, solutions))
+}
@@ -0,0 +1,240 @@
+use crate::{
+    cores::{enumerate_muses, MusEnumeration},
+    problem::{Problem, TokenTime},
+    transitionsolver::{self, SolverSettings},
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::channel,
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// Diagnosis of over-constrained problems by enumerating the minimal sets of
+// problem-level constraints that together make the problem infeasible.
+// The constraints are the goals, the condition alternative sets of token types
+// and facts, and the capacities of resources. Each of them gets a selector
+// literal in a single encoding of the problem, and a subset is checked by
+// assuming the selectors of its constraints. A subset that can not be decided
+// within the time limit stops the enumeration, which is then reported as
+// incomplete.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstraintKind {
+    Goal { static_token: usize },
+    TokenTypeConditions { token_type: usize, group: usize },
+    StaticTokenConditions { static_token: usize, group: usize },
+    Capacity { value: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosisConstraint {
+    pub timeline: usize,
+    pub kind: ConstraintKind,
+    pub description: String,
+}
+
+#[derive(Debug)]
+pub struct Diagnosis {
+    pub constraints: Vec<DiagnosisConstraint>,
+    // Minimal conflicting sets, as indices into `constraints`.
+    pub conflicts: Vec<Vec<usize>>,
+    // All minimal conflicts were found within the limits.
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosisLimits {
+    pub max_conflicts: usize,
+    pub time_limit: Duration,
+}
+
+impl Default for DiagnosisLimits {
+    fn default() -> Self {
+        DiagnosisLimits {
+            max_conflicts: 10,
+            time_limit: Duration::from_secs(60),
+        }
+    }
+}
+
+pub fn problem_constraints(problem: &Problem) -> Vec<DiagnosisConstraint> {
+    let mut constraints = Vec::new();
+    for (tl_idx, timeline) in problem.timelines.iter().enumerate() {
+        for (token_idx, token) in timeline.static_tokens.iter().enumerate() {
+            if matches!(token.const_time, TokenTime::Goal) {
+                constraints.push(DiagnosisConstraint {
+                    timeline: tl_idx,
+                    kind: ConstraintKind::Goal {
+                        static_token: token_idx,
+                    },
+                    description: format!("goal {}.{}", timeline.name, token.value),
+                });
+            }
+            // Conditions are only used on facts.
+            if !matches!(token.const_time, TokenTime::Fact(_, _)) {
+                continue;
+            }
+            for (group, alternatives) in token.conditions.iter().enumerate() {
+                constraints.push(DiagnosisConstraint {
+                    timeline: tl_idx,
+                    kind: ConstraintKind::StaticTokenConditions {
+                        static_token: token_idx,
+                        group,
+                    },
+                    description: format!(
+                        "conditions on fact {}.{}: {}",
+                        timeline.name,
+                        token.value,
+                        describe_alternatives(alternatives)
+                    ),
+                });
+            }
+        }
+
+        for (tt_idx, token_type) in timeline.token_types.iter().enumerate() {
+            for (group, alternatives) in token_type.conditions.iter().enumerate() {
+                constraints.push(DiagnosisConstraint {
+                    timeline: tl_idx,
+                    kind: ConstraintKind::TokenTypeConditions {
+                        token_type: tt_idx,
+                        group,
+                    },
+                    description: format!(
+                        "conditions on {}.{}: {}",
+                        timeline.name,
+                        token_type.value,
+                        describe_alternatives(alternatives)
+                    ),
+                });
+            }
+        }
+    }
+
+    // Resources that are used by any condition.
+    let mut resources = Vec::new();
+    for timeline in problem.timelines.iter() {
+        let conditions = timeline
+            .token_types
+            .iter()
+            .flat_map(|tt| tt.conditions.iter())
+            .chain(
+                timeline
+                    .static_tokens
+                    .iter()
+                    .flat_map(|t| t.conditions.iter()),
+            )
+            .flat_map(|alternatives| alternatives.iter());
+        for cond in conditions.filter(|c| c.amount > 0) {
+            let resource = (cond.timeline_ref.clone(), cond.value.clone());
+            if !resources.contains(&resource) {
+                resources.push(resource);
+            }
+        }
+    }
+    for (timeline_name, value) in resources {
+        if let Some(tl_idx) = problem
+            .timelines
+            .iter()
+            .position(|t| t.name == timeline_name)
+        {
+            constraints.push(DiagnosisConstraint {
+                timeline: tl_idx,
+                description: format!("capacity of {}.{}", timeline_name, value),
+                kind: ConstraintKind::Capacity { value },
+            });
+        }
+    }
+
+    constraints
+}
+
+fn describe_alternatives(alternatives: &[crate::problem::Condition]) -> String {
+    alternatives
+        .iter()
+        .map(|c| {
+            format!(
+                "{:?} {}.{}",
+                c.temporal_relationship, c.timeline_ref, c.value
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+pub fn diagnose(
+    problem: &Problem,
+    settings: &SolverSettings,
+    limits: &DiagnosisLimits,
+) -> Diagnosis {
+    let _p = hprof::enter("diagnose");
+    let constraints = problem_constraints(problem);
+    println!("Diagnosing with {} constraints", constraints.len());
+
+    let deadline = Instant::now() + limits.time_limit;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let settings = SolverSettings {
+        cancel: Some(cancel.clone()),
+        ..settings.clone()
+    };
+    let done = AtomicBool::new(false);
+
+    let enumeration = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(Ordering::SeqCst) {
+                if Instant::now() >= deadline {
+                    cancel.store(true, Ordering::SeqCst);
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let (subset_tx, subset_rx) = channel();
+        let (result_tx, result_rx) = channel();
+        let solver = scope.spawn(|| {
+            transitionsolver::check_subsets(problem, &settings, &constraints, subset_rx, result_tx)
+        });
+
+        let enumeration = enumerate_muses(constraints.len(), limits.max_conflicts, |enabled| {
+            if Instant::now() >= deadline {
+                return None;
+            }
+            subset_tx.send(enabled.to_vec()).ok()?;
+            result_rx.recv().ok().flatten()
+        });
+
+        drop(subset_tx);
+        done.store(true, Ordering::SeqCst);
+        let failure = match solver.join() {
+            Ok(Ok(())) => None,
+            Ok(Err(err)) => Some(format!("{:?}", err)),
+            Err(_) => Some("the solver panicked".to_string()),
+        };
+        match failure {
+            Some(failure) => {
+                println!("Could not check subsets: {}", failure);
+                enumeration.map(|e| MusEnumeration {
+                    complete: false,
+                    ..e
+                })
+            }
+            None => enumeration,
+        }
+    });
+    let enumeration = enumeration.unwrap_or_else(|err| {
+        println!("Could not enumerate conflicts: {:?}", err);
+        MusEnumeration {
+            muses: Vec::new(),
+            complete: false,
+        }
+    });
+
+    Diagnosis {
+        constraints,
+        conflicts: enumeration.muses,
+        complete: enumeration.complete,
+    }
+}
@@ -0,0 +1,236 @@
+use crate::problem::{Solution, SolutionToken};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Differences between two solutions. The tokens on each timeline are matched by
+// aligning the sequences of values, so a token that is not matched has been
+// inserted or removed, and a matched token that has moved by more than the
+// tolerance has been retimed.
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    pub tolerance: f32,
+    // Tolerance relative to the larger of the two times.
+    pub relative_tolerance: f32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            tolerance: 1e-3,
+            relative_tolerance: 0.0,
+        }
+    }
+}
+
+impl DiffOptions {
+    fn same_time(&self, a: f32, b: f32) -> bool {
+        (a - b).abs() <= self.tolerance + self.relative_tolerance * a.abs().max(b.abs())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiffToken {
+    pub id: usize,
+    pub value: String,
+    pub start_time: f32,
+    pub end_time: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetimedToken {
+    pub old: DiffToken,
+    pub new: DiffToken,
+    pub start_shift: f32,
+    pub end_shift: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimelineDiff {
+    pub name: String,
+    pub inserted: Vec<DiffToken>,
+    pub removed: Vec<DiffToken>,
+    pub retimed: Vec<RetimedToken>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SolutionDiff {
+    pub old_end_of_time: f32,
+    pub new_end_of_time: f32,
+    pub makespan_change: f32,
+    // Only the timelines that have changed.
+    pub timelines: Vec<TimelineDiff>,
+}
+
+impl SolutionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.timelines.is_empty() && self.makespan_change == 0.0
+    }
+}
+
+fn diff_token(token: &SolutionToken) -> DiffToken {
+    DiffToken {
+        id: token.id,
+        value: token.value.clone(),
+        start_time: token.start_time,
+        end_time: token.end_time,
+    }
+}
+
+// Longest common subsequence of the values, as pairs of matched positions.
+fn align(old: &[SolutionToken], new: &[SolutionToken]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if old[i].value == new[j].value {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i].value == new[j].value {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+fn diff_timeline(
+    name: &str,
+    old: &[SolutionToken],
+    new: &[SolutionToken],
+    options: &DiffOptions,
+) -> TimelineDiff {
+    let pairs = align(old, new);
+    let mut timeline_diff = TimelineDiff {
+        name: name.to_string(),
+        inserted: Vec::new(),
+        removed: Vec::new(),
+        retimed: Vec::new(),
+    };
+    for (i, token) in old.iter().enumerate() {
+        if !pairs.iter().any(|(p, _)| *p == i) {
+            timeline_diff.removed.push(diff_token(token));
+        }
+    }
+    for (j, token) in new.iter().enumerate() {
+        if !pairs.iter().any(|(_, p)| *p == j) {
+            timeline_diff.inserted.push(diff_token(token));
+        }
+    }
+    for (i, j) in pairs {
+        let (old_token, new_token) = (&old[i], &new[j]);
+        if !options.same_time(old_token.start_time, new_token.start_time)
+            || !options.same_time(old_token.end_time, new_token.end_time)
+        {
+            timeline_diff.retimed.push(RetimedToken {
+                old: diff_token(old_token),
+                new: diff_token(new_token),
+                start_shift: new_token.start_time - old_token.start_time,
+                end_shift: new_token.end_time - old_token.end_time,
+            });
+        }
+    }
+    timeline_diff
+}
+
+fn timeline_tokens<'a>(solution: &'a Solution, name: &str) -> &'a [SolutionToken] {
+    solution
+        .timelines
+        .iter()
+        .find(|tl| tl.name == name)
+        .map(|tl| tl.tokens.as_slice())
+        .unwrap_or_default()
+}
+
+pub fn diff(old: &Solution, new: &Solution) -> SolutionDiff {
+    diff_with_options(old, new, &DiffOptions::default())
+}
+
+pub fn diff_with_options(old: &Solution, new: &Solution, options: &DiffOptions) -> SolutionDiff {
+    let _p = hprof::enter("diff");
+    // Timelines in the order of the new solution, then the removed timelines.
+    let mut names = new
+        .timelines
+        .iter()
+        .map(|tl| tl.name.as_str())
+        .collect::<Vec<_>>();
+    for timeline in old.timelines.iter() {
+        if !names.contains(&timeline.name.as_str()) {
+            names.push(&timeline.name);
+        }
+    }
+
+    let timelines = names
+        .into_iter()
+        .map(|name| {
+            diff_timeline(
+                name,
+                timeline_tokens(old, name),
+                timeline_tokens(new, name),
+                options,
+            )
+        })
+        .filter(|d| !(d.inserted.is_empty() && d.removed.is_empty() && d.retimed.is_empty()))
+        .collect();
+
+    let makespan_change = if options.same_time(old.end_of_time, new.end_of_time) {
+        0.0
+    } else {
+        new.end_of_time - old.end_of_time
+    };
+    SolutionDiff {
+        old_end_of_time: old.end_of_time,
+        new_end_of_time: new.end_of_time,
+        makespan_change,
+        timelines,
+    }
+}
+
+impl fmt::Display for SolutionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "End of time {} -> {} ({:+})",
+            self.old_end_of_time, self.new_end_of_time, self.makespan_change
+        )?;
+        if self.timelines.is_empty() {
+            writeln!(f, "No changes in timelines.")?;
+        }
+        for timeline in self.timelines.iter() {
+            writeln!(f, "Timeline \"{}\":", timeline.name)?;
+            for t in timeline.removed.iter() {
+                writeln!(f, "  - ({},{},{})", t.value, t.start_time, t.end_time)?;
+            }
+            for t in timeline.inserted.iter() {
+                writeln!(f, "  + ({},{},{})", t.value, t.start_time, t.end_time)?;
+            }
+            for t in timeline.retimed.iter() {
+                writeln!(
+                    f,
+                    "  ~ ({},{},{}) -> ({},{}) ({:+}/{:+})",
+                    t.old.value,
+                    t.old.start_time,
+                    t.old.end_time,
+                    t.new.start_time,
+                    t.new.end_time,
+                    t.start_shift,
+                    t.end_shift
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
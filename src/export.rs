@@ -0,0 +1,260 @@
+use crate::problem::Solution;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+// Export of solutions to other formats. Solver times are mapped to wall-clock
+// timestamps as an epoch plus a number of seconds per time unit.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    ICalendar,
+    JsonLines,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "ics" => Ok(ExportFormat::ICalendar),
+            "jsonl" => Ok(ExportFormat::JsonLines),
+            _ => Err(format!("Unknown export format \"{}\"", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeMapping {
+    // Unix time in seconds of solver time 0.
+    pub epoch: i64,
+    pub seconds_per_unit: f64,
+}
+
+impl Default for TimeMapping {
+    fn default() -> Self {
+        TimeMapping {
+            epoch: 0,
+            seconds_per_unit: 1.0,
+        }
+    }
+}
+
+impl TimeMapping {
+    pub fn timestamp(&self, time: f32) -> i64 {
+        self.epoch + (time as f64 * self.seconds_per_unit).round() as i64
+    }
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Parse a UTC timestamp as "2024-01-31", "2024-01-31T12:00:00Z" or Unix seconds.
+pub fn parse_timestamp(s: &str) -> Result<i64, String> {
+    if let Ok(seconds) = s.parse::<i64>() {
+        return Ok(seconds);
+    }
+    let err = || {
+        format!(
+            "Expected YYYY-MM-DD[THH:MM:SS[Z]] or Unix seconds, got \"{}\"",
+            s
+        )
+    };
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, time.trim_end_matches('Z')),
+        None => (s, "00:00:00"),
+    };
+    let numbers = |s: &str, sep: char| {
+        s.split(sep)
+            .map(|n| n.parse::<i64>().map_err(|_| err()))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let (date, time) = (numbers(date, '-')?, numbers(time, ':')?);
+    if date.len() != 3 || time.len() != 3 {
+        return Err(err());
+    }
+    Ok(
+        days_from_civil(date[0], date[1], date[2]) * 86400
+            + time[0] * 3600
+            + time[1] * 60
+            + time[2],
+    )
+}
+
+// A Unix time as "2024-01-31T12:00:00Z".
+pub fn format_timestamp(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(86400));
+    let seconds = timestamp.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedToken {
+    pub timeline: String,
+    pub id: usize,
+    pub value: String,
+    pub start_time: f32,
+    pub end_time: f32,
+    pub start: String,
+    pub end: String,
+}
+
+pub fn exported_tokens(solution: &Solution, mapping: &TimeMapping) -> Vec<ExportedToken> {
+    solution
+        .timelines
+        .iter()
+        .flat_map(|tl| {
+            tl.tokens.iter().map(move |t| ExportedToken {
+                timeline: tl.name.clone(),
+                id: t.id,
+                value: t.value.clone(),
+                start_time: t.start_time,
+                end_time: t.end_time,
+                start: format_timestamp(mapping.timestamp(t.start_time)),
+                end: format_timestamp(mapping.timestamp(t.end_time)),
+            })
+        })
+        .collect()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn to_csv(solution: &Solution, mapping: &TimeMapping) -> String {
+    let mut csv = "timeline,id,value,start_time,end_time,start,end\n".to_string();
+    for t in exported_tokens(solution, mapping) {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{}",
+            csv_field(&t.timeline),
+            t.id,
+            csv_field(&t.value),
+            t.start_time,
+            t.end_time,
+            t.start,
+            t.end
+        )
+        .unwrap();
+    }
+    csv
+}
+
+pub fn to_json_lines(solution: &Solution, mapping: &TimeMapping) -> String {
+    let mut jsonl = String::new();
+    for t in exported_tokens(solution, mapping) {
+        writeln!(jsonl, "{}", serde_json::to_string(&t).unwrap()).unwrap();
+    }
+    jsonl
+}
+
+fn ical_text(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn ical_time(timestamp: i64) -> String {
+    format_timestamp(timestamp).replace(['-', ':'], "")
+}
+
+// Content lines are folded to at most 75 octets.
+fn ical_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+pub fn to_ical(solution: &Solution, mapping: &TimeMapping) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut ics = String::new();
+    ical_line(&mut ics, "BEGIN:VCALENDAR");
+    ical_line(&mut ics, "VERSION:2.0");
+    ical_line(&mut ics, "PRODID:-//paraspace//solution//EN");
+    for timeline in solution.timelines.iter() {
+        for t in timeline.tokens.iter() {
+            ical_line(&mut ics, "BEGIN:VEVENT");
+            ical_line(
+                &mut ics,
+                &format!("UID:{}-{}@paraspace", t.id, ical_text(&timeline.name)),
+            );
+            ical_line(&mut ics, &format!("DTSTAMP:{}", ical_time(now)));
+            ical_line(
+                &mut ics,
+                &format!("DTSTART:{}", ical_time(mapping.timestamp(t.start_time))),
+            );
+            ical_line(
+                &mut ics,
+                &format!("DTEND:{}", ical_time(mapping.timestamp(t.end_time))),
+            );
+            ical_line(
+                &mut ics,
+                &format!(
+                    "SUMMARY:{}: {}",
+                    ical_text(&timeline.name),
+                    ical_text(&t.value)
+                ),
+            );
+            ical_line(&mut ics, "END:VEVENT");
+        }
+    }
+    ical_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+pub fn export(solution: &Solution, format: ExportFormat, mapping: &TimeMapping) -> String {
+    let _p = hprof::enter("export");
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(solution).unwrap(),
+        ExportFormat::Csv => to_csv(solution, mapping),
+        ExportFormat::ICalendar => to_ical(solution, mapping),
+        ExportFormat::JsonLines => to_json_lines(solution, mapping),
+    }
+}
@@ -0,0 +1,439 @@
+use crate::{
+    problem::{Problem, Solution, TemporalRelationship},
+    validate::{time_le, time_lt},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+// Flexible plans. The tokens, condition links and resource orderings of a
+// solution are kept, and its times are relaxed to a Simple Temporal Network,
+// where each token can start and end anywhere in a window, as long as the
+// other timepoints are moved consistently. The plan stays within the time
+// horizon of the solution.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlexibleSolution {
+    pub timelines: Vec<FlexibleTimeline>,
+    pub end_of_time: TimeWindow,
+    pub stn: Stn,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlexibleTimeline {
+    pub name: String,
+    pub tokens: Vec<FlexibleToken>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlexibleToken {
+    pub id: usize,
+    pub value: String,
+    // Timepoints in the STN. Consecutive tokens on a timeline share a timepoint.
+    pub start_timepoint: usize,
+    pub end_timepoint: usize,
+    pub start: TimeWindow,
+    pub end: TimeWindow,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TimeWindow {
+    pub earliest: f32,
+    pub latest: f32,
+}
+
+// Why a solution has no flexible plan. The solution's times satisfy the temporal
+// network of any valid solution, so these are all errors in the solution.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlexibleError {
+    UnknownTimeline(String),
+    UnknownValue { timeline: String, token: usize },
+    // A recorded condition satisfaction refers to a condition or token that does not exist.
+    UnknownSatisfaction { token: usize, group: usize },
+    // A condition group of a token has no recorded satisfaction.
+    MissingSatisfaction { token: usize, group: usize },
+    // Two tokens have the same id.
+    DuplicateId(usize),
+    Inconsistent,
+}
+
+// The first timepoints of the network are the time origin and the end of time.
+pub const ORIGIN: usize = 0;
+pub const END_OF_TIME: usize = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Stn {
+    pub timepoints: Vec<String>,
+    pub constraints: Vec<StnConstraint>,
+}
+
+// `min <= to - from <= max`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StnConstraint {
+    pub from: usize,
+    pub to: usize,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub kind: ConstraintKind,
+}
+
+// Where a constraint in the network comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ConstraintKind {
+    Horizon,
+    Duration {
+        token: usize,
+    },
+    FixedTime {
+        token: usize,
+    },
+    Condition {
+        token: usize,
+        group: usize,
+        target: usize,
+    },
+    ResourceOrder {
+        resource: usize,
+    },
+    Observation,
+}
+
+impl Stn {
+    pub fn add_timepoint(&mut self, name: String) -> usize {
+        self.timepoints.push(name);
+        self.timepoints.len() - 1
+    }
+
+    pub fn add_constraint(
+        &mut self,
+        from: usize,
+        to: usize,
+        min: Option<f32>,
+        max: Option<f32>,
+        kind: ConstraintKind,
+    ) {
+        self.constraints.push(StnConstraint {
+            from,
+            to,
+            min,
+            max,
+            kind,
+        });
+    }
+
+    // Edges of the distance graph with the constraints they come from. An edge
+    // from a to b with weight w means b - a <= w.
+    fn distance_graph(&self) -> (Vec<(usize, usize, f32)>, Vec<usize>) {
+        let mut edges = Vec::new();
+        let mut constraint_idxs = Vec::new();
+        for (constraint_idx, c) in self.constraints.iter().enumerate() {
+            if let Some(max) = c.max {
+                edges.push((c.from, c.to, max));
+                constraint_idxs.push(constraint_idx);
+            }
+            if let Some(min) = c.min {
+                edges.push((c.to, c.from, -min));
+                constraint_idxs.push(constraint_idx);
+            }
+        }
+        (edges, constraint_idxs)
+    }
+
+    // The earliest and latest time of each timepoint, or None if the network is inconsistent.
+    pub fn windows(&self) -> Option<Vec<TimeWindow>> {
+        let (edges, _) = self.distance_graph();
+        let (latest, _) = shortest_paths(self.timepoints.len(), &edges, ORIGIN)?;
+        let (earliest, _) = shortest_paths(self.timepoints.len(), &reversed(&edges), ORIGIN)?;
+        Some(
+            earliest
+                .into_iter()
+                .zip(latest)
+                .map(|(earliest, latest)| TimeWindow {
+                    earliest: 0.0 - earliest,
+                    latest,
+                })
+                .collect(),
+        )
+    }
+
+    // For each timepoint, the constraint that its earliest time follows from,
+    // and the timepoint at the other end of that constraint.
+    pub fn earliest_causes(&self) -> Option<Vec<Option<(usize, usize)>>> {
+        let (edges, constraint_idxs) = self.distance_graph();
+        let reversed = reversed(&edges);
+        let (_, preds) = shortest_paths(self.timepoints.len(), &reversed, ORIGIN)?;
+        Some(
+            preds
+                .into_iter()
+                .map(|pred| pred.map(|edge_idx| (constraint_idxs[edge_idx], reversed[edge_idx].0)))
+                .collect(),
+        )
+    }
+}
+
+fn reversed(edges: &[(usize, usize, f32)]) -> Vec<(usize, usize, f32)> {
+    edges.iter().map(|(a, b, w)| (*b, *a, *w)).collect()
+}
+
+// Bellman-Ford with a queue, giving the distances and the last edge on each
+// shortest path, or None if there is a negative cycle.
+type ShortestPaths = (Vec<f32>, Vec<Option<usize>>);
+
+fn shortest_paths(n: usize, edges: &[(usize, usize, f32)], source: usize) -> Option<ShortestPaths> {
+    let mut out_edges = vec![Vec::new(); n];
+    for (edge_idx, (from, to, weight)) in edges.iter().enumerate() {
+        out_edges[*from].push((*to, *weight, edge_idx));
+    }
+
+    let mut dist = vec![f32::INFINITY; n];
+    let mut pred = vec![None; n];
+    let mut relaxations = vec![0; n];
+    let mut in_queue = vec![false; n];
+    let mut queue = VecDeque::from(vec![source]);
+    dist[source] = 0.0;
+    in_queue[source] = true;
+    while let Some(a) = queue.pop_front() {
+        in_queue[a] = false;
+        for (b, weight, edge_idx) in out_edges[a].iter() {
+            let d = dist[a] + weight;
+            if dist[*b].is_infinite() || time_lt(d, dist[*b]) {
+                dist[*b] = d;
+                pred[*b] = Some(*edge_idx);
+                relaxations[*b] += 1;
+                if relaxations[*b] > n {
+                    return None;
+                }
+                if !in_queue[*b] {
+                    in_queue[*b] = true;
+                    queue.push_back(*b);
+                }
+            }
+        }
+    }
+    Some((dist, pred))
+}
+
+// Build the temporal network of a solution, using the condition satisfactions
+// that are recorded in the solution, and compute the time windows.
+pub fn flexible_solution(
+    problem: &Problem,
+    solution: &Solution,
+) -> Result<FlexibleSolution, FlexibleError> {
+    let _p = hprof::enter("flexible_solution");
+    let mut stn = Stn::default();
+    let origin = stn.add_timepoint("origin".to_string());
+    let end_of_time = stn.add_timepoint("end of time".to_string());
+
+    let all_tokens = || solution.timelines.iter().flat_map(|tl| tl.tokens.iter());
+    let first_time = all_tokens().map(|t| t.start_time).fold(0.0, f32::min);
+    let last_time = all_tokens()
+        .map(|t| t.end_time)
+        .fold(solution.end_of_time, f32::max);
+    stn.add_constraint(
+        origin,
+        end_of_time,
+        Some(first_time),
+        Some(last_time),
+        ConstraintKind::Horizon,
+    );
+
+    // Timepoints and specs, by token id.
+    let mut timepoints = HashMap::new();
+    let mut specs = HashMap::new();
+    for solution_timeline in solution.timelines.iter() {
+        let timeline = problem
+            .timelines
+            .iter()
+            .find(|tl| tl.name == solution_timeline.name)
+            .ok_or_else(|| FlexibleError::UnknownTimeline(solution_timeline.name.clone()))?;
+        let contiguous = !timeline.token_types.is_empty();
+
+        let mut prev_end = None;
+        for (pos, token) in solution_timeline.tokens.iter().enumerate() {
+            let name = |point: &str| format!("{}[{}].{}", timeline.name, pos, point);
+            let start = match prev_end.filter(|_| contiguous) {
+                Some(prev_end) => prev_end,
+                None => stn.add_timepoint(name("start")),
+            };
+            let end = stn.add_timepoint(name("end"));
+            prev_end = Some(end);
+            if timepoints.insert(token.id, (start, end)).is_some() {
+                return Err(FlexibleError::DuplicateId(token.id));
+            }
+
+            stn.add_constraint(
+                origin,
+                start,
+                Some(first_time),
+                None,
+                ConstraintKind::Horizon,
+            );
+            stn.add_constraint(end, end_of_time, Some(0.0), None, ConstraintKind::Horizon);
+            // The last state on a timeline lasts until the end of time.
+            if contiguous && pos + 1 == solution_timeline.tokens.len() {
+                stn.add_constraint(
+                    end,
+                    end_of_time,
+                    Some(0.0),
+                    Some(0.0),
+                    ConstraintKind::Horizon,
+                );
+            }
+
+            let spec = timeline.token_spec(pos, &token.value).ok_or_else(|| {
+                FlexibleError::UnknownValue {
+                    timeline: timeline.name.clone(),
+                    token: pos,
+                }
+            })?;
+            let (min, max) = spec.duration_limits;
+            let duration = ConstraintKind::Duration { token: token.id };
+            stn.add_constraint(
+                start,
+                end,
+                Some(min as f32),
+                max.map(|m| m as f32),
+                duration,
+            );
+            if let Some((start_time, end_time)) = spec.fact {
+                let fixed = || ConstraintKind::FixedTime { token: token.id };
+                if let Some(t) = start_time {
+                    stn.add_constraint(origin, start, Some(t as f32), Some(t as f32), fixed());
+                }
+                if let Some(t) = end_time {
+                    stn.add_constraint(origin, end, Some(t as f32), Some(t as f32), fixed());
+                }
+            }
+            if let Some(group) =
+                (0..spec.conditions.len()).find(|g| !token.conditions.iter().any(|s| s.group == *g))
+            {
+                return Err(FlexibleError::MissingSatisfaction {
+                    token: token.id,
+                    group,
+                });
+            }
+            specs.insert(token.id, spec);
+        }
+    }
+
+    // CONDITIONS
+
+    // Resource users by the id of the token they use.
+    let mut users: HashMap<usize, Vec<(usize, u32)>> = HashMap::new();
+    for token in all_tokens() {
+        let (start, end) = timepoints[&token.id];
+        for satisfaction in token.conditions.iter() {
+            let condition = specs[&token.id]
+                .conditions
+                .get(satisfaction.group)
+                .and_then(|group| group.get(satisfaction.alternative));
+            let (condition, (target_start, target_end)) =
+                match (condition, timepoints.get(&satisfaction.target)) {
+                    (Some(condition), Some(target)) => (condition, *target),
+                    _ => {
+                        return Err(FlexibleError::UnknownSatisfaction {
+                            token: token.id,
+                            group: satisfaction.group,
+                        })
+                    }
+                };
+            let exact = Some(0.0);
+            let before = (Some(0.0), None);
+            let mut add = |from, to, (min, max): (Option<f32>, Option<f32>)| {
+                let kind = ConstraintKind::Condition {
+                    token: token.id,
+                    group: satisfaction.group,
+                    target: satisfaction.target,
+                };
+                stn.add_constraint(from, to, min, max, kind)
+            };
+            match condition.temporal_relationship {
+                TemporalRelationship::MetBy | TemporalRelationship::MetByTransitionFrom => {
+                    add(target_end, start, (exact, exact))
+                }
+                TemporalRelationship::Meets => add(end, target_start, (exact, exact)),
+                TemporalRelationship::Starts => add(target_start, start, (exact, exact)),
+                TemporalRelationship::StartsAfter => add(target_start, start, before),
+                TemporalRelationship::Cover => {
+                    add(target_start, start, before);
+                    add(end, target_end, before);
+                }
+                TemporalRelationship::StartPrecond => {
+                    add(target_start, start, (Some(1.0), None));
+                    add(start, target_end, before);
+                }
+                TemporalRelationship::StartEffect => {
+                    add(target_start, start, before);
+                    add(start, target_end, (Some(1.0), None));
+                }
+                TemporalRelationship::Equal => {
+                    add(start, target_start, (exact, exact));
+                    add(end, target_end, (exact, exact));
+                }
+            }
+            if satisfaction.amount > 0 {
+                users
+                    .entry(satisfaction.target)
+                    .or_default()
+                    .push((token.id, satisfaction.amount));
+            }
+        }
+    }
+
+    // RESOURCE ORDERINGS
+
+    // Users that do not overlap in the solution are kept in the same order. Then
+    // users that overlap in the flexible plan also overlap pairwise in the
+    // solution, and intervals that overlap pairwise have a common point, so the
+    // capacity is respected.
+    let tokens_by_id = all_tokens().map(|t| (t.id, t)).collect::<HashMap<_, _>>();
+    let mut targets = users.keys().copied().collect::<Vec<_>>();
+    targets.sort_unstable();
+    for target in targets {
+        let target_users = &users[&target];
+        if target_users.iter().map(|(_, amount)| *amount).sum::<u32>() <= specs[&target].capacity {
+            continue;
+        }
+        for (a, _) in target_users.iter() {
+            for (b, _) in target_users.iter() {
+                if a != b && time_le(tokens_by_id[a].end_time, tokens_by_id[b].start_time) {
+                    stn.add_constraint(
+                        timepoints[a].1,
+                        timepoints[b].0,
+                        Some(0.0),
+                        None,
+                        ConstraintKind::ResourceOrder { resource: target },
+                    );
+                }
+            }
+        }
+    }
+
+    let windows = stn.windows().ok_or(FlexibleError::Inconsistent)?;
+
+    Ok(FlexibleSolution {
+        timelines: solution
+            .timelines
+            .iter()
+            .map(|tl| FlexibleTimeline {
+                name: tl.name.clone(),
+                tokens: tl
+                    .tokens
+                    .iter()
+                    .map(|token| {
+                        let (start, end) = timepoints[&token.id];
+                        FlexibleToken {
+                            id: token.id,
+                            value: token.value.clone(),
+                            start_timepoint: start,
+                            end_timepoint: end,
+                            start: windows[start],
+                            end: windows[end],
+                        }
+                    })
+                    .collect(),
+            })
+            .collect(),
+        end_of_time: windows[end_of_time],
+        stn,
+    })
+}
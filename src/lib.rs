@@ -1,6 +1,25 @@
 pub mod problem;
+pub mod compiled;
+pub mod transitiongraph;
 pub mod transitionsolver;
 pub mod cores;
+pub mod symmetry;
+pub mod portfolio;
+pub mod decompose;
+pub mod diagnosis;
+pub mod smtdump;
+pub mod backend;
+#[cfg(feature = "native")]
+pub mod native;
+pub mod validate;
+pub mod flexible;
+pub mod monitor;
+pub mod slack;
+pub mod resources;
+pub mod render;
+pub mod report;
+pub mod diff;
+pub mod export;
 
 pub fn solve_json(input :String) -> String {
     let problem = serde_json::de::from_str::<problem::Problem>(&input).unwrap();
@@ -30,10 +49,14 @@ pub enum SolverError {
     NoSolution,
     GoalValueDurationLimit,
     GoalStateMissing,
-}
-
-
-pub fn z3real_value(real: &z3::ast::Real) -> f32 {
-    let (num, den) = real.as_real().unwrap();
-    num as f32 / den as f32
+    Cancelled,
+    Panicked(String),
+    // The solver gave up, e.g. at a timeout from its parameters.
+    Unknown,
+    // The solver process replied with an error or stopped.
+    Backend(String),
+    // Writing the SMT-LIB2 dump failed.
+    Io(String),
+    // The debug self-check found a solution that breaks the problem's constraints.
+    InvalidSolution(Vec<validate::Violation>),
 }
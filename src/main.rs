@@ -1,5 +1,14 @@
-use paraspace::{print_calc_time, problem, transitionsolver};
-use std::path::PathBuf;
+use paraspace::{
+    cores::{CoreBudget, CoreMinimization},
+    decompose, diagnosis, diff, export, flexible, monitor, portfolio, print_calc_time, problem,
+    render, report, resources, slack,
+    transitionsolver::{self, ResourceEncoding, SolverBackend, SolverSettings},
+    SolverError,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -13,35 +22,300 @@ struct Opt {
     #[structopt(parse(from_os_str))]
     output: Option<PathBuf>,
 
+    /// Format of the output file: json, csv, ics or jsonl
+    #[structopt(long = "format", default_value = "json")]
+    format: export::ExportFormat,
+
+    /// Wall-clock time of solver time 0 in exported timestamps, as YYYY-MM-DD[THH:MM:SS[Z]] or Unix seconds
+    #[structopt(long = "epoch", default_value = "0", parse(try_from_str = export::parse_timestamp))]
+    epoch: i64,
+
+    /// Length of one solver time unit in seconds in exported timestamps
+    #[structopt(long = "timeunit", default_value = "1")]
+    time_unit: f64,
+
     #[structopt(long = "benchmark")]
     perftest: bool,
 
     #[structopt(long = "minimizecores")]
     minimizecores: bool,
+
+    /// Core minimization algorithm: linear, quickxplain, dnc or progression
+    #[structopt(long = "coreminimization", default_value = "linear")]
+    core_minimization: CoreMinimization,
+
+    /// Time limit for each solver call during core minimization, in milliseconds
+    #[structopt(long = "coretimeout")]
+    core_timeout: Option<u32>,
+
+    /// Conflict limit for each solver call during core minimization
+    #[structopt(long = "coreconflicts")]
+    core_conflicts: Option<u32>,
+
+    #[structopt(long = "nosymmetry")]
+    nosymmetry: bool,
+
+    /// Resource encoding: pairwise, pb, taskinterval or lazy
+    #[structopt(long = "resourceencoding", default_value = "pairwise")]
+    resource_encoding: ResourceEncoding,
+
+    /// Resource encoding for a single resource, as timeline:value=encoding (can be repeated)
+    #[structopt(long = "resourceencodingfor", parse(try_from_str = parse_resource_encoding))]
+    resource_encoding_overrides: Vec<((String, String), ResourceEncoding)>,
+
+    #[structopt(long = "autoconfig")]
+    autoconfig: bool,
+
+    #[structopt(long = "arithsolver", default_value = "6")]
+    arith_solver: u32,
+
+    #[structopt(long = "seed")]
+    seed: Option<u32>,
+
+    /// Solution to a similar problem to start from
+    #[structopt(long = "hint", parse(from_os_str))]
+    hint: Option<PathBuf>,
+
+    /// Write the SMT-LIB2 formula of each solver call to this directory
+    #[structopt(long = "smtdump", parse(from_os_str))]
+    smt_dump_dir: Option<PathBuf>,
+
+    /// Write a flexible solution with time windows for each token to this file
+    #[structopt(long = "flexible", parse(from_os_str))]
+    flexible: Option<PathBuf>,
+
+    /// Replay observed token start and end times from this file on the solution
+    #[structopt(long = "observations", parse(from_os_str))]
+    observations: Option<PathBuf>,
+
+    /// Print the slack of each token and the critical path of the solution, and draw it in charts
+    #[structopt(long = "slack")]
+    slack: bool,
+
+    /// Print the usage profile of each resource in the solution, and draw it in charts
+    #[structopt(long = "resourceprofiles")]
+    resource_profiles: bool,
+
+    /// Write a Gantt chart of the solution to this SVG file
+    #[structopt(long = "svg", parse(from_os_str))]
+    svg: Option<PathBuf>,
+
+    /// Write a Gantt chart of the solution to this HTML file
+    #[structopt(long = "html", parse(from_os_str))]
+    html: Option<PathBuf>,
+
+    /// Write a self-contained HTML report of the solve to this file
+    #[structopt(long = "report", parse(from_os_str))]
+    report: Option<PathBuf>,
+
+    /// Compare the solution with an earlier solution from this file
+    #[structopt(long = "diff", parse(from_os_str))]
+    diff: Option<PathBuf>,
+
+    /// Time differences up to this are not reported by --diff
+    #[structopt(long = "difftolerance", default_value = "0.001")]
+    diff_tolerance: f32,
+
+    /// Time differences up to this fraction of the time are not reported by --diff
+    #[structopt(long = "diffrelativetolerance", default_value = "0")]
+    diff_relative_tolerance: f32,
+
+    /// Write the differences found by --diff as JSON to this file
+    #[structopt(long = "diffjson", parse(from_os_str))]
+    diff_json: Option<PathBuf>,
+
+    /// Compare the solution in this file with the one given by --diff, without solving
+    #[structopt(long = "diffwith", parse(from_os_str), requires = "diff")]
+    diff_with: Option<PathBuf>,
+
+    /// Use an SMT-LIB2 solver process instead of Z3, e.g. "cvc5 --incremental"
+    #[structopt(long = "smtlib")]
+    smtlib: Option<String>,
+
+    /// Use the built-in difference logic solver instead of Z3
+    #[structopt(long = "native")]
+    native: bool,
+
+    /// Fix the Z3 random seed so that repeated runs give the same solution
+    #[structopt(long = "deterministic")]
+    deterministic: bool,
+
+    /// Run several solver configurations in parallel, starting with the one given by the other options
+    #[structopt(long = "portfolio")]
+    portfolio: bool,
+
+    /// Solve independent groups of timelines separately
+    #[structopt(long = "decompose")]
+    decompose: bool,
+
+    /// Solve the independent groups of timelines in parallel (with --decompose)
+    #[structopt(long = "parallel")]
+    parallel: bool,
+
+    /// Enumerate minimal conflicting sets of goals, conditions and capacities
+    #[structopt(long = "diagnose")]
+    diagnose: bool,
+
+    /// Maximum number of conflicts to find (with --diagnose)
+    #[structopt(long = "diagnoseconflicts", default_value = "10")]
+    diagnose_conflicts: usize,
+
+    /// Time limit in seconds (with --diagnose)
+    #[structopt(long = "diagnosetimeout", default_value = "60")]
+    diagnose_timeout: u64,
+
+    /// Z3 parameter, as name=value (can be repeated)
+    #[structopt(long = "z3param", parse(try_from_str = parse_z3_param))]
+    z3_params: Vec<(String, String)>,
+}
+
+fn parse_z3_param(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected name=value, got \"{}\"", s))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn parse_resource_encoding(s: &str) -> Result<((String, String), ResourceEncoding), String> {
+    let (resource, encoding) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("Expected timeline:value=encoding, got \"{}\"", s))?;
+    let (timeline, value) = resource
+        .split_once(':')
+        .ok_or_else(|| format!("Expected timeline:value=encoding, got \"{}\"", s))?;
+    Ok(((timeline.to_string(), value.to_string()), encoding.parse()?))
+}
+
+impl Opt {
+    fn solver_settings(&self) -> SolverSettings {
+        SolverSettings {
+            minimize_cores: self.minimizecores,
+            core_minimization: self.core_minimization,
+            core_budget: CoreBudget {
+                timeout_ms: self.core_timeout,
+                max_conflicts: self.core_conflicts,
+            },
+            symmetry_reduction: !self.nosymmetry,
+            resource_encoding: self.resource_encoding,
+            resource_encoding_overrides: self.resource_encoding_overrides.iter().cloned().collect(),
+            auto_config: self.autoconfig,
+            arith_solver: self.arith_solver,
+            random_seed: self.seed,
+            deterministic: self.deterministic,
+            z3_params: self.z3_params.clone(),
+            cancel: None,
+            hint: self.hint.as_ref().map(|filename| {
+                let contents = std::fs::read_to_string(filename).unwrap();
+                Arc::new(serde_json::de::from_str::<problem::Solution>(&contents).unwrap())
+            }),
+            smt_dump_dir: self.smt_dump_dir.clone(),
+            end_of_time: None,
+            backend: match self.smtlib.as_ref() {
+                Some(command) => SolverBackend::SmtLibProcess {
+                    command: command.split_whitespace().map(str::to_string).collect(),
+                },
+                None if self.native => SolverBackend::Native,
+                None => SolverBackend::default(),
+            },
+        }
+    }
+}
+
+// Print the differences from the solution in the `old` file, and write them as
+// JSON if asked to.
+fn print_diff(opt: &Opt, old: &Path, solution: &problem::Solution) {
+    let contents = std::fs::read_to_string(old).unwrap();
+    let old_solution = serde_json::de::from_str::<problem::Solution>(&contents).unwrap();
+    let options = diff::DiffOptions {
+        tolerance: opt.diff_tolerance,
+        relative_tolerance: opt.diff_relative_tolerance,
+    };
+    let solution_diff = diff::diff_with_options(&old_solution, solution, &options);
+    print!("{}", solution_diff);
+    if let Some(diff_json) = opt.diff_json.as_ref() {
+        std::fs::write(
+            diff_json,
+            serde_json::to_string_pretty(&solution_diff).unwrap(),
+        )
+        .unwrap();
+        println!(
+            "Wrote differences to file '{}'",
+            diff_json.to_str().unwrap()
+        );
+    }
 }
 
 fn main() {
     let opt = Opt::from_args();
     println!("{:?}", opt);
 
+    let settings = opt.solver_settings();
+
     if opt.perftest {
-        perftest();
+        perftest(&settings);
     }
 
-    let solver_func = transitionsolver::solve;
+    let solver_func = |problem: &problem::Problem, settings: &SolverSettings| {
+        if opt.decompose {
+            (
+                decompose::solve_decomposed(problem, settings, opt.parallel),
+                None,
+            )
+        } else if opt.portfolio {
+            let mut configurations = portfolio::default_portfolio();
+            configurations[0] = ("command line".to_string(), settings.clone());
+            (
+                portfolio::solve_portfolio(problem, &configurations).and_then(|p| p.result),
+                None,
+            )
+        } else {
+            let (result, statistics) = transitionsolver::solve_with_statistics(problem, settings);
+            println!("Statistics: {:?}", statistics);
+            (result, Some(statistics))
+        }
+    };
 
-    if let Some(filename) = opt.input {
+    if let (Some(old), Some(new)) = (opt.diff.as_ref(), opt.diff_with.as_ref()) {
+        let contents = std::fs::read_to_string(new).unwrap();
+        let new_solution = serde_json::de::from_str::<problem::Solution>(&contents).unwrap();
+        print_diff(&opt, old, &new_solution);
+    } else if let Some(filename) = opt.input.as_ref() {
         let problem = {
             let _p = hprof::enter("load_problem");
-            let contents = std::fs::read_to_string(&filename).unwrap();
+            let contents = std::fs::read_to_string(filename).unwrap();
             serde_json::de::from_str::<problem::Problem>(&contents).unwrap()
         };
 
-        let minimizecores = opt.minimizecores;
-        let result = print_calc_time(filename.to_str().unwrap(), || {
-            solver_func(&problem, &Default::default())
+        let limits = diagnosis::DiagnosisLimits {
+            max_conflicts: opt.diagnose_conflicts,
+            time_limit: std::time::Duration::from_secs(opt.diagnose_timeout),
+        };
+        if opt.diagnose {
+            let diagnosis = diagnosis::diagnose(&problem, &settings, &limits);
+            if diagnosis.complete {
+                println!("Found {} conflicts.", diagnosis.conflicts.len());
+            } else {
+                println!(
+                    "Found {} conflicts before stopping, there may be more.",
+                    diagnosis.conflicts.len()
+                );
+            }
+            for (i, conflict) in diagnosis.conflicts.iter().enumerate() {
+                println!("Conflict #{}:", i + 1);
+                for c in conflict.iter() {
+                    println!("  - {}", diagnosis.constraints[*c].description);
+                }
+            }
+            hprof::profiler().print_timing();
+            return;
+        }
+
+        let solve_start = std::time::Instant::now();
+        let (result, statistics) = print_calc_time(filename.to_str().unwrap(), || {
+            solver_func(&problem, &settings)
         });
-        match result {
+        let solve_time = solve_start.elapsed();
+        match &result {
             Ok(solution) => {
                 println!("Solved.  (end of time = {})", solution.end_of_time);
                 for timeline in solution.timelines.iter() {
@@ -57,17 +331,161 @@ fn main() {
                     )
                 }
 
+                if opt.slack {
+                    match slack::slack_analysis(&problem, solution) {
+                        Ok(analysis) => {
+                            println!(
+                                "Makespan {} (end of time = {})",
+                                analysis.makespan, analysis.end_of_time
+                            );
+                            for token in analysis.tokens.iter() {
+                                println!(
+                                    "  {} {} ({}): slack {}/{}{}",
+                                    token.timeline,
+                                    token.token,
+                                    token.value,
+                                    token.start_slack,
+                                    token.end_slack,
+                                    if token.critical { " (critical)" } else { "" }
+                                );
+                            }
+                            println!("Critical path:");
+                            for step in analysis.critical_path.iter() {
+                                println!(
+                                    "  {} at {} by {:?}",
+                                    step.timepoint, step.earliest, step.constraint
+                                );
+                            }
+                        }
+                        Err(err) => println!("No flexible plan: {:?}", err),
+                    }
+                }
+
+                if opt.resource_profiles {
+                    for profile in resources::resource_profiles(&problem, solution) {
+                        println!(
+                            "Resource {} {} ({}): peak {}/{}, full at {:?}",
+                            profile.timeline,
+                            profile.token,
+                            profile.value,
+                            profile.peak,
+                            profile.capacity,
+                            profile.full
+                        );
+                        println!("  usage {:?}", profile.steps);
+                    }
+                }
+
+                if let Some(old) = opt.diff.as_ref() {
+                    print_diff(&opt, old, solution);
+                }
+
                 if let Some(output) = opt.output {
-                    std::fs::write(&output, serde_json::to_string_pretty(&solution).unwrap())
+                    let mapping = export::TimeMapping {
+                        epoch: opt.epoch,
+                        seconds_per_unit: opt.time_unit,
+                    };
+                    std::fs::write(&output, export::export(solution, opt.format, &mapping))
                         .unwrap();
 
                     println!("Wrote to file '{}'", output.to_str().unwrap());
                 }
+
+                let render_options = render::RenderOptions {
+                    resource_profiles: opt.resource_profiles,
+                    slack: opt.slack,
+                    ..Default::default()
+                };
+                if let Some(svg) = opt.svg {
+                    let chart = render::render_svg(solution, Some(&problem), &render_options);
+                    std::fs::write(&svg, chart).unwrap();
+                    println!("Wrote chart to file '{}'", svg.to_str().unwrap());
+                }
+                if let Some(html) = opt.html {
+                    let chart = render::render_html(solution, Some(&problem), &render_options);
+                    std::fs::write(&html, chart).unwrap();
+                    println!("Wrote chart to file '{}'", html.to_str().unwrap());
+                }
+
+                if let Some(flexible) = opt.flexible {
+                    match flexible::flexible_solution(&problem, solution) {
+                        Ok(flexible_solution) => {
+                            std::fs::write(
+                                &flexible,
+                                serde_json::to_string_pretty(&flexible_solution).unwrap(),
+                            )
+                            .unwrap();
+
+                            println!(
+                                "Wrote flexible solution to file '{}'",
+                                flexible.to_str().unwrap()
+                            );
+                        }
+                        Err(err) => println!("No flexible plan: {:?}", err),
+                    }
+                }
+
+                if let Some(observations) = opt.observations {
+                    let contents = std::fs::read_to_string(&observations).unwrap();
+                    let observations =
+                        serde_json::de::from_str::<Vec<monitor::Observation>>(&contents).unwrap();
+                    match monitor::Monitor::from_solution(&problem, solution) {
+                        Ok(mut monitor) => {
+                            for observation in observations.iter() {
+                                match monitor.observe(observation) {
+                                    Ok(delays) => {
+                                        println!("Observed {:?}", observation);
+                                        for delay in delays {
+                                            println!(
+                                                "  {} {} ({}) {:?} delayed by {} to {}",
+                                                delay.timeline,
+                                                delay.token,
+                                                delay.value,
+                                                delay.point,
+                                                delay.delay,
+                                                delay.earliest
+                                            );
+                                        }
+                                    }
+                                    Err(err) => {
+                                        println!(
+                                            "Observed {:?}: {:?}, replanning needed.",
+                                            observation, err
+                                        );
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        Err(err) => println!("Can not monitor the plan: {:?}", err),
+                    }
+                }
             }
             Err(err) => {
                 println!("Error: {:#?}", err);
             }
         }
+
+        if let Some(report) = opt.report.as_ref() {
+            // Infeasible problems are explained in the report.
+            let diagnosis = match &result {
+                Err(SolverError::NoSolution) => {
+                    Some(diagnosis::diagnose(&problem, &settings, &limits))
+                }
+                _ => None,
+            };
+            let html = report::SolveReport {
+                name: filename.to_str().unwrap(),
+                problem: &problem,
+                result: &result,
+                statistics: statistics.as_ref(),
+                diagnosis: diagnosis.as_ref(),
+                solve_time: Some(solve_time),
+            }
+            .to_html();
+            std::fs::write(report, html).unwrap();
+            println!("Wrote report to file '{}'", report.to_str().unwrap());
+        }
     } else {
         println!("No problem files given.");
     }
@@ -75,7 +493,7 @@ fn main() {
     hprof::profiler().print_timing();
 }
 
-fn perftest() {
+fn perftest(settings: &SolverSettings) {
     let mut problem_names = Vec::new();
     for plates in [1, 2] {
         for n_carbonaras in [
@@ -105,7 +523,7 @@ fn perftest() {
         // println!("Problem:\n{:#?}", problem);
         // println!("Solving...");
         let result = print_calc_time(&problem_name, || {
-            transitionsolver::solve(&problem, &Default::default())
+            transitionsolver::solve(&problem, settings)
         });
         match result {
             Ok(solution) => {
@@ -122,10 +540,3 @@ fn perftest() {
         }
     }
 }
-
-// Compilation idea:
-//  Detect when two resources can be joined together into one
-//  For example, in carbonara domain, boiling/cooking needs to select a plate,
-//   and then use it exclusively, but if there are several plates they behave
-//   just like if there was a resource with higher capacity. Symmetry reduction
-//   effect by treating them as interchangable.
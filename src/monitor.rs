@@ -0,0 +1,237 @@
+use crate::{
+    flexible::{
+        flexible_solution, ConstraintKind, FlexibleError, FlexibleSolution, Stn, TimeWindow,
+        END_OF_TIME, ORIGIN,
+    },
+    problem::{Problem, Solution},
+    validate::time_lt,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Execution monitoring. Observed start and end times of tokens are added to the
+// temporal network of a flexible plan, and the time windows of the remaining
+// timepoints are updated. The end of time may be pushed beyond the planned
+// horizon, but any other constraint that can not be met means that the plan has
+// to be replaced.
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenPoint {
+    Start,
+    End,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub token: usize,
+    pub point: TokenPoint,
+    pub time: f32,
+}
+
+// A token start or end that can not happen as early as before.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Delay {
+    pub timeline: String,
+    pub token: usize,
+    pub value: String,
+    pub point: TokenPoint,
+    pub earliest: f32,
+    pub delay: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorError {
+    UnknownToken(usize),
+    Inconsistent,
+    Flexible(FlexibleError),
+}
+
+pub struct Monitor {
+    plan: FlexibleSolution,
+    stn: Stn,
+    horizon: f32,
+    token_idxs: HashMap<usize, (usize, usize)>,
+    observed: Vec<Option<f32>>,
+    now: f32,
+    windows: Vec<TimeWindow>,
+    consistent: bool,
+}
+
+impl Monitor {
+    pub fn new(plan: FlexibleSolution) -> Result<Monitor, MonitorError> {
+        let mut stn = plan.stn.clone();
+        for c in stn.constraints.iter_mut() {
+            if c.from == ORIGIN && c.to == END_OF_TIME {
+                c.max = None;
+            }
+        }
+
+        let token_idxs = plan
+            .timelines
+            .iter()
+            .enumerate()
+            .flat_map(|(timeline_idx, tl)| {
+                tl.tokens
+                    .iter()
+                    .enumerate()
+                    .map(move |(token_idx, t)| (t.id, (timeline_idx, token_idx)))
+            })
+            .collect();
+
+        // Times that are fixed in the problem are known from the start.
+        let mut observed = vec![None; stn.timepoints.len()];
+        for c in stn.constraints.iter() {
+            if let (ORIGIN, Some(min), Some(max)) = (c.from, c.min, c.max) {
+                if min == max {
+                    observed[c.to] = Some(min);
+                }
+            }
+        }
+
+        let mut monitor = Monitor {
+            horizon: plan.end_of_time.latest,
+            observed,
+            now: f32::NEG_INFINITY,
+            windows: Vec::new(),
+            consistent: true,
+            token_idxs,
+            stn,
+            plan,
+        };
+        monitor.windows = monitor
+            .compute_windows()
+            .ok_or(MonitorError::Inconsistent)?;
+        Ok(monitor)
+    }
+
+    pub fn from_solution(problem: &Problem, solution: &Solution) -> Result<Monitor, MonitorError> {
+        Monitor::new(flexible_solution(problem, solution).map_err(MonitorError::Flexible)?)
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.consistent
+    }
+
+    pub fn end_of_time(&self) -> TimeWindow {
+        self.windows[END_OF_TIME]
+    }
+
+    pub fn window(&self, token: usize, point: TokenPoint) -> Option<TimeWindow> {
+        self.timepoint(token, point).map(|tp| self.windows[tp])
+    }
+
+    // The plan with the current time windows.
+    pub fn current_plan(&self) -> FlexibleSolution {
+        let mut plan = self.plan.clone();
+        for token in plan
+            .timelines
+            .iter_mut()
+            .flat_map(|tl| tl.tokens.iter_mut())
+        {
+            token.start = self.windows[token.start_timepoint];
+            token.end = self.windows[token.end_timepoint];
+        }
+        plan.end_of_time = self.windows[END_OF_TIME];
+        plan
+    }
+
+    pub fn observe(&mut self, observation: &Observation) -> Result<Vec<Delay>, MonitorError> {
+        let tp = self
+            .timepoint(observation.token, observation.point)
+            .ok_or(MonitorError::UnknownToken(observation.token))?;
+        let time = observation.time;
+        self.stn.add_constraint(
+            ORIGIN,
+            tp,
+            Some(time),
+            Some(time),
+            ConstraintKind::Observation,
+        );
+        self.observed[tp] = Some(time);
+        self.update()
+    }
+
+    // Timepoints that have not been observed by `now` have not happened yet.
+    pub fn advance(&mut self, now: f32) -> Result<Vec<Delay>, MonitorError> {
+        self.now = self.now.max(now);
+        self.update()
+    }
+
+    fn timepoint(&self, token: usize, point: TokenPoint) -> Option<usize> {
+        let (timeline_idx, token_idx) = *self.token_idxs.get(&token)?;
+        let token = &self.plan.timelines[timeline_idx].tokens[token_idx];
+        Some(match point {
+            TokenPoint::Start => token.start_timepoint,
+            TokenPoint::End => token.end_timepoint,
+        })
+    }
+
+    fn compute_windows(&self) -> Option<Vec<TimeWindow>> {
+        let mut stn = self.stn.clone();
+        if self.now.is_finite() {
+            for tp in 0..stn.timepoints.len() {
+                if tp != ORIGIN && self.observed[tp].is_none() {
+                    stn.add_constraint(
+                        ORIGIN,
+                        tp,
+                        Some(self.now),
+                        None,
+                        ConstraintKind::Observation,
+                    );
+                }
+            }
+        }
+
+        // The earliest times do not depend on the horizon, which is extended
+        // only as far as needed.
+        let earliest_end = stn.windows()?[END_OF_TIME].earliest;
+        let horizon = self.horizon.max(earliest_end);
+        stn.add_constraint(
+            ORIGIN,
+            END_OF_TIME,
+            None,
+            Some(horizon),
+            ConstraintKind::Horizon,
+        );
+        stn.windows()
+    }
+
+    fn update(&mut self) -> Result<Vec<Delay>, MonitorError> {
+        if !self.consistent {
+            return Err(MonitorError::Inconsistent);
+        }
+        let windows = match self.compute_windows() {
+            Some(windows) => windows,
+            None => {
+                self.consistent = false;
+                return Err(MonitorError::Inconsistent);
+            }
+        };
+
+        let mut delays = Vec::new();
+        for timeline in self.plan.timelines.iter() {
+            for token in timeline.tokens.iter() {
+                let points = [
+                    (TokenPoint::Start, token.start_timepoint),
+                    (TokenPoint::End, token.end_timepoint),
+                ];
+                for (point, tp) in points.iter() {
+                    let (before, after) = (self.windows[*tp].earliest, windows[*tp].earliest);
+                    if self.observed[*tp].is_none() && time_lt(before, after) {
+                        delays.push(Delay {
+                            timeline: timeline.name.clone(),
+                            token: token.id,
+                            value: token.value.clone(),
+                            point: *point,
+                            earliest: after,
+                            delay: after - before,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.windows = windows;
+        Ok(delays)
+    }
+}
@@ -0,0 +1,1392 @@
+use crate::backend::{real_literal, Backend, CheckResult};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// A solver for the part of SMT that the timeline encoding uses, written in Rust
+// so that paraspace can be built without Z3: a CDCL SAT solver with
+// pseudo-boolean constraints, and a difference logic theory for the times.
+// Real terms are a variable plus a constant, so the arithmetic atoms are
+// `x - y <= c` and their negations `y - x < -c`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Lit(u32);
+
+impl Lit {
+    fn new(var: usize, positive: bool) -> Lit {
+        Lit((var as u32) << 1 | (!positive) as u32)
+    }
+
+    fn var(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    fn is_positive(self) -> bool {
+        self.0 & 1 == 0
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl std::ops::Not for Lit {
+    type Output = Lit;
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+// Variable 0 is the constant true.
+const TRUE: Lit = Lit(0);
+
+//
+// Numbers
+//
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rat {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Rat {
+    const ZERO: Rat = Rat { num: 0, den: 1 };
+    const ONE: Rat = Rat { num: 1, den: 1 };
+
+    fn new(num: i128, den: i128) -> Rat {
+        if den == 1 {
+            return Rat { num, den };
+        }
+        let g = gcd(num, den).max(1) * den.signum();
+        Rat {
+            num: num / g,
+            den: den / g,
+        }
+    }
+}
+
+impl std::ops::Add for Rat {
+    type Output = Rat;
+    fn add(self, other: Rat) -> Rat {
+        if self.den == other.den {
+            Rat::new(self.num + other.num, self.den)
+        } else {
+            Rat::new(
+                self.num * other.den + other.num * self.den,
+                self.den * other.den,
+            )
+        }
+    }
+}
+
+impl std::ops::Neg for Rat {
+    type Output = Rat;
+    fn neg(self) -> Rat {
+        Rat {
+            num: -self.num,
+            den: self.den,
+        }
+    }
+}
+
+impl std::ops::Sub for Rat {
+    type Output = Rat;
+    fn sub(self, other: Rat) -> Rat {
+        self + (-other)
+    }
+}
+
+impl std::ops::Mul for Rat {
+    type Output = Rat;
+    fn mul(self, other: Rat) -> Rat {
+        Rat::new(self.num * other.num, self.den * other.den)
+    }
+}
+
+impl std::ops::Div for Rat {
+    type Output = Rat;
+    fn div(self, other: Rat) -> Rat {
+        Rat::new(self.num * other.den, self.den * other.num)
+    }
+}
+
+impl Ord for Rat {
+    fn cmp(&self, other: &Rat) -> std::cmp::Ordering {
+        (self.num * other.den).cmp(&(other.num * self.den))
+    }
+}
+
+impl PartialOrd for Rat {
+    fn partial_cmp(&self, other: &Rat) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// `value + delta * d` for an arbitrarily small positive `d`, which turns strict
+// inequalities into non-strict ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Bound {
+    value: Rat,
+    delta: i64,
+}
+
+impl std::ops::Add for Bound {
+    type Output = Bound;
+    fn add(self, other: Bound) -> Bound {
+        Bound {
+            value: self.value + other.value,
+            delta: self.delta + other.delta,
+        }
+    }
+}
+
+impl std::ops::Sub for Bound {
+    type Output = Bound;
+    fn sub(self, other: Bound) -> Bound {
+        Bound {
+            value: self.value - other.value,
+            delta: self.delta - other.delta,
+        }
+    }
+}
+
+//
+// Difference logic
+//
+
+// The constraint `potential[to] <= potential[from] + weight`.
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    from: usize,
+    to: usize,
+    weight: Bound,
+    lit: Lit,
+}
+
+#[derive(Default)]
+struct DifferenceLogic {
+    // A solution of the active edges, which stays a solution when edges are removed.
+    potential: Vec<Bound>,
+    out_edges: Vec<Vec<usize>>,
+    // Active edges, with the trail position of the literal that added them.
+    edges: Vec<(usize, Edge)>,
+    pred: Vec<usize>,
+    in_queue: Vec<bool>,
+}
+
+impl DifferenceLogic {
+    fn add_node(&mut self) -> usize {
+        self.potential.push(Bound {
+            value: Rat::ZERO,
+            delta: 0,
+        });
+        self.out_edges.push(Vec::new());
+        self.pred.push(usize::MAX);
+        self.in_queue.push(false);
+        self.potential.len() - 1
+    }
+
+    // Add the edge and update the potentials, or return the literals of the
+    // edges on a negative cycle.
+    fn add_edge(&mut self, trail_pos: usize, edge: Edge) -> Result<(), Vec<Lit>> {
+        let reached = self.potential[edge.from] + edge.weight;
+        if reached < self.potential[edge.to] {
+            let mut changes = vec![(edge.to, self.potential[edge.to])];
+            self.potential[edge.to] = reached;
+            self.pred[edge.to] = usize::MAX;
+            let mut queue = VecDeque::from(vec![edge.to]);
+            self.in_queue[edge.to] = true;
+
+            while let Some(n) = queue.pop_front() {
+                self.in_queue[n] = false;
+                for i in 0..self.out_edges[n].len() {
+                    let e = self.out_edges[n][i];
+                    let (to, weight) = (self.edges[e].1.to, self.edges[e].1.weight);
+                    let reached = self.potential[n] + weight;
+                    if reached >= self.potential[to] {
+                        continue;
+                    }
+                    if to == edge.from {
+                        // The path from `edge.to` to `n`, the edge `e` and the new edge form a negative cycle.
+                        let mut lits = vec![edge.lit, self.edges[e].1.lit];
+                        let mut m = n;
+                        while m != edge.to {
+                            let p = self.edges[self.pred[m]].1;
+                            lits.push(p.lit);
+                            m = p.from;
+                        }
+                        for n in queue {
+                            self.in_queue[n] = false;
+                        }
+                        for (n, potential) in changes.into_iter().rev() {
+                            self.potential[n] = potential;
+                        }
+                        return Err(lits);
+                    }
+                    changes.push((to, self.potential[to]));
+                    self.potential[to] = reached;
+                    self.pred[to] = e;
+                    if !self.in_queue[to] {
+                        self.in_queue[to] = true;
+                        queue.push_back(to);
+                    }
+                }
+            }
+        }
+
+        self.out_edges[edge.from].push(self.edges.len());
+        self.edges.push((trail_pos, edge));
+        Ok(())
+    }
+
+    fn backtrack(&mut self, trail_len: usize) {
+        while let Some((pos, edge)) = self.edges.last() {
+            if *pos < trail_len {
+                break;
+            }
+            self.out_edges[edge.from].pop();
+            self.edges.pop();
+        }
+    }
+
+    // Values for the nodes relative to node 0, with a value for `d` that
+    // satisfies all the active edges.
+    fn values(&self) -> Vec<Rat> {
+        let mut d = Rat::ONE;
+        for (_, edge) in self.edges.iter() {
+            let diff = self.potential[edge.to] - self.potential[edge.from];
+            if diff.value < edge.weight.value && diff.delta > edge.weight.delta {
+                let limit = (edge.weight.value - diff.value)
+                    / Rat::new((diff.delta - edge.weight.delta) as i128, 1);
+                d = d.min(limit);
+            }
+        }
+        let zero = self.potential[0];
+        self.potential
+            .iter()
+            .map(|p| {
+                let b = *p - zero;
+                b.value + d * Rat::new(b.delta as i128, 1)
+            })
+            .collect()
+    }
+}
+
+//
+// SAT solver
+//
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Reason {
+    None,
+    Clause(usize),
+    Explanation(usize),
+}
+
+struct Clause {
+    lits: Vec<Lit>,
+    learnt: bool,
+    activity: f64,
+}
+
+// The sum of the weights of the true literals is at most `bound` when the guard is true.
+struct PseudoBoolean {
+    guard: Lit,
+    lits: Vec<(Lit, u64)>,
+    bound: u64,
+    sum: u64,
+}
+
+// Variables by activity, for choosing the next decision.
+#[derive(Default)]
+struct VarHeap {
+    heap: Vec<usize>,
+    position: Vec<Option<usize>>,
+}
+
+impl VarHeap {
+    fn less(activity: &[f64], a: usize, b: usize) -> bool {
+        activity[a] > activity[b] || (activity[a] == activity[b] && a < b)
+    }
+
+    fn contains(&self, v: usize) -> bool {
+        self.position.get(v).map(|p| p.is_some()).unwrap_or(false)
+    }
+
+    fn insert(&mut self, activity: &[f64], v: usize) {
+        if self.position.len() <= v {
+            self.position.resize(v + 1, None);
+        }
+        if self.contains(v) {
+            return;
+        }
+        self.heap.push(v);
+        self.position[v] = Some(self.heap.len() - 1);
+        self.up(activity, self.heap.len() - 1);
+    }
+
+    fn increased(&mut self, activity: &[f64], v: usize) {
+        if let Some(i) = self.position.get(v).copied().flatten() {
+            self.up(activity, i);
+        }
+    }
+
+    fn pop(&mut self, activity: &[f64]) -> Option<usize> {
+        if self.heap.is_empty() {
+            return None;
+        }
+        let v = self.heap.swap_remove(0);
+        self.position[v] = None;
+        if !self.heap.is_empty() {
+            self.position[self.heap[0]] = Some(0);
+            self.down(activity, 0);
+        }
+        Some(v)
+    }
+
+    fn up(&mut self, activity: &[f64], mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !Self::less(activity, self.heap[i], self.heap[parent]) {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn down(&mut self, activity: &[f64], mut i: usize) {
+        loop {
+            let mut best = i;
+            for child in [2 * i + 1, 2 * i + 2] {
+                if child < self.heap.len()
+                    && Self::less(activity, self.heap[child], self.heap[best])
+                {
+                    best = child;
+                }
+            }
+            if best == i {
+                break;
+            }
+            self.swap(i, best);
+            i = best;
+        }
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        self.position[self.heap[i]] = Some(i);
+        self.position[self.heap[j]] = Some(j);
+    }
+}
+
+fn luby(mut i: u64) -> u64 {
+    // The i-th element (from 0) of 1, 1, 2, 1, 1, 2, 4, 1, 1, 2, ...
+    let mut size = 1;
+    let mut seq = 0;
+    while size < i + 1 {
+        seq += 1;
+        size = 2 * size + 1;
+    }
+    while size - 1 != i {
+        size = (size - 1) / 2;
+        seq -= 1;
+        i %= size;
+    }
+    1 << seq
+}
+
+#[derive(Default)]
+struct Model {
+    bools: Vec<bool>,
+    reals: Vec<Rat>,
+}
+
+// How a variable was created, for writing the formula.
+enum VarKind {
+    Named(String),
+    Internal,
+    // `x - y <= c`
+    Atom(usize, usize, Rat),
+}
+
+#[derive(Default)]
+struct Solver {
+    // Per variable
+    kinds: Vec<VarKind>,
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Reason>,
+    activity: Vec<f64>,
+    polarity: Vec<bool>,
+    seen: Vec<bool>,
+    heap: VarHeap,
+    var_inc: f64,
+
+    // Per literal
+    watches: Vec<Vec<usize>>,
+    pb_occurrences: Vec<Vec<(usize, u64)>>,
+
+    trail: Vec<Lit>,
+    trail_lim: Vec<usize>,
+    qhead: usize,
+    clauses: Vec<Clause>,
+    clause_inc: f64,
+    max_learnts: f64,
+    pbs: Vec<PseudoBoolean>,
+    // Reasons for theory and pseudo-boolean propagations, with the trail position
+    // of the propagated literal. The propagated literal comes first.
+    explanations: Vec<(usize, Vec<Lit>)>,
+    ok: bool,
+
+    real_names: Vec<String>,
+    difference_logic: DifferenceLogic,
+    atoms: HashMap<(usize, usize, Rat), Lit>,
+    n_fresh: usize,
+
+    core: Vec<Lit>,
+    // Literals tried as true first, restored at the start of each check.
+    preferred: Vec<Lit>,
+    model: Model,
+    timeout_ms: Option<u32>,
+    max_conflicts: Option<u32>,
+}
+
+impl Solver {
+    fn new() -> Solver {
+        let mut solver = Solver {
+            ok: true,
+            var_inc: 1.0,
+            clause_inc: 1.0,
+            ..Default::default()
+        };
+        solver.new_var(VarKind::Internal);
+        solver.enqueue(TRUE, Reason::None);
+        solver.real_names.push("0.0".to_string());
+        solver.difference_logic.add_node();
+        solver
+    }
+
+    fn new_var(&mut self, kind: VarKind) -> usize {
+        let v = self.values.len();
+        self.kinds.push(kind);
+        self.values.push(None);
+        self.levels.push(0);
+        self.reasons.push(Reason::None);
+        self.activity.push(0.0);
+        self.polarity.push(false);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.pb_occurrences.push(Vec::new());
+        self.pb_occurrences.push(Vec::new());
+        self.heap.insert(&self.activity, v);
+        v
+    }
+
+    fn fresh_name(&mut self, prefix: &str) -> String {
+        self.n_fresh += 1;
+        format!("{}!{}", prefix, self.n_fresh - 1)
+    }
+
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.values[lit.var()].map(|v| v == lit.is_positive())
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Reason) {
+        let v = lit.var();
+        self.values[v] = Some(lit.is_positive());
+        self.levels[v] = self.decision_level();
+        self.reasons[v] = reason;
+        self.trail.push(lit);
+        for (pb, weight) in self.pb_occurrences[lit.index()].iter() {
+            self.pbs[*pb].sum += weight;
+        }
+    }
+
+    fn enqueue_explained(&mut self, explanation: Vec<Lit>) {
+        let lit = explanation[0];
+        self.explanations.push((self.trail.len(), explanation));
+        self.enqueue(lit, Reason::Explanation(self.explanations.len() - 1));
+    }
+
+    fn cancel_until(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+        let lim = self.trail_lim[level];
+        for i in (lim..self.trail.len()).rev() {
+            let lit = self.trail[i];
+            let v = lit.var();
+            self.values[v] = None;
+            self.reasons[v] = Reason::None;
+            self.polarity[v] = lit.is_positive();
+            for (pb, weight) in self.pb_occurrences[lit.index()].iter() {
+                self.pbs[*pb].sum -= weight;
+            }
+            self.heap.insert(&self.activity, v);
+        }
+        self.trail.truncate(lim);
+        self.trail_lim.truncate(level);
+        self.qhead = lim;
+        while matches!(self.explanations.last(), Some((pos, _)) if *pos >= lim) {
+            self.explanations.pop();
+        }
+        self.difference_logic.backtrack(lim);
+    }
+
+    fn reason_lits(&self, v: usize) -> &[Lit] {
+        match self.reasons[v] {
+            Reason::Clause(c) => &self.clauses[c].lits,
+            Reason::Explanation(e) => &self.explanations[e].1,
+            Reason::None => &[],
+        }
+    }
+
+    //
+    // Adding constraints, at level 0
+    //
+
+    fn add_clause(&mut self, lits: &[Lit]) {
+        self.cancel_until(0);
+        if !self.ok {
+            return;
+        }
+        let mut lits = lits.to_vec();
+        lits.sort_unstable();
+        lits.dedup();
+        if lits.windows(2).any(|w| w[0] == !w[1])
+            || lits.iter().any(|l| self.value(*l) == Some(true))
+        {
+            return;
+        }
+        lits.retain(|l| self.value(*l).is_none());
+        match lits.len() {
+            0 => self.ok = false,
+            1 => self.enqueue(lits[0], Reason::None),
+            _ => {
+                self.watches[lits[0].index()].push(self.clauses.len());
+                self.watches[lits[1].index()].push(self.clauses.len());
+                self.clauses.push(Clause {
+                    lits,
+                    learnt: false,
+                    activity: 0.0,
+                });
+            }
+        }
+    }
+
+    fn add_pb(&mut self, guard: Lit, lits: Vec<(Lit, u64)>, bound: u64) {
+        self.cancel_until(0);
+        let idx = self.pbs.len();
+        self.pb_occurrences[guard.index()].push((idx, 0));
+        let mut sum = 0;
+        for (lit, weight) in lits.iter() {
+            self.pb_occurrences[lit.index()].push((idx, *weight));
+            if self.value(*lit) == Some(true) {
+                sum += weight;
+            }
+        }
+        self.pbs.push(PseudoBoolean {
+            guard,
+            lits,
+            bound,
+            sum,
+        });
+        if self.ok && self.check_pb(idx).is_some() {
+            self.ok = false;
+        }
+    }
+
+    fn and(&mut self, args: &[Lit]) -> Lit {
+        let mut args = args
+            .iter()
+            .copied()
+            .filter(|a| *a != TRUE)
+            .collect::<Vec<_>>();
+        args.sort_unstable();
+        args.dedup();
+        if args.contains(&!TRUE) || args.windows(2).any(|w| w[0] == !w[1]) {
+            return !TRUE;
+        }
+        match args.len() {
+            0 => TRUE,
+            1 => args[0],
+            _ => {
+                let v = Lit::new(self.new_var(VarKind::Internal), true);
+                for a in args.iter() {
+                    self.add_clause(&[!v, *a]);
+                }
+                let mut clause = args.iter().map(|a| !*a).collect::<Vec<_>>();
+                clause.push(v);
+                self.add_clause(&clause);
+                v
+            }
+        }
+    }
+
+    fn pb_le(&mut self, args: &[(Lit, i32)], k: i32) -> Lit {
+        // Negative weights are moved to the negated literal.
+        let mut bound = k as i64;
+        let mut lits = Vec::new();
+        for (lit, weight) in args.iter() {
+            match (*weight).cmp(&0) {
+                std::cmp::Ordering::Greater => lits.push((*lit, *weight as u64)),
+                std::cmp::Ordering::Less => {
+                    lits.push((!*lit, weight.unsigned_abs() as u64));
+                    bound -= *weight as i64;
+                }
+                std::cmp::Ordering::Equal => {}
+            }
+        }
+        let total = lits.iter().map(|(_, w)| *w).sum::<u64>() as i64;
+        if bound < 0 {
+            return !TRUE;
+        }
+        if total <= bound {
+            return TRUE;
+        }
+
+        let p = Lit::new(self.new_var(VarKind::Internal), true);
+        let negated = lits.iter().map(|(l, w)| (!*l, *w)).collect();
+        self.add_pb(p, lits, bound as u64);
+        self.add_pb(!p, negated, (total - bound - 1) as u64);
+        p
+    }
+
+    // `potential[x] - potential[y] <= c`
+    fn atom(&mut self, x: usize, y: usize, c: Rat) -> Lit {
+        if let Some(lit) = self.atoms.get(&(x, y, c)) {
+            return *lit;
+        }
+        let lit = Lit::new(self.new_var(VarKind::Atom(x, y, c)), true);
+        self.atoms.insert((x, y, c), lit);
+        lit
+    }
+
+    //
+    // Propagation
+    //
+
+    fn propagate(&mut self) -> Option<Vec<Lit>> {
+        while self.qhead < self.trail.len() {
+            let pos = self.qhead;
+            let lit = self.trail[pos];
+            self.qhead += 1;
+
+            if let Some(conflict) = self.propagate_clauses(lit) {
+                return Some(conflict);
+            }
+
+            for i in 0..self.pb_occurrences[lit.index()].len() {
+                let pb = self.pb_occurrences[lit.index()][i].0;
+                if let Some(conflict) = self.check_pb(pb) {
+                    return Some(conflict);
+                }
+            }
+
+            if let VarKind::Atom(x, y, c) = self.kinds[lit.var()] {
+                let edge = if lit.is_positive() {
+                    Edge {
+                        from: y,
+                        to: x,
+                        weight: Bound { value: c, delta: 0 },
+                        lit,
+                    }
+                } else {
+                    Edge {
+                        from: x,
+                        to: y,
+                        weight: Bound {
+                            value: -c,
+                            delta: -1,
+                        },
+                        lit,
+                    }
+                };
+                if let Err(cycle) = self.difference_logic.add_edge(pos, edge) {
+                    return Some(cycle.into_iter().map(|l| !l).collect());
+                }
+            }
+        }
+        None
+    }
+
+    fn propagate_clauses(&mut self, lit: Lit) -> Option<Vec<Lit>> {
+        let false_lit = !lit;
+        let watchers = std::mem::take(&mut self.watches[false_lit.index()]);
+        let mut kept = Vec::with_capacity(watchers.len());
+        let mut conflict = None;
+        let mut i = 0;
+        while i < watchers.len() {
+            let c = watchers[i];
+            i += 1;
+            let lits = &mut self.clauses[c].lits;
+            if lits[0] == false_lit {
+                lits.swap(0, 1);
+            }
+            let first = lits[0];
+            if self.values[first.var()].map(|v| v == first.is_positive()) == Some(true) {
+                kept.push(c);
+                continue;
+            }
+            let values = &self.values;
+            let replacement = (2..lits.len()).find(|k| {
+                let l = lits[*k];
+                values[l.var()].map(|v| v == l.is_positive()) != Some(false)
+            });
+            if let Some(k) = replacement {
+                lits.swap(1, k);
+                let watch = lits[1];
+                self.watches[watch.index()].push(c);
+                continue;
+            }
+            kept.push(c);
+            if self.value(first) == Some(false) {
+                conflict = Some(self.clauses[c].lits.clone());
+                kept.extend_from_slice(&watchers[i..]);
+                break;
+            }
+            self.enqueue(first, Reason::Clause(c));
+        }
+        self.watches[false_lit.index()] = kept;
+        conflict
+    }
+
+    fn check_pb(&mut self, idx: usize) -> Option<Vec<Lit>> {
+        let pb = &self.pbs[idx];
+        let guard = self.value(pb.guard);
+        if guard == Some(false) {
+            return None;
+        }
+        let true_lits = || {
+            let mut explanation = vec![!pb.guard];
+            explanation.extend(
+                pb.lits
+                    .iter()
+                    .filter(|(l, _)| self.value(*l) == Some(true))
+                    .map(|(l, _)| !*l),
+            );
+            explanation
+        };
+
+        if pb.sum > pb.bound {
+            let explanation = true_lits();
+            if guard == Some(true) {
+                return Some(explanation);
+            }
+            self.enqueue_explained(explanation);
+            return None;
+        }
+
+        if guard == Some(true) {
+            let slack = pb.bound - pb.sum;
+            let implied = pb
+                .lits
+                .iter()
+                .filter(|(l, w)| *w > slack && self.value(*l).is_none())
+                .map(|(l, _)| !*l)
+                .collect::<Vec<_>>();
+            if !implied.is_empty() {
+                let explanation = true_lits();
+                for lit in implied {
+                    let mut reason = vec![lit];
+                    reason.extend_from_slice(&explanation);
+                    self.enqueue_explained(reason);
+                }
+            }
+        }
+        None
+    }
+
+    //
+    // Search
+    //
+
+    fn bump_var(&mut self, v: usize) {
+        self.activity[v] += self.var_inc;
+        if self.activity[v] > 1e100 {
+            for a in self.activity.iter_mut() {
+                *a *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+        self.heap.increased(&self.activity, v);
+    }
+
+    fn bump_clause(&mut self, c: usize) {
+        self.clauses[c].activity += self.clause_inc;
+        if self.clauses[c].activity > 1e20 {
+            for clause in self.clauses.iter_mut() {
+                clause.activity *= 1e-20;
+            }
+            self.clause_inc *= 1e-20;
+        }
+    }
+
+    // First-UIP conflict analysis. Returns the learnt clause, with the asserting
+    // literal first, and the level to backjump to.
+    fn analyze(&mut self, conflict: Vec<Lit>) -> (Vec<Lit>, usize) {
+        let current = self.decision_level();
+        let mut learnt = vec![TRUE];
+        let mut open = 0;
+        let mut clause = conflict;
+        let mut skip_first = false;
+        let mut idx = self.trail.len();
+        let uip = loop {
+            for q in clause.iter().skip(skip_first as usize) {
+                let v = q.var();
+                if !self.seen[v] && self.levels[v] > 0 {
+                    self.seen[v] = true;
+                    self.bump_var(v);
+                    if self.levels[v] >= current {
+                        open += 1;
+                    } else {
+                        learnt.push(*q);
+                    }
+                }
+            }
+
+            loop {
+                idx -= 1;
+                if self.seen[self.trail[idx].var()] {
+                    break;
+                }
+            }
+            let p = self.trail[idx];
+            self.seen[p.var()] = false;
+            open -= 1;
+            if open == 0 {
+                break p;
+            }
+            if let Reason::Clause(c) = self.reasons[p.var()] {
+                if self.clauses[c].learnt {
+                    self.bump_clause(c);
+                }
+            }
+            clause = self.reason_lits(p.var()).to_vec();
+            skip_first = true;
+        };
+        learnt[0] = !uip;
+        for lit in learnt[1..].iter() {
+            self.seen[lit.var()] = false;
+        }
+
+        let mut backjump = 0;
+        if learnt.len() > 1 {
+            let max = (1..learnt.len())
+                .max_by_key(|i| self.levels[learnt[*i].var()])
+                .unwrap();
+            learnt.swap(1, max);
+            backjump = self.levels[learnt[1].var()];
+        }
+        (learnt, backjump)
+    }
+
+    // The assumptions that imply the negation of the assumption `p`.
+    fn analyze_final(&mut self, p: Lit) -> Vec<Lit> {
+        let mut core = vec![p];
+        if self.levels[p.var()] == 0 {
+            return core;
+        }
+        self.seen[p.var()] = true;
+        for i in (self.trail_lim[0]..self.trail.len()).rev() {
+            let lit = self.trail[i];
+            let v = lit.var();
+            if !self.seen[v] {
+                continue;
+            }
+            if self.reasons[v] == Reason::None {
+                core.push(lit);
+            } else {
+                let reason = self.reason_lits(v).to_vec();
+                for q in reason[1..].iter() {
+                    if self.levels[q.var()] > 0 {
+                        self.seen[q.var()] = true;
+                    }
+                }
+            }
+            self.seen[v] = false;
+        }
+        self.seen[p.var()] = false;
+        core
+    }
+
+    fn learn(&mut self, learnt: Vec<Lit>) {
+        if learnt.len() == 1 {
+            self.enqueue(learnt[0], Reason::None);
+            return;
+        }
+        let c = self.clauses.len();
+        self.watches[learnt[0].index()].push(c);
+        self.watches[learnt[1].index()].push(c);
+        let lit = learnt[0];
+        self.clauses.push(Clause {
+            lits: learnt,
+            learnt: true,
+            activity: 0.0,
+        });
+        self.bump_clause(c);
+        self.enqueue(lit, Reason::Clause(c));
+    }
+
+    // Remove the less active half of the learnt clauses. Only at level 0, where
+    // no clause is needed as a reason.
+    fn reduce_learnts(&mut self) {
+        debug_assert!(self.decision_level() == 0);
+        let mut learnts = (0..self.clauses.len())
+            .filter(|c| self.clauses[*c].learnt && self.clauses[*c].lits.len() > 2)
+            .collect::<Vec<_>>();
+        if (learnts.len() as f64) < self.max_learnts {
+            return;
+        }
+        learnts.sort_by(|a, b| {
+            self.clauses[*a]
+                .activity
+                .partial_cmp(&self.clauses[*b].activity)
+                .unwrap()
+        });
+        let mut remove = vec![false; self.clauses.len()];
+        for c in learnts[..learnts.len() / 2].iter() {
+            remove[*c] = true;
+        }
+
+        for reason in self.reasons.iter_mut() {
+            if matches!(reason, Reason::Clause(_)) {
+                *reason = Reason::None;
+            }
+        }
+        let mut i = 0;
+        self.clauses.retain(|_| {
+            i += 1;
+            !remove[i - 1]
+        });
+        for watches in self.watches.iter_mut() {
+            watches.clear();
+        }
+        for (c, clause) in self.clauses.iter().enumerate() {
+            self.watches[clause.lits[0].index()].push(c);
+            self.watches[clause.lits[1].index()].push(c);
+        }
+        self.max_learnts *= 1.1;
+    }
+
+    fn solve(&mut self, assumptions: &[Lit], interrupt: &AtomicBool) -> CheckResult {
+        self.cancel_until(0);
+        self.core.clear();
+        for lit in self.preferred.iter() {
+            self.polarity[lit.var()] = lit.is_positive();
+        }
+        if !self.ok {
+            return CheckResult::Unsat;
+        }
+        if self.max_learnts == 0.0 {
+            self.max_learnts = (self.clauses.len() as f64 / 3.0).max(5000.0);
+        }
+        let deadline = self
+            .timeout_ms
+            .map(|ms| Instant::now() + Duration::from_millis(ms as u64));
+        let mut conflicts = 0;
+        let mut restarts = 0;
+
+        loop {
+            let restart_conflicts = luby(restarts) * 100;
+            let mut local_conflicts = 0;
+            loop {
+                if let Some(conflict) = self.propagate() {
+                    conflicts += 1;
+                    local_conflicts += 1;
+                    let max_level = conflict
+                        .iter()
+                        .map(|l| self.levels[l.var()])
+                        .max()
+                        .unwrap_or(0);
+                    if max_level == 0 {
+                        self.ok = false;
+                        return CheckResult::Unsat;
+                    }
+                    self.cancel_until(max_level);
+                    let (learnt, backjump) = self.analyze(conflict);
+                    self.cancel_until(backjump);
+                    self.learn(learnt);
+                    self.var_inc /= 0.95;
+                    self.clause_inc /= 0.999;
+
+                    if interrupt.load(Ordering::SeqCst)
+                        || self.max_conflicts.map(|m| conflicts >= m).unwrap_or(false)
+                        || deadline.map(|d| Instant::now() >= d).unwrap_or(false)
+                    {
+                        self.cancel_until(0);
+                        return CheckResult::Unknown;
+                    }
+                    continue;
+                }
+
+                if local_conflicts >= restart_conflicts {
+                    break;
+                }
+
+                let mut next = None;
+                while self.decision_level() < assumptions.len() {
+                    let p = assumptions[self.decision_level()];
+                    match self.value(p) {
+                        Some(true) => self.trail_lim.push(self.trail.len()),
+                        Some(false) => {
+                            self.core = self.analyze_final(p);
+                            return CheckResult::Unsat;
+                        }
+                        None => {
+                            next = Some(p);
+                            break;
+                        }
+                    }
+                }
+                let next = match next.or_else(|| self.pick_branch()) {
+                    Some(lit) => lit,
+                    None => {
+                        self.model = Model {
+                            bools: self.values.iter().map(|v| v.unwrap_or(false)).collect(),
+                            reals: self.difference_logic.values(),
+                        };
+                        return CheckResult::Sat;
+                    }
+                };
+                self.trail_lim.push(self.trail.len());
+                self.enqueue(next, Reason::None);
+            }
+
+            restarts += 1;
+            self.cancel_until(0);
+            self.reduce_learnts();
+        }
+    }
+
+    fn pick_branch(&mut self) -> Option<Lit> {
+        while let Some(v) = self.heap.pop(&self.activity) {
+            if self.values[v].is_none() {
+                return Some(Lit::new(v, self.polarity[v]));
+            }
+        }
+        None
+    }
+
+    //
+    // SMT-LIB2 output
+    //
+
+    fn var_name(&self, v: usize) -> String {
+        match &self.kinds[v] {
+            VarKind::Named(name) => name.clone(),
+            _ if v == 0 => "true".to_string(),
+            _ => format!("_b{}", v),
+        }
+    }
+
+    fn display(&self, lit: Lit) -> String {
+        if lit.is_positive() {
+            self.var_name(lit.var())
+        } else {
+            format!("(not {})", self.var_name(lit.var()))
+        }
+    }
+
+    fn formula(&self) -> String {
+        let rat = |r: Rat| real_literal(r.num as i64, r.den as i64);
+        let node = |n: usize| self.real_names[n].clone();
+        let mut out = String::new();
+        for name in self.real_names[1..].iter() {
+            out.push_str(&format!("(declare-fun {} () Real)\n", name));
+        }
+        for (v, kind) in self.kinds.iter().enumerate().skip(1) {
+            match kind {
+                VarKind::Atom(x, y, c) => out.push_str(&format!(
+                    "(define-fun _b{} () Bool (<= (- {} {}) {}))\n",
+                    v,
+                    node(*x),
+                    node(*y),
+                    rat(*c)
+                )),
+                _ => out.push_str(&format!("(declare-fun {} () Bool)\n", self.var_name(v))),
+            }
+        }
+        let level0 = self.trail_lim.first().copied().unwrap_or(self.trail.len());
+        for lit in self.trail[1..level0].iter() {
+            out.push_str(&format!("(assert {})\n", self.display(*lit)));
+        }
+        for clause in self.clauses.iter().filter(|c| !c.learnt) {
+            let lits = clause
+                .lits
+                .iter()
+                .map(|l| self.display(*l))
+                .collect::<Vec<_>>();
+            out.push_str(&format!("(assert (or {}))\n", lits.join(" ")));
+        }
+        for pb in self.pbs.iter() {
+            let terms = pb
+                .lits
+                .iter()
+                .map(|(l, w)| format!("(ite {} {}.0 0.0)", self.display(*l), w))
+                .collect::<Vec<_>>();
+            out.push_str(&format!(
+                "(assert (=> {} (<= (+ 0.0 {}) {}.0)))\n",
+                self.display(pb.guard),
+                terms.join(" "),
+                pb.bound
+            ));
+        }
+        if !self.ok {
+            out.push_str("(assert false)\n");
+        }
+        out
+    }
+}
+
+//
+// Backend
+//
+
+// A real term: the value of a difference logic node plus a constant. Node 0 is zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NativeReal {
+    node: usize,
+    offset: Rat,
+}
+
+pub struct NativeBackend {
+    solver: RefCell<Solver>,
+    interrupt: Arc<AtomicBool>,
+}
+
+impl Default for NativeBackend {
+    fn default() -> Self {
+        NativeBackend::new()
+    }
+}
+
+fn unsupported(op: &str) -> ! {
+    panic!(
+        "The native backend only supports difference constraints, not {}. \
+         Use another resource encoding or backend.",
+        op
+    )
+}
+
+impl NativeBackend {
+    pub fn new() -> NativeBackend {
+        NativeBackend {
+            solver: RefCell::new(Solver::new()),
+            interrupt: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Stops the running check and all later checks.
+    pub fn interrupter(&self) -> impl Fn() + Send + Sync {
+        let interrupt = self.interrupt.clone();
+        move || interrupt.store(true, Ordering::SeqCst)
+    }
+}
+
+impl Backend for NativeBackend {
+    type Bool = Lit;
+    type Real = NativeReal;
+
+    fn fresh_bool(&self, prefix: &str) -> Lit {
+        let mut s = self.solver.borrow_mut();
+        let name = s.fresh_name(prefix);
+        Lit::new(s.new_var(VarKind::Named(name)), true)
+    }
+
+    fn fresh_real(&self, prefix: &str) -> NativeReal {
+        let mut s = self.solver.borrow_mut();
+        let name = s.fresh_name(prefix);
+        s.real_names.push(name);
+        NativeReal {
+            node: s.difference_logic.add_node(),
+            offset: Rat::ZERO,
+        }
+    }
+
+    fn bool_const(&self, value: bool) -> Lit {
+        if value {
+            TRUE
+        } else {
+            !TRUE
+        }
+    }
+
+    fn real_const(&self, num: i64, den: i64) -> NativeReal {
+        NativeReal {
+            node: 0,
+            offset: Rat::new(num as i128, den as i128),
+        }
+    }
+
+    fn not(&self, a: &Lit) -> Lit {
+        !*a
+    }
+
+    fn and(&self, args: &[&Lit]) -> Lit {
+        let args = args.iter().map(|a| **a).collect::<Vec<_>>();
+        self.solver.borrow_mut().and(&args)
+    }
+
+    fn or(&self, args: &[&Lit]) -> Lit {
+        let args = args.iter().map(|a| !**a).collect::<Vec<_>>();
+        !self.solver.borrow_mut().and(&args)
+    }
+
+    fn implies(&self, a: &Lit, b: &Lit) -> Lit {
+        self.or(&[&!*a, b])
+    }
+
+    fn pb_le(&self, args: &[(&Lit, i32)], k: i32) -> Lit {
+        let args = args.iter().map(|(a, w)| (**a, *w)).collect::<Vec<_>>();
+        self.solver.borrow_mut().pb_le(&args, k)
+    }
+
+    fn add(&self, args: &[&NativeReal]) -> NativeReal {
+        let mut sum = NativeReal {
+            node: 0,
+            offset: Rat::ZERO,
+        };
+        for a in args.iter() {
+            if a.node != 0 && sum.node != 0 {
+                unsupported("sums of variables");
+            }
+            sum.node += a.node;
+            sum.offset = sum.offset + a.offset;
+        }
+        sum
+    }
+
+    fn sub(&self, args: &[&NativeReal]) -> NativeReal {
+        let mut result = *args[0];
+        for a in args[1..].iter() {
+            if a.node != 0 && a.node != result.node {
+                unsupported("differences of variables as terms");
+            }
+            if a.node != 0 {
+                result.node = 0;
+            }
+            result.offset = result.offset - a.offset;
+        }
+        result
+    }
+
+    fn mul(&self, args: &[&NativeReal]) -> NativeReal {
+        if args.iter().any(|a| a.node != 0) {
+            unsupported("multiplication");
+        }
+        NativeReal {
+            node: 0,
+            offset: args.iter().fold(Rat::ONE, |p, a| p * a.offset),
+        }
+    }
+
+    fn ite(&self, _c: &Lit, a: &NativeReal, b: &NativeReal) -> NativeReal {
+        if a != b {
+            unsupported("if-then-else terms");
+        }
+        *a
+    }
+
+    fn le(&self, a: &NativeReal, b: &NativeReal) -> Lit {
+        let c = b.offset - a.offset;
+        if a.node == b.node {
+            return self.bool_const(Rat::ZERO <= c);
+        }
+        self.solver.borrow_mut().atom(a.node, b.node, c)
+    }
+
+    fn lt(&self, a: &NativeReal, b: &NativeReal) -> Lit {
+        !self.le(b, a)
+    }
+
+    fn ge(&self, a: &NativeReal, b: &NativeReal) -> Lit {
+        self.le(b, a)
+    }
+
+    fn eq(&self, a: &NativeReal, b: &NativeReal) -> Lit {
+        self.and(&[&self.le(a, b), &self.le(b, a)])
+    }
+
+    fn assert(&self, a: &Lit) {
+        self.solver.borrow_mut().add_clause(&[*a]);
+    }
+
+    fn check(&self, assumptions: &[Lit]) -> CheckResult {
+        self.solver.borrow_mut().solve(assumptions, &self.interrupt)
+    }
+
+    fn unsat_core(&self) -> Vec<Lit> {
+        self.solver.borrow().core.clone()
+    }
+
+    fn bool_value(&self, a: &Lit) -> bool {
+        let s = self.solver.borrow();
+        s.model.bools.get(a.var()).copied().unwrap_or(false) == a.is_positive()
+    }
+
+    fn real_value(&self, a: &NativeReal) -> (i64, i64) {
+        let s = self.solver.borrow();
+        let value = s.model.reals.get(a.node).copied().unwrap_or(Rat::ZERO) + a.offset;
+        (value.num as i64, value.den as i64)
+    }
+
+    fn set_budget(&self, timeout_ms: Option<u32>, max_conflicts: Option<u32>) {
+        let mut s = self.solver.borrow_mut();
+        s.timeout_ms = timeout_ms;
+        s.max_conflicts = max_conflicts;
+    }
+
+    fn supports_phase(&self) -> bool {
+        true
+    }
+
+    fn prefer(&self, lits: &[Lit]) {
+        self.solver.borrow_mut().preferred = lits.to_vec();
+    }
+
+    fn bool_name(&self, a: &Lit) -> Option<String> {
+        match &self.solver.borrow().kinds[a.var()] {
+            VarKind::Named(name) if a.is_positive() => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    fn real_name(&self, a: &NativeReal) -> Option<String> {
+        (a.node != 0 && a.offset == Rat::ZERO)
+            .then(|| self.solver.borrow().real_names[a.node].clone())
+    }
+
+    fn display(&self, a: &Lit) -> String {
+        self.solver.borrow().display(*a)
+    }
+
+    fn formula(&self) -> String {
+        self.solver.borrow().formula()
+    }
+
+    fn describe(&self) -> String {
+        "Native solver".to_string()
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{
+    problem::{Problem, Solution},
+    transitionsolver::{self, ResourceEncoding, SolverSettings},
+    SolverError,
+};
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+};
+
+// Portfolio solving: run several solver configurations in parallel, each in its
+// own thread with its own Z3 context, and use the first one that finishes.
+
+#[derive(Debug)]
+pub struct PortfolioResult {
+    // Index of the configuration that finished first.
+    pub winner: usize,
+    pub winner_name: String,
+    pub result: Result<Solution, SolverError>,
+}
+
+pub fn default_portfolio() -> Vec<(String, SolverSettings)> {
+    vec![
+        ("default".to_string(), SolverSettings::default()),
+        (
+            "lazy".to_string(),
+            SolverSettings {
+                resource_encoding: ResourceEncoding::Lazy,
+                ..Default::default()
+            },
+        ),
+        (
+            "minimizecores".to_string(),
+            SolverSettings {
+                minimize_cores: true,
+                ..Default::default()
+            },
+        ),
+        (
+            "arith2".to_string(),
+            SolverSettings {
+                arith_solver: 2,
+                ..Default::default()
+            },
+        ),
+    ]
+}
+
+// Only a solution or a proof that there is none finishes the portfolio. Fails
+// with the last error if every configuration failed in another way, e.g. by
+// panicking, being cancelled, or losing its solver process.
+pub fn solve_portfolio(
+    problem: &Problem,
+    configurations: &[(String, SolverSettings)],
+) -> Result<PortfolioResult, SolverError> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+
+    let (winner, result) = std::thread::scope(|scope| {
+        for (idx, (_, settings)) in configurations.iter().enumerate() {
+            let settings = SolverSettings {
+                cancel: Some(cancel.clone()),
+                ..settings.clone()
+            };
+            let sender = sender.clone();
+            scope.spawn(move || {
+                // A configuration that panics should not bring down the others.
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    transitionsolver::solve(problem, &settings)
+                }))
+                .unwrap_or_else(|panic| {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    Err(SolverError::Panicked(message))
+                });
+                // The receiver is gone if another configuration already finished.
+                let _ = sender.send((idx, result));
+            });
+        }
+        drop(sender);
+
+        let mut first = Err(SolverError::Cancelled);
+        for (idx, result) in receiver.iter() {
+            let finished = matches!(
+                result,
+                Ok(_)
+                    | Err(SolverError::NoSolution
+                        | SolverError::GoalStateMissing
+                        | SolverError::GoalValueDurationLimit)
+            );
+            if finished {
+                first = Ok((idx, result));
+                break;
+            }
+            first = Err(result.unwrap_err());
+        }
+        // Also cancel the others if all configurations were cancelled or failed.
+        cancel.store(true, Ordering::SeqCst);
+        first
+    })?;
+
+    println!(
+        "Portfolio configuration \"{}\" finished first.",
+        configurations[winner].0
+    );
+
+    Ok(PortfolioResult {
+        winner,
+        winner_name: configurations[winner].0.clone(),
+        result,
+    })
+}
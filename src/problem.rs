@@ -1,11 +1,11 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Problem {
     pub timelines: Vec<Timeline>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Token {
     pub value: String,
     pub capacity: u32,
@@ -13,20 +13,20 @@ pub struct Token {
     pub conditions: Vec<Vec<Condition>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum TokenTime {
     Fact(Option<usize>, Option<usize>),
     Goal,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Timeline {
     pub name: String,
     pub token_types: Vec<TokenType>,
     pub static_tokens: Vec<Token>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TokenType {
     pub value: String,
     pub duration_limits: (usize, Option<usize>),
@@ -34,7 +34,7 @@ pub struct TokenType {
     pub capacity: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Condition {
     pub timeline_ref: String,
     pub temporal_relationship: TemporalRelationship,
@@ -59,7 +59,58 @@ impl Condition {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// What a token must satisfy, from its fact or token type: its conditions,
+// duration limits and capacity, and a fact's fixed times.
+pub struct TokenSpec<'a> {
+    pub fact: Option<(Option<usize>, Option<usize>)>,
+    pub duration_limits: (usize, Option<usize>),
+    pub conditions: &'a [Vec<Condition>],
+    pub capacity: u32,
+}
+
+impl<'a> TokenSpec<'a> {
+    pub fn of_fact(fact: &'a Token) -> TokenSpec<'a> {
+        TokenSpec {
+            fact: match fact.const_time {
+                TokenTime::Fact(start_time, end_time) => Some((start_time, end_time)),
+                TokenTime::Goal => None,
+            },
+            duration_limits: (1, None),
+            conditions: &fact.conditions,
+            capacity: fact.capacity,
+        }
+    }
+
+    pub fn of_token_type(token_type: &'a TokenType) -> TokenSpec<'a> {
+        TokenSpec {
+            fact: None,
+            duration_limits: token_type.duration_limits,
+            conditions: &token_type.conditions,
+            capacity: token_type.capacity,
+        }
+    }
+}
+
+impl Timeline {
+    // The spec of the token with the given value at the given position. Facts are
+    // the first tokens on timelines with token types.
+    pub fn token_spec(&self, pos: usize, value: &str) -> Option<TokenSpec<'_>> {
+        let fact = self
+            .static_tokens
+            .iter()
+            .find(|t| t.value == value && matches!(t.const_time, TokenTime::Fact(_, _)));
+        let token_type = self.token_types.iter().find(|tt| tt.value == value);
+        match (fact, token_type) {
+            (Some(fact), token_type) if pos == 0 || token_type.is_none() => {
+                Some(TokenSpec::of_fact(fact))
+            }
+            (_, Some(token_type)) => Some(TokenSpec::of_token_type(token_type)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum TemporalRelationship {
     MetBy,
     MetByTransitionFrom,
@@ -76,21 +127,38 @@ pub enum TemporalRelationship {
 // SOLUTION
 //
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Solution {
     pub timelines: Vec<SolutionTimeline>,
     pub end_of_time :f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SolutionTimeline {
     pub name: String,
     pub tokens: Vec<SolutionToken>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SolutionToken {
+    // Unique within the solution, numbered in timeline order.
+    #[serde(default)]
+    pub id: usize,
     pub value: String,
     pub start_time: f32,
     pub end_time: f32,
+    #[serde(default)]
+    pub conditions: Vec<ConditionSatisfaction>,
+}
+
+// How one of a token's condition groups is satisfied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConditionSatisfaction {
+    // Indices into the conditions of the token's token type or fact.
+    pub group: usize,
+    pub alternative: usize,
+    // The id of the token that the condition refers to.
+    pub target: usize,
+    // The capacity used on the target token.
+    pub amount: u32,
 }
@@ -0,0 +1,316 @@
+use crate::{
+    problem::{Problem, Solution},
+    resources::resource_profiles,
+    slack::{slack_analysis, TokenSlack},
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Gantt charts of solutions as standalone SVG or HTML. Facts are drawn faded
+// with a dashed outline, and critical tokens with a thick outline and a line
+// for their slack. Without the problem, the chart has only the tokens.
+
+const PALETTE: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+const LABEL_WIDTH: f32 = 180.0;
+const AXIS_HEIGHT: f32 = 24.0;
+const ROW_HEIGHT: f32 = 24.0;
+const PROFILE_HEIGHT: f32 = 48.0;
+const MARGIN: f32 = 8.0;
+
+const STYLE: &str = "text { font: 12px sans-serif; } \
+    .axis { stroke: #ccc; } \
+    .token { stroke: #333; stroke-width: 0.5; } \
+    .fact { stroke-dasharray: 4 2; fill-opacity: 0.4; } \
+    .critical { stroke: #000; stroke-width: 2; } \
+    .slack { stroke: #888; stroke-width: 2; } \
+    .usage { fill: #4e79a7; fill-opacity: 0.6; } \
+    .full { fill: #e15759; } \
+    .capacity { stroke: #e15759; stroke-dasharray: 2 2; }";
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    // Width of the time axis in pixels.
+    pub width: f32,
+    pub resource_profiles: bool,
+    pub slack: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            width: 800.0,
+            resource_profiles: false,
+            slack: false,
+        }
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// 1, 2 or 5 times a power of ten, giving about ten ticks.
+fn tick_step(range: f32) -> f32 {
+    let rough = range / 10.0;
+    let magnitude = 10f32.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= rough)
+        .unwrap()
+}
+
+// A colour for each value, in the order the values appear in the solution.
+pub fn value_colors(solution: &Solution) -> HashMap<&str, &'static str> {
+    let mut colors = HashMap::new();
+    for token in solution.timelines.iter().flat_map(|tl| tl.tokens.iter()) {
+        let n = colors.len();
+        colors
+            .entry(token.value.as_str())
+            .or_insert(PALETTE[n % PALETTE.len()]);
+    }
+    colors
+}
+
+pub fn render_svg(
+    solution: &Solution,
+    problem: Option<&Problem>,
+    options: &RenderOptions,
+) -> String {
+    let _p = hprof::enter("render_svg");
+    let profiles = match problem {
+        Some(problem) if options.resource_profiles => resource_profiles(problem, solution),
+        _ => Vec::new(),
+    };
+    let slack: HashMap<usize, TokenSlack> = match problem {
+        Some(problem) if options.slack => slack_analysis(problem, solution)
+            .map(|analysis| analysis.tokens)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| (t.token, t))
+            .collect(),
+        _ => HashMap::new(),
+    };
+    let colors = value_colors(solution);
+
+    let all_tokens = || solution.timelines.iter().flat_map(|tl| tl.tokens.iter());
+    let t0 = all_tokens().map(|t| t.start_time).fold(0.0, f32::min);
+    let mut t1 = all_tokens()
+        .map(|t| t.end_time)
+        .fold(solution.end_of_time, f32::max);
+    if t1 <= t0 {
+        t1 = t0 + 1.0;
+    }
+    let x = |t: f32| LABEL_WIDTH + (t - t0) / (t1 - t0) * options.width;
+
+    let rows_height = solution.timelines.len() as f32 * ROW_HEIGHT;
+    let height = AXIS_HEIGHT + rows_height + profiles.len() as f32 * PROFILE_HEIGHT + MARGIN;
+    let width = LABEL_WIDTH + options.width + MARGIN;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+        width, height, width, height
+    )
+    .unwrap();
+    writeln!(svg, "<style>{}</style>", STYLE).unwrap();
+
+    // TIME AXIS
+
+    let step = tick_step(t1 - t0);
+    let first_tick = (t0 / step).ceil() as i64;
+    for tick in (first_tick..)
+        .map(|i| i as f32 * step)
+        .take_while(|t| *t <= t1)
+    {
+        writeln!(
+            svg,
+            "<line class=\"axis\" x1=\"{x}\" y1=\"{y1}\" x2=\"{x}\" y2=\"{y2}\"/><text x=\"{x}\" y=\"{ty}\" text-anchor=\"middle\">{t}</text>",
+            x = x(tick),
+            y1 = AXIS_HEIGHT - 4.0,
+            y2 = height - MARGIN,
+            ty = AXIS_HEIGHT - 8.0,
+            t = tick
+        )
+        .unwrap();
+    }
+
+    // TIMELINES
+
+    for (row, timeline) in solution.timelines.iter().enumerate() {
+        let y = AXIS_HEIGHT + row as f32 * ROW_HEIGHT;
+        writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\">{}</text>",
+            MARGIN,
+            y + ROW_HEIGHT * 0.65,
+            escape(&timeline.name)
+        )
+        .unwrap();
+        let problem_timeline =
+            problem.and_then(|p| p.timelines.iter().find(|tl| tl.name == timeline.name));
+
+        for (pos, token) in timeline.tokens.iter().enumerate() {
+            let is_fact = problem_timeline
+                .and_then(|tl| tl.token_spec(pos, &token.value))
+                .map(|spec| spec.fact.is_some())
+                .unwrap_or(false);
+            let token_slack = slack.get(&token.id);
+            let mut class = "token".to_string();
+            if is_fact {
+                class.push_str(" fact");
+            }
+            if token_slack.map(|s| s.critical).unwrap_or(false) {
+                class.push_str(" critical");
+            }
+
+            let mut title = format!(
+                "{} {} [{}, {}]",
+                timeline.name, token.value, token.start_time, token.end_time
+            );
+            if is_fact {
+                title.push_str(" (fact)");
+            }
+            if let Some(s) = token_slack {
+                write!(title, ", slack {}/{}", s.start_slack, s.end_slack).unwrap();
+            }
+
+            let (x1, x2) = (x(token.start_time), x(token.end_time));
+            let w = (x2 - x1).max(1.0);
+            writeln!(
+                svg,
+                "<g><title>{}</title><rect class=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                escape(&title),
+                class,
+                x1,
+                y + 3.0,
+                w,
+                ROW_HEIGHT - 6.0,
+                colors[token.value.as_str()]
+            )
+            .unwrap();
+            if w > 7.0 * token.value.len() as f32 {
+                writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\">{}</text>",
+                    x1 + 3.0,
+                    y + ROW_HEIGHT * 0.65,
+                    escape(&token.value)
+                )
+                .unwrap();
+            }
+            if let Some(s) = token_slack.filter(|s| s.end_slack > 0.0) {
+                writeln!(
+                    svg,
+                    "<line class=\"slack\" x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\"/>",
+                    x2,
+                    x(token.end_time + s.end_slack),
+                    y = y + ROW_HEIGHT - 2.0
+                )
+                .unwrap();
+            }
+            writeln!(svg, "</g>").unwrap();
+        }
+    }
+
+    // RESOURCE PROFILES
+
+    for (i, profile) in profiles.iter().enumerate() {
+        let y = AXIS_HEIGHT + rows_height + i as f32 * PROFILE_HEIGHT;
+        let bar_height = PROFILE_HEIGHT - 8.0;
+        writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\">{}.{} ({})</text>",
+            MARGIN,
+            y + PROFILE_HEIGHT * 0.6,
+            escape(&profile.timeline),
+            escape(&profile.value),
+            profile.capacity
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<line class=\"capacity\" x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\"/>",
+            x(profile.start_time),
+            x(profile.end_time),
+            y = y + 4.0
+        )
+        .unwrap();
+        for (j, (time, usage)) in profile.steps.iter().enumerate() {
+            let until = profile
+                .steps
+                .get(j + 1)
+                .map(|s| s.0)
+                .unwrap_or(profile.end_time);
+            let h = bar_height * (*usage as f32 / profile.capacity as f32).min(1.0);
+            writeln!(
+                svg,
+                "<g><title>{}/{} [{}, {}]</title><rect class=\"usage\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/></g>",
+                usage,
+                profile.capacity,
+                time,
+                until,
+                x(*time),
+                y + 4.0 + bar_height - h,
+                (x(until) - x(*time)).max(0.0),
+                h
+            )
+            .unwrap();
+        }
+        for (start, end) in profile.full.iter() {
+            writeln!(
+                svg,
+                "<rect class=\"full\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"3\"/>",
+                x(*start),
+                y + 4.0 + bar_height,
+                (x(*end) - x(*start)).max(1.0)
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+pub fn render_html(
+    solution: &Solution,
+    problem: Option<&Problem>,
+    options: &RenderOptions,
+) -> String {
+    let svg = render_svg(solution, problem, options);
+    let colors = value_colors(solution);
+    let mut values = colors.keys().copied().collect::<Vec<_>>();
+    values.sort_unstable();
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>").unwrap();
+    writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(html, "<title>Solution</title>\n</head>\n<body>").unwrap();
+    writeln!(
+        html,
+        "<h1>Solution</h1>\n<p>End of time: {}</p>",
+        solution.end_of_time
+    )
+    .unwrap();
+    html.push_str(&svg);
+    writeln!(html, "<ul style=\"list-style: none\">").unwrap();
+    for value in values {
+        writeln!(
+            html,
+            "<li><span style=\"display: inline-block; width: 1em; height: 1em; background: {}\"></span> {}</li>",
+            colors[value],
+            escape(value)
+        )
+        .unwrap();
+    }
+    writeln!(html, "</ul>\n</body>\n</html>").unwrap();
+    html
+}
@@ -0,0 +1,213 @@
+use crate::{
+    diagnosis::Diagnosis,
+    problem::{Problem, Solution, TokenTime},
+    render::{escape, render_svg, RenderOptions},
+    transitionsolver::SolverStatistics,
+    SolverError,
+};
+use std::fmt::Write;
+use std::time::Duration;
+
+// A solve report as a single static HTML page, with the problem summary, the
+// Gantt chart of the solution, the solver statistics with the progress of each
+// refinement iteration, and the explanation of an infeasible problem.
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; } \
+    table { border-collapse: collapse; margin-bottom: 1em; } \
+    th, td { border: 1px solid #ccc; padding: 2px 8px; text-align: left; } \
+    th { background: #eee; }";
+
+pub struct SolveReport<'a> {
+    pub name: &'a str,
+    pub problem: &'a Problem,
+    pub result: &'a Result<Solution, SolverError>,
+    pub statistics: Option<&'a SolverStatistics>,
+    pub diagnosis: Option<&'a Diagnosis>,
+    pub solve_time: Option<Duration>,
+}
+
+impl<'a> SolveReport<'a> {
+    pub fn to_html(&self) -> String {
+        let _p = hprof::enter("solve_report");
+        let mut html = String::new();
+        writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+        )
+        .unwrap();
+        writeln!(html, "<title>{}</title>", escape(self.name)).unwrap();
+        writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE).unwrap();
+        writeln!(html, "<h1>{}</h1>", escape(self.name)).unwrap();
+
+        self.write_problem(&mut html);
+        self.write_result(&mut html);
+        if let Some(statistics) = self.statistics {
+            self.write_statistics(&mut html, statistics);
+        }
+        if let Some(diagnosis) = self.diagnosis {
+            self.write_diagnosis(&mut html, diagnosis);
+        }
+
+        writeln!(html, "</body>\n</html>").unwrap();
+        html
+    }
+
+    fn write_problem(&self, html: &mut String) {
+        writeln!(html, "<h2>Problem</h2>").unwrap();
+        writeln!(
+            html,
+            "<table>\n<tr><th>Timeline</th><th>Token types</th><th>Facts</th><th>Goals</th><th>Resources</th></tr>"
+        )
+        .unwrap();
+        for timeline in self.problem.timelines.iter() {
+            let facts = timeline
+                .static_tokens
+                .iter()
+                .filter(|t| matches!(t.const_time, TokenTime::Fact(_, _)))
+                .count();
+            let goals = timeline.static_tokens.len() - facts;
+            let resources = timeline
+                .token_types
+                .iter()
+                .map(|t| t.capacity)
+                .chain(timeline.static_tokens.iter().map(|t| t.capacity))
+                .filter(|c| *c > 0)
+                .count();
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&timeline.name),
+                timeline.token_types.len(),
+                facts,
+                goals,
+                resources
+            )
+            .unwrap();
+        }
+        writeln!(html, "</table>").unwrap();
+    }
+
+    fn write_result(&self, html: &mut String) {
+        writeln!(html, "<h2>Result</h2>").unwrap();
+        let time = self
+            .solve_time
+            .map(|t| format!(" in {:.3} s", t.as_secs_f64()))
+            .unwrap_or_default();
+        match self.result {
+            Ok(solution) => {
+                writeln!(
+                    html,
+                    "<p>Solved{} (end of time = {}).</p>",
+                    time, solution.end_of_time
+                )
+                .unwrap();
+                let options = RenderOptions {
+                    resource_profiles: true,
+                    slack: true,
+                    ..Default::default()
+                };
+                html.push_str(&render_svg(solution, Some(self.problem), &options));
+            }
+            Err(err) => {
+                writeln!(html, "<p>Not solved{}: {:?}.</p>", time, err).unwrap();
+            }
+        }
+    }
+
+    fn write_statistics(&self, html: &mut String, statistics: &SolverStatistics) {
+        writeln!(html, "<h2>Statistics</h2>\n<table>").unwrap();
+        let rows = [
+            ("Solver calls", statistics.solver_calls),
+            ("States", statistics.states),
+            ("Tokens", statistics.tokens),
+            ("Conditions", statistics.conditions),
+            ("Exclusions", statistics.exclusions),
+            ("Pseudo-boolean constraints", statistics.pbs),
+            ("Hints", statistics.hints),
+            ("Hints dropped", statistics.hints_dropped),
+        ];
+        for (name, value) in rows.iter() {
+            writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", name, value).unwrap();
+        }
+        writeln!(html, "</table>").unwrap();
+
+        writeln!(html, "<h3>Refinement</h3>").unwrap();
+        writeln!(
+            html,
+            "<table>\n<tr><th>Call</th><th>States</th><th>Added</th><th>Core size</th><th>By timeline</th></tr>"
+        )
+        .unwrap();
+        let mut prev_states = vec![0; self.problem.timelines.len()];
+        for (i, iteration) in statistics.iterations.iter().enumerate() {
+            let mut by_timeline = Vec::new();
+            for (tl_idx, timeline) in self.problem.timelines.iter().enumerate() {
+                let states = iteration.states.get(tl_idx).copied().unwrap_or(0);
+                let added = states.saturating_sub(prev_states.get(tl_idx).copied().unwrap_or(0));
+                let core = iteration.core_timelines.get(tl_idx).copied().unwrap_or(0);
+                let mut parts = Vec::new();
+                if added > 0 && i > 0 {
+                    parts.push(format!("+{} states", added));
+                }
+                if core > 0 {
+                    parts.push(format!("{} in core", core));
+                }
+                if !parts.is_empty() {
+                    by_timeline.push(format!("{}: {}", escape(&timeline.name), parts.join(", ")));
+                }
+            }
+            // The states of the first call are not counted as added.
+            let total = iteration.states.iter().sum::<usize>();
+            let added = if i > 0 {
+                total
+                    .saturating_sub(prev_states.iter().sum::<usize>())
+                    .to_string()
+            } else {
+                String::new()
+            };
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                i + 1,
+                total,
+                added,
+                iteration
+                    .core_size
+                    .map(|c| c.to_string())
+                    .unwrap_or_default(),
+                by_timeline.join("<br>")
+            )
+            .unwrap();
+            prev_states = iteration.states.clone();
+        }
+        writeln!(html, "</table>").unwrap();
+    }
+
+    fn write_diagnosis(&self, html: &mut String, diagnosis: &Diagnosis) {
+        writeln!(html, "<h2>Infeasibility</h2>").unwrap();
+        writeln!(
+            html,
+            "<p>{} minimal conflicts ({}).</p>",
+            diagnosis.conflicts.len(),
+            if diagnosis.complete {
+                "complete"
+            } else {
+                "incomplete"
+            }
+        )
+        .unwrap();
+        writeln!(html, "<ol>").unwrap();
+        for conflict in diagnosis.conflicts.iter() {
+            writeln!(html, "<li><ul>").unwrap();
+            for c in conflict.iter() {
+                writeln!(
+                    html,
+                    "<li>{}</li>",
+                    escape(&diagnosis.constraints[*c].description)
+                )
+                .unwrap();
+            }
+            writeln!(html, "</ul></li>").unwrap();
+        }
+        writeln!(html, "</ol>").unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{
+    problem::{Problem, Solution},
+    validate::time_eq,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Resource usage profiles. Tokens with a capacity act as resources, and the
+// usage over time is the sum of the amounts of the conditions that are
+// satisfied by the token, as recorded in the solution.
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResourceProfile {
+    pub timeline: String,
+    pub token: usize,
+    pub value: String,
+    pub capacity: u32,
+    pub start_time: f32,
+    pub end_time: f32,
+    // The usage from each time until the next step, or until the end time.
+    pub steps: Vec<(f32, u32)>,
+    pub peak: u32,
+    // Intervals where the usage is at (or above) the capacity.
+    pub full: Vec<(f32, f32)>,
+}
+
+pub fn resource_profiles(problem: &Problem, solution: &Solution) -> Vec<ResourceProfile> {
+    let _p = hprof::enter("resource_profiles");
+
+    let tokens = solution
+        .timelines
+        .iter()
+        .flat_map(|tl| tl.tokens.iter())
+        .map(|t| (t.id, t))
+        .collect::<HashMap<_, _>>();
+    let mut users: HashMap<usize, Vec<(f32, f32, u32)>> = HashMap::new();
+    for token in tokens.values() {
+        for satisfaction in token.conditions.iter().filter(|s| s.amount > 0) {
+            users.entry(satisfaction.target).or_default().push((
+                token.start_time,
+                token.end_time,
+                satisfaction.amount,
+            ));
+        }
+    }
+
+    let mut profiles = Vec::new();
+    for solution_timeline in solution.timelines.iter() {
+        let timeline = match problem
+            .timelines
+            .iter()
+            .find(|tl| tl.name == solution_timeline.name)
+        {
+            Some(timeline) => timeline,
+            None => continue,
+        };
+        for (pos, token) in solution_timeline.tokens.iter().enumerate() {
+            let capacity = match timeline.token_spec(pos, &token.value) {
+                Some(spec) if spec.capacity > 0 => spec.capacity,
+                _ => continue,
+            };
+
+            // Usage changes, with the ends of users before the starts at the same time.
+            let mut events = users
+                .get(&token.id)
+                .map(|users| users.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .flat_map(|(start, end, amount)| {
+                    vec![(*start, *amount as i64), (*end, -(*amount as i64))]
+                })
+                .collect::<Vec<_>>();
+            events.sort_by(|(t1, a1), (t2, a2)| t1.partial_cmp(t2).unwrap().then(a1.cmp(a2)));
+
+            let mut steps = vec![(token.start_time, 0)];
+            let mut usage = 0i64;
+            for (time, change) in events {
+                usage += change;
+                let (last_time, last_usage) = steps.last_mut().unwrap();
+                if time_eq(*last_time, time) || time < *last_time {
+                    *last_usage = usage.max(0) as u32;
+                } else {
+                    steps.push((time, usage.max(0) as u32));
+                }
+            }
+            steps.dedup_by(|(_, u2), (_, u1)| u1 == u2);
+            if steps.len() > 1 && time_eq(steps.last().unwrap().0, token.end_time) {
+                steps.pop();
+            }
+
+            let mut full = Vec::new();
+            for (i, (time, usage)) in steps.iter().enumerate() {
+                if *usage >= capacity {
+                    let until = steps.get(i + 1).map(|s| s.0).unwrap_or(token.end_time);
+                    match full.last_mut() {
+                        Some((_, end)) if *end == *time => *end = until,
+                        _ => full.push((*time, until)),
+                    }
+                }
+            }
+
+            profiles.push(ResourceProfile {
+                timeline: timeline.name.clone(),
+                token: token.id,
+                value: token.value.clone(),
+                capacity,
+                start_time: token.start_time,
+                end_time: token.end_time,
+                peak: steps.iter().map(|(_, usage)| *usage).max().unwrap_or(0),
+                steps,
+                full,
+            });
+        }
+    }
+    profiles
+}
@@ -0,0 +1,115 @@
+use crate::{
+    flexible::{flexible_solution, ConstraintKind, FlexibleError, END_OF_TIME, ORIGIN},
+    monitor::TokenPoint,
+    problem::{Problem, Solution},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// Slack and critical path of a solution, using the temporal network of its
+// flexible plan. The slack of a token is how far it can be delayed without
+// moving the end of time, and the critical path is the chain of constraints
+// that gives the earliest possible end of time.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlackAnalysis {
+    pub end_of_time: f32,
+    // The earliest end of time with the links and resource orderings of the solution.
+    pub makespan: f32,
+    pub tokens: Vec<TokenSlack>,
+    pub critical_path: Vec<CriticalStep>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenSlack {
+    pub timeline: String,
+    pub token: usize,
+    pub value: String,
+    pub start_slack: f32,
+    pub end_slack: f32,
+    pub critical: bool,
+}
+
+// A timepoint on the critical path, with its earliest time and the constraint
+// that it follows from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CriticalStep {
+    pub timepoint: String,
+    pub tokens: Vec<(usize, TokenPoint)>,
+    pub earliest: f32,
+    pub constraint: ConstraintKind,
+}
+
+pub fn slack_analysis(
+    problem: &Problem,
+    solution: &Solution,
+) -> Result<SlackAnalysis, FlexibleError> {
+    let plan = flexible_solution(problem, solution)?;
+    let _p = hprof::enter("slack_analysis");
+    let windows = plan.stn.windows().unwrap();
+    let causes = plan
+        .stn
+        .earliest_causes()
+        .expect("The solution is not consistent with its temporal network.");
+
+    let mut tokens_at: HashMap<usize, Vec<(usize, TokenPoint)>> = HashMap::new();
+    for token in plan.timelines.iter().flat_map(|tl| tl.tokens.iter()) {
+        let points = [
+            (token.start_timepoint, TokenPoint::Start),
+            (token.end_timepoint, TokenPoint::End),
+        ];
+        for (tp, point) in points.iter() {
+            tokens_at.entry(*tp).or_default().push((token.id, *point));
+        }
+    }
+
+    let mut critical_path = Vec::new();
+    let mut tp = END_OF_TIME;
+    while tp != ORIGIN && critical_path.len() < causes.len() {
+        let (constraint_idx, prev) = match causes[tp] {
+            Some(cause) => cause,
+            None => break,
+        };
+        critical_path.push(CriticalStep {
+            timepoint: plan.stn.timepoints[tp].clone(),
+            tokens: tokens_at.get(&tp).cloned().unwrap_or_default(),
+            earliest: windows[tp].earliest,
+            constraint: plan.stn.constraints[constraint_idx].kind.clone(),
+        });
+        tp = prev;
+    }
+    critical_path.reverse();
+
+    let on_path = critical_path
+        .iter()
+        .flat_map(|step| step.tokens.iter().copied())
+        .collect::<HashSet<_>>();
+    let tokens = solution
+        .timelines
+        .iter()
+        .zip(plan.timelines.iter())
+        .flat_map(|(timeline, flexible_timeline)| {
+            let on_path = &on_path;
+            timeline
+                .tokens
+                .iter()
+                .zip(flexible_timeline.tokens.iter())
+                .map(move |(token, flexible_token)| TokenSlack {
+                    timeline: timeline.name.clone(),
+                    token: token.id,
+                    value: token.value.clone(),
+                    start_slack: (flexible_token.start.latest - token.start_time).max(0.0),
+                    end_slack: (flexible_token.end.latest - token.end_time).max(0.0),
+                    critical: on_path.contains(&(token.id, TokenPoint::Start))
+                        && on_path.contains(&(token.id, TokenPoint::End)),
+                })
+        })
+        .collect();
+
+    Ok(SlackAnalysis {
+        end_of_time: solution.end_of_time,
+        makespan: windows[END_OF_TIME].earliest,
+        tokens,
+        critical_path,
+    })
+}
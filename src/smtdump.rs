@@ -0,0 +1,89 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+// Writes the formula and assumptions of each solver call in the refinement loop
+// to `iteration_NNNN.smt2` files, together with an `index.json` that maps the
+// solver's internal literals back to the problem.
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LiteralInfo {
+    // The prefix of the literal's name, e.g. "exp", "addgoal", "nxstate", "cl", "condactive".
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<String>,
+    // Position of the state in the timeline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    // Index and description of the condition instance in the solver.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition_spec: Option<String>,
+    // The token that a causal link chooses, as "timeline[state]=value".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Index<'a> {
+    iterations: &'a [String],
+    literals: &'a BTreeMap<String, LiteralInfo>,
+}
+
+pub struct SmtDump {
+    dir: PathBuf,
+    iterations: Vec<String>,
+    literals: BTreeMap<String, LiteralInfo>,
+}
+
+impl SmtDump {
+    pub fn new(dir: &Path) -> std::io::Result<SmtDump> {
+        std::fs::create_dir_all(dir)?;
+        Ok(SmtDump {
+            dir: dir.to_path_buf(),
+            iterations: Vec::new(),
+            literals: BTreeMap::new(),
+        })
+    }
+
+    // Only named variables are indexed, i.e. `name` is the backend's name for
+    // a fresh variable, or none for compound terms and constants.
+    pub fn record(&mut self, name: Option<String>, info: LiteralInfo) {
+        if let Some(name) = name {
+            self.literals.insert(name, info);
+        }
+    }
+
+    pub fn write_iteration(
+        &mut self,
+        options: &str,
+        formula: &str,
+        description: &str,
+        assumptions: &[String],
+    ) -> std::io::Result<()> {
+        let filename = format!("iteration_{:04}.smt2", self.iterations.len() + 1);
+        let contents = format!(
+            "; {}\n{}{}(check-sat-assuming ({}))\n",
+            description,
+            options,
+            formula,
+            assumptions.join(" ")
+        );
+        std::fs::write(self.dir.join(&filename), contents)?;
+        self.iterations.push(filename);
+
+        let index = Index {
+            iterations: &self.iterations,
+            literals: &self.literals,
+        };
+        std::fs::write(
+            self.dir.join("index.json"),
+            serde_json::to_string_pretty(&index)?,
+        )
+    }
+}
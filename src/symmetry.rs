@@ -0,0 +1,394 @@
+use crate::problem::{Condition, Problem, Solution, SolutionTimeline, Timeline, TokenTime};
+use std::collections::HashMap;
+
+// Symmetry reduction for interchangeable timelines.
+//
+// Several structurally identical timelines that are only ever referenced through
+// condition alternatives listing all of them in the same way (like the plates in
+// the carbonara domain) can be permuted in any solution. Unit-capacity resources
+// of this kind are merged into one pooled resource with a higher capacity, which
+// is exact because interval scheduling on k identical machines is feasible
+// iff at most k intervals overlap at any time. Other interchangeable timelines
+// are kept, and the solver orders them by the end time of their first state.
+
+#[derive(Debug, Clone)]
+pub struct TimelinePool {
+    // Timeline indices in the original problem.
+    pub timelines: Vec<usize>,
+    // Merged into a single resource timeline (the first in the pool).
+    pub pooled_resource: bool,
+}
+
+// A token using a pooled resource, as a condition group of the token at a
+// position on a timeline, and the pool's timeline it is assigned to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PooledUse {
+    pub timeline: usize,
+    pub token: usize,
+    pub group: usize,
+    pub member: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct SymmetryReduction {
+    pub pools: Vec<TimelinePool>,
+}
+
+impl SymmetryReduction {
+    pub fn analyze(problem: &Problem) -> SymmetryReduction {
+        let mut candidates: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut signatures = Vec::new();
+        for (tl_idx, timeline) in problem.timelines.iter().enumerate() {
+            let signature = timeline_signature(timeline);
+            if !candidates.contains_key(&signature) {
+                signatures.push(signature.clone());
+            }
+            candidates.entry(signature).or_default().push(tl_idx);
+        }
+
+        let mut pools = Vec::new();
+        for signature in signatures {
+            let members = &candidates[&signature];
+            if members.len() < 2 || !referenced_interchangeably(problem, members) {
+                continue;
+            }
+
+            // Alternatives referring to the pool must be exactly one condition for each
+            // member, so that every user can be assigned to one of the members afterwards.
+            let is_member = |c: &Condition| {
+                members
+                    .iter()
+                    .any(|m| problem.timelines[*m].name == c.timeline_ref)
+            };
+            let pooled_resource = members
+                .iter()
+                .all(|t| is_unit_resource(&problem.timelines[*t]))
+                && all_conditions(problem).all(|(_, c)| !is_member(c) || c.amount <= 1)
+                && condition_groups(problem).all(|(_, alternatives)| {
+                    let n = alternatives.iter().filter(|c| is_member(c)).count();
+                    n == 0 || (n == alternatives.len() && n == members.len())
+                });
+
+            // Interchangeable facts-only timelines that are not unit resources
+            // cannot be ordered by their (fact) first states, so there is nothing to gain.
+            if !pooled_resource && problem.timelines[members[0]].token_types.is_empty() {
+                continue;
+            }
+
+            pools.push(TimelinePool {
+                timelines: members.clone(),
+                pooled_resource,
+            });
+        }
+
+        SymmetryReduction { pools }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    // Timeline index in the reduced problem, or None if the timeline was merged
+    // into the first timeline of its pool.
+    fn reduced_index(&self, tl_idx: usize) -> Option<usize> {
+        let merged = |t: usize| {
+            self.pools
+                .iter()
+                .any(|p| p.pooled_resource && p.timelines[1..].contains(&t))
+        };
+        (!merged(tl_idx)).then(|| (0..tl_idx).filter(|t| !merged(*t)).count())
+    }
+
+    pub fn reduced_problem(&self, problem: &Problem) -> Problem {
+        let mut problem = problem.clone();
+        for pool in self.pools.iter().filter(|p| p.pooled_resource) {
+            let first_name = problem.timelines[pool.timelines[0]].name.clone();
+            let merged_names = pool.timelines[1..]
+                .iter()
+                .map(|t| problem.timelines[*t].name.clone())
+                .collect::<Vec<_>>();
+
+            problem.timelines[pool.timelines[0]].static_tokens[0].capacity =
+                pool.timelines.len() as u32;
+
+            for timeline in problem.timelines.iter_mut() {
+                for alternatives in condition_groups_mut(timeline) {
+                    alternatives.retain(|c| !merged_names.contains(&c.timeline_ref));
+                }
+            }
+
+            println!(
+                "Symmetry reduction: pooled {} interchangeable resources into {}",
+                pool.timelines.len(),
+                first_name
+            );
+        }
+
+        let mut tl_idx = 0;
+        problem.timelines.retain(|_| {
+            tl_idx += 1;
+            self.reduced_index(tl_idx - 1).is_some()
+        });
+        problem
+    }
+
+    // Pooled resource timelines, as indices in the reduced problem.
+    pub fn pooled_timelines(&self) -> Vec<usize> {
+        self.pools
+            .iter()
+            .filter(|p| p.pooled_resource)
+            .map(|p| self.reduced_index(p.timelines[0]).unwrap())
+            .collect()
+    }
+
+    // Groups of timelines that need symmetry-breaking ordering constraints,
+    // as indices in the reduced problem.
+    pub fn interchangeable_timelines(&self) -> Vec<Vec<usize>> {
+        self.pools
+            .iter()
+            .filter(|p| !p.pooled_resource)
+            .map(|p| {
+                p.timelines
+                    .iter()
+                    .map(|t| self.reduced_index(*t).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Map a solution of the reduced problem back to the original timelines.
+    pub fn expand_solution(&self, problem: &Problem, solution: Solution) -> Solution {
+        let reduced_timelines = solution.timelines;
+        let merged_names = self
+            .pools
+            .iter()
+            .filter(|p| p.pooled_resource)
+            .flat_map(|p| p.timelines[1..].iter())
+            .map(|t| problem.timelines[*t].name.as_str())
+            .collect::<Vec<_>>();
+
+        let mut next_id = 0;
+        let mut new_ids = HashMap::new();
+        let mut timelines = problem
+            .timelines
+            .iter()
+            .enumerate()
+            .map(|(tl_idx, timeline)| {
+                let reduced_idx = self.reduced_index(tl_idx);
+                let source = reduced_idx.unwrap_or_else(|| {
+                    let pool = self
+                        .pools
+                        .iter()
+                        .find(|p| p.timelines.contains(&tl_idx))
+                        .unwrap();
+                    self.reduced_index(pool.timelines[0]).unwrap()
+                });
+                let mut tokens = reduced_timelines[source].tokens.clone();
+                for token in tokens.iter_mut() {
+                    // Merged timelines get copies of their pool's tokens.
+                    if reduced_idx.is_some() {
+                        new_ids.insert(token.id, next_id);
+                    }
+                    token.id = next_id;
+                    next_id += 1;
+                }
+                SolutionTimeline {
+                    name: timeline.name.clone(),
+                    tokens,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Alternatives referring to merged timelines were removed from the reduced problem.
+        for (timeline, solution_timeline) in problem.timelines.iter().zip(timelines.iter_mut()) {
+            for (pos, token) in solution_timeline.tokens.iter_mut().enumerate() {
+                let groups = timeline
+                    .token_spec(pos, &token.value)
+                    .map(|s| s.conditions)
+                    .unwrap_or(&[]);
+                for satisfaction in token.conditions.iter_mut() {
+                    satisfaction.target = new_ids[&satisfaction.target];
+                    satisfaction.alternative = groups[satisfaction.group]
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, c)| !merged_names.contains(&c.timeline_ref.as_str()))
+                        .nth(satisfaction.alternative)
+                        .unwrap()
+                        .0;
+                }
+            }
+        }
+
+        let mut solution = Solution {
+            timelines,
+            end_of_time: solution.end_of_time,
+        };
+
+        // Users of a pooled resource refer to the timeline they are assigned to.
+        for u in self.pooled_uses(problem, &solution) {
+            let member = &solution.timelines[u.member];
+            let (name, target) = (member.name.clone(), member.tokens[0].id);
+            let token = &mut solution.timelines[u.timeline].tokens[u.token];
+            let groups = problem.timelines[u.timeline]
+                .token_spec(u.token, &token.value)
+                .map(|s| s.conditions)
+                .unwrap_or(&[]);
+            if let Some(satisfaction) = token.conditions.iter_mut().find(|s| s.group == u.group) {
+                satisfaction.target = target;
+                satisfaction.alternative = groups[u.group]
+                    .iter()
+                    .position(|c| c.timeline_ref == name)
+                    .unwrap();
+            }
+        }
+        solution
+    }
+
+    // Assign the users of each pooled resource in an expanded solution to the
+    // pool's timelines, which is possible because at most as many users as there
+    // are timelines overlap at any time.
+    pub fn pooled_uses(&self, problem: &Problem, solution: &Solution) -> Vec<PooledUse> {
+        let mut uses = Vec::new();
+        for pool in self.pools.iter().filter(|p| p.pooled_resource) {
+            let names = pool
+                .timelines
+                .iter()
+                .map(|t| problem.timelines[*t].name.as_str())
+                .collect::<Vec<_>>();
+            let mut users = Vec::new();
+            for (tl_idx, timeline) in solution.timelines.iter().enumerate() {
+                for (token_idx, token) in timeline.tokens.iter().enumerate() {
+                    let groups = problem.timelines[tl_idx]
+                        .token_spec(token_idx, &token.value)
+                        .map(|s| s.conditions)
+                        .unwrap_or(&[]);
+                    for (group, alternatives) in groups.iter().enumerate() {
+                        if alternatives
+                            .iter()
+                            .any(|c| c.amount > 0 && names.contains(&c.timeline_ref.as_str()))
+                        {
+                            users.push((
+                                token.start_time,
+                                token.end_time,
+                                tl_idx,
+                                token_idx,
+                                group,
+                            ));
+                        }
+                    }
+                }
+            }
+            users.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+            let mut free_from = vec![f32::NEG_INFINITY; pool.timelines.len()];
+            for (start_time, end_time, timeline, token, group) in users {
+                let member = (0..free_from.len())
+                    .min_by(|a, b| free_from[*a].partial_cmp(&free_from[*b]).unwrap())
+                    .unwrap();
+                debug_assert!(
+                    free_from[member] <= start_time,
+                    "pooled resource used above its capacity"
+                );
+                free_from[member] = end_time;
+                uses.push(PooledUse {
+                    timeline,
+                    token,
+                    group,
+                    member: pool.timelines[member],
+                });
+            }
+        }
+        uses
+    }
+}
+
+fn timeline_signature(timeline: &Timeline) -> String {
+    let mut normalized = timeline.clone();
+    normalized.name = String::new();
+    for alternatives in condition_groups_mut(&mut normalized) {
+        for cond in alternatives.iter_mut() {
+            if cond.timeline_ref == timeline.name {
+                cond.timeline_ref = String::new();
+            }
+        }
+    }
+    serde_json::to_string(&normalized).unwrap()
+}
+
+fn condition_signature(cond: &Condition) -> String {
+    let mut normalized = cond.clone();
+    normalized.timeline_ref = String::new();
+    serde_json::to_string(&normalized).unwrap()
+}
+
+fn condition_groups_mut(timeline: &mut Timeline) -> impl Iterator<Item = &mut Vec<Condition>> {
+    timeline
+        .token_types
+        .iter_mut()
+        .flat_map(|tt| tt.conditions.iter_mut())
+        .chain(
+            timeline
+                .static_tokens
+                .iter_mut()
+                .flat_map(|t| t.conditions.iter_mut()),
+        )
+}
+
+// All condition alternative groups, with the index of the timeline they belong to.
+fn condition_groups(problem: &Problem) -> impl Iterator<Item = (usize, &Vec<Condition>)> {
+    problem
+        .timelines
+        .iter()
+        .enumerate()
+        .flat_map(|(tl_idx, tl)| {
+            tl.token_types
+                .iter()
+                .flat_map(|tt| tt.conditions.iter())
+                .chain(tl.static_tokens.iter().flat_map(|t| t.conditions.iter()))
+                .map(move |alternatives| (tl_idx, alternatives))
+        })
+}
+
+fn all_conditions(problem: &Problem) -> impl Iterator<Item = (usize, &Condition)> {
+    condition_groups(problem)
+        .flat_map(|(tl_idx, alternatives)| alternatives.iter().map(move |c| (tl_idx, c)))
+}
+
+// Every condition referring to one of the members must come in an alternative
+// group that lists the same condition for each of the members exactly once.
+fn referenced_interchangeably(problem: &Problem, members: &[usize]) -> bool {
+    let member_idx = |name: &str| {
+        members
+            .iter()
+            .position(|m| problem.timelines[*m].name == name)
+    };
+
+    condition_groups(problem).all(|(owner, alternatives)| {
+        let mut orbits: HashMap<String, Vec<usize>> = HashMap::new();
+        for cond in alternatives.iter() {
+            if problem.timelines[owner].name == cond.timeline_ref {
+                // References to the timeline itself are part of the timeline signature.
+                continue;
+            }
+            if let Some(idx) = member_idx(&cond.timeline_ref) {
+                orbits
+                    .entry(condition_signature(cond))
+                    .or_default()
+                    .push(idx);
+            }
+        }
+
+        orbits.values_mut().all(|orbit| {
+            orbit.sort_unstable();
+            orbit.iter().copied().eq(0..members.len())
+        })
+    })
+}
+
+fn is_unit_resource(timeline: &Timeline) -> bool {
+    timeline.token_types.is_empty()
+        && timeline.static_tokens.len() == 1
+        && matches!(timeline.static_tokens[0].const_time, TokenTime::Fact(_, _))
+        && timeline.static_tokens[0].capacity == 1
+        && timeline.static_tokens[0].conditions.is_empty()
+}
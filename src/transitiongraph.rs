@@ -0,0 +1,280 @@
+use crate::compiled::{CompiledProblem, TimelineId, ValueId};
+use std::collections::HashMap;
+
+// Transition automaton for a single timeline.
+//
+// The allowed transitions are given by the conditions on the immediately previous
+// and next tokens on the same timeline (Allen relations MetBy and Meets). A node
+// is a set of values that a state can take, and the successor of a node is the
+// set of values that the next state can take. The automaton is built once per
+// timeline, starting from the initial state and from each single value, and
+// distances from each node to each value are precomputed.
+
+#[derive(Debug)]
+pub struct TransitionGraph {
+    pub timeline: TimelineId,
+    // Sorted value sets.
+    pub nodes: Vec<Vec<ValueId>>,
+    nodes_by_values: HashMap<Vec<ValueId>, usize>,
+    // The values that the first state on the timeline can take.
+    pub initial: usize,
+    pub next: Vec<usize>,
+    // Number of states needed to reach a value from a node, indexed by node and value.
+    pub distances: Vec<Vec<Option<usize>>>,
+
+    // Indexed by value.
+    has_token_type: Vec<bool>,
+    // Condition groups where all alternatives are transitions from a previous value.
+    required_previous: Vec<Vec<Vec<ValueId>>>,
+    // Values allowed to follow a value, if restricted by transitions to a next value.
+    allowed_next: Vec<Option<Vec<ValueId>>>,
+}
+
+impl TransitionGraph {
+    pub fn build(problem: &CompiledProblem, timeline_idx: TimelineId) -> TransitionGraph {
+        let timeline = &problem.timelines[timeline_idx];
+        let required_previous = timeline
+            .token_types
+            .iter()
+            .map(|tt| {
+                tt.iter()
+                    .flat_map(|tt| tt.conditions.iter())
+                    .filter_map(|cs| {
+                        cs.iter()
+                            .map(|c| {
+                                problem.conditions[*c].is_timeline_transition_from(timeline_idx)
+                            })
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+
+        let allowed_next = timeline
+            .token_types
+            .iter()
+            .map(|tt| {
+                tt.as_ref().and_then(|tt| {
+                    tt.conditions
+                        .iter()
+                        .map(|cs| {
+                            cs.iter()
+                                .map(|c| {
+                                    problem.conditions[*c].is_timeline_transition_to(timeline_idx)
+                                })
+                                .collect::<Option<Vec<_>>>()
+                        })
+                        .reduce(|a, b| match (a, b) {
+                            (Some(a), Some(b)) => {
+                                Some(a.into_iter().filter(|v| b.contains(v)).collect())
+                            }
+                            _ => None,
+                        })
+                        .flatten()
+                        .map(|mut set| {
+                            set.sort_unstable();
+                            set.dedup();
+                            set
+                        })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        TransitionGraph::with_restrictions(problem, timeline_idx, required_previous, allowed_next)
+    }
+
+    // Any value with a token type may follow any other value, so that the
+    // transition conditions are left to the encoding.
+    pub fn unrestricted(problem: &CompiledProblem, timeline_idx: TimelineId) -> TransitionGraph {
+        let n_values = problem.timelines[timeline_idx].values.len();
+        TransitionGraph::with_restrictions(
+            problem,
+            timeline_idx,
+            vec![Vec::new(); n_values],
+            vec![None; n_values],
+        )
+    }
+
+    fn with_restrictions(
+        problem: &CompiledProblem,
+        timeline_idx: TimelineId,
+        required_previous: Vec<Vec<Vec<ValueId>>>,
+        allowed_next: Vec<Option<Vec<ValueId>>>,
+    ) -> TransitionGraph {
+        let timeline = &problem.timelines[timeline_idx];
+        let n_values = timeline.values.len();
+        let has_token_type = timeline
+            .token_types
+            .iter()
+            .map(|tt| tt.is_some())
+            .collect::<Vec<_>>();
+
+        let mut graph = TransitionGraph {
+            timeline: timeline_idx,
+            nodes: Vec::new(),
+            nodes_by_values: HashMap::new(),
+            initial: 0,
+            next: Vec::new(),
+            distances: Vec::new(),
+            has_token_type,
+            required_previous,
+            allowed_next,
+        };
+
+        let initial_values = graph.successors(None);
+        graph.initial = graph.add_node(initial_values);
+        for value in 0..n_values {
+            graph.add_node(vec![value]);
+        }
+
+        let mut node_idx = 0;
+        while node_idx < graph.nodes.len() {
+            let next_values = graph.successors(Some(&graph.nodes[node_idx]));
+            let next = graph.add_node(next_values);
+            graph.next.push(next);
+            node_idx += 1;
+        }
+
+        graph.distances = graph
+            .nodes
+            .iter()
+            .map(|values| graph.search_distances(values))
+            .collect();
+
+        graph
+    }
+
+    fn add_node(&mut self, values: Vec<ValueId>) -> usize {
+        if let Some(idx) = self.nodes_by_values.get(&values) {
+            return *idx;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(values.clone());
+        self.nodes_by_values.insert(values, idx);
+        idx
+    }
+
+    pub fn node(&self, values: &[ValueId]) -> Option<usize> {
+        let mut values = values.to_vec();
+        values.sort_unstable();
+        values.dedup();
+        self.nodes_by_values.get(&values).copied()
+    }
+
+    // The values that can follow a state with the given values, or the values of
+    // the first state on the timeline if there are no previous values.
+    pub fn next_values(&self, prev_values: Option<&[ValueId]>) -> Vec<ValueId> {
+        match prev_values {
+            None => self.nodes[self.initial].clone(),
+            Some(prev_values) => match self.node(prev_values) {
+                Some(node) => self.nodes[self.next[node]].clone(),
+                None => self.successors(Some(prev_values)),
+            },
+        }
+    }
+
+    // The number of states needed after a state with the given values
+    // to reach a state that can take the goal value.
+    pub fn distance(&self, start_values: &[ValueId], goal_value: ValueId) -> Option<usize> {
+        match self.node(start_values) {
+            Some(node) => self.distances[node][goal_value],
+            None => self.search_distances(start_values)[goal_value],
+        }
+    }
+
+    fn successors(&self, prev_values: Option<&[ValueId]>) -> Vec<ValueId> {
+        let n_values = self.has_token_type.len();
+        let prev = prev_values.unwrap_or(&[]);
+        let is_prev = {
+            let mut is_prev = vec![false; n_values];
+            for v in prev.iter() {
+                is_prev[*v] = true;
+            }
+            is_prev
+        };
+
+        // If any of the previous values does not restrict its next value,
+        // any value may follow.
+        let allowed = prev_values.and_then(|prev_values| {
+            let mut allowed = vec![false; n_values];
+            for pv in prev_values.iter() {
+                for v in self.allowed_next[*pv].as_ref()?.iter() {
+                    allowed[*v] = true;
+                }
+            }
+            Some(allowed)
+        });
+
+        let single_prev = (prev.len() == 1).then(|| prev[0]);
+
+        (0..n_values)
+            .filter(|v| {
+                self.has_token_type[*v]
+                    && self.required_previous[*v]
+                        .iter()
+                        .all(|group| group.iter().any(|pv| is_prev[*pv]))
+                    && allowed.as_ref().map(|a| a[*v]).unwrap_or(true)
+                    // Cannot transition to the same value.
+                    && single_prev != Some(*v)
+            })
+            .collect()
+    }
+
+    // Breadth-first search over the values that have not been seen before.
+    fn search_distances(&self, start_values: &[ValueId]) -> Vec<Option<usize>> {
+        let n_values = self.has_token_type.len();
+        let mut distances = vec![None; n_values];
+        let mut visited = vec![false; n_values];
+        let mut current_values = start_values.to_vec();
+        current_values.sort_unstable();
+        current_values.dedup();
+
+        let mut steps = 1;
+        loop {
+            let mut next_values = Vec::new();
+            for next in self.successors(Some(&current_values)) {
+                if distances[next].is_none() {
+                    distances[next] = Some(steps);
+                }
+                if !visited[next] {
+                    visited[next] = true;
+                    next_values.push(next);
+                }
+            }
+
+            if next_values.is_empty() {
+                return distances;
+            }
+
+            current_values = next_values;
+            steps += 1;
+        }
+    }
+
+    // Values that can occur anywhere on the timeline.
+    pub fn reachable_values(&self) -> Vec<ValueId> {
+        let mut reachable = self.nodes[self.initial].clone();
+        for (value, d) in self.distances[self.initial].iter().enumerate() {
+            if d.is_some() && !reachable.contains(&value) {
+                reachable.push(value);
+            }
+        }
+        reachable.sort_unstable();
+        reachable
+    }
+}
+
+pub fn build_transition_graphs(problem: &CompiledProblem) -> Vec<TransitionGraph> {
+    let _p = hprof::enter("transition graphs");
+    (0..problem.timelines.len())
+        .map(|tl_idx| {
+            let graph = TransitionGraph::build(problem, tl_idx);
+            println!(
+                "Transition graph for {}: {} value sets",
+                problem.timelines[tl_idx].name,
+                graph.nodes.len()
+            );
+            graph
+        })
+        .collect()
+}
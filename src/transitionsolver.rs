@@ -1,80 +1,462 @@
+#[cfg(feature = "z3")]
+use crate::backend::Z3Backend;
+use crate::backend::{run_interruptible, Backend, CheckResult, SmtLibProcess};
+#[cfg(feature = "native")]
+use crate::native::NativeBackend;
 use crate::{
+    compiled::{CompiledCondition, CompiledProblem, TimelineId, ValueId},
+    cores::{CoreBudget, CoreMinimization},
+    diagnosis::{ConstraintKind, DiagnosisConstraint},
     problem::{
-        self, Problem, Solution, SolutionTimeline, SolutionToken, TemporalRelationship, TokenTime,
-        TokenType,
+        ConditionSatisfaction, Problem, Solution, SolutionTimeline, SolutionToken,
+        TemporalRelationship, TokenTime,
     },
+    smtdump::{LiteralInfo, SmtDump},
     // transitionrelation::{transitionrelation, TransitionRelation},
-    z3real_value,
+    symmetry::SymmetryReduction,
+    validate::validate_solution,
     SolverError,
 };
-use std::collections::{HashMap, HashSet};
-use z3::ast::{Ast, Bool, Real};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{Receiver, Sender},
+    Arc,
+};
 
+#[derive(Debug, Clone)]
 pub struct SolverSettings {
     pub minimize_cores: bool,
+    pub core_minimization: CoreMinimization,
+    pub core_budget: CoreBudget,
+    pub symmetry_reduction: bool,
+    pub resource_encoding: ResourceEncoding,
+    // Resource encodings for specific resources, by timeline name and value.
+    pub resource_encoding_overrides: HashMap<(String, String), ResourceEncoding>,
+    pub auto_config: bool,
+    pub arith_solver: u32,
+    pub random_seed: Option<u32>,
+    // Fix the Z3 random seed (to 0 unless `random_seed` is given), so that
+    // the same problem and settings always give the same solution.
+    pub deterministic: bool,
+    // Additional Z3 parameters, applied after the ones above.
+    pub z3_params: Vec<(String, String)>,
+    // Set from another thread to stop the solver with `SolverError::Cancelled`.
+    pub cancel: Option<Arc<AtomicBool>>,
+    // A solution to a similar problem. Timelines are matched by name and
+    // pre-expanded to the hint's number of tokens, and the hint's values and
+    // times are preferred as long as they do not conflict with the problem.
+    pub hint: Option<Arc<Solution>>,
+    // Write the formula and assumptions of each solver call to this directory.
+    pub smt_dump_dir: Option<PathBuf>,
+    // Fix the end of time, e.g. to the one of the other parts of a decomposed problem.
+    pub end_of_time: Option<f32>,
+    pub backend: SolverBackend,
+}
+
+// Z3 and the native solver are cargo features, and Z3 is the default when
+// both are built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolverBackend {
+    Z3,
+    // The difference logic solver in `native`, which needs no C++ libraries,
+    // but does not support the task interval resource encoding.
+    Native,
+    // An SMT-LIB2 solver that reads commands from stdin, given as the program
+    // and its arguments. It must support `check-sat-assuming` and
+    // `get-unsat-assumptions`.
+    SmtLibProcess { command: Vec<String> },
+}
+
+impl Default for SolverBackend {
+    fn default() -> Self {
+        if cfg!(feature = "z3") {
+            SolverBackend::Z3
+        } else {
+            SolverBackend::Native
+        }
+    }
 }
 
 impl Default for SolverSettings {
     fn default() -> Self {
         SolverSettings {
             minimize_cores: false,
+            core_minimization: CoreMinimization::LinearDeletion,
+            core_budget: CoreBudget::default(),
+            symmetry_reduction: true,
+            resource_encoding: ResourceEncoding::Pairwise,
+            resource_encoding_overrides: HashMap::new(),
+            auto_config: false,
+            arith_solver: 6,
+            random_seed: None,
+            deterministic: false,
+            z3_params: Vec::new(),
+            cancel: None,
+            hint: None,
+            smt_dump_dir: None,
+            end_of_time: None,
+            backend: SolverBackend::default(),
         }
     }
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SolverStatistics {
+    pub solver_calls: usize,
+    pub core_sizes: Vec<usize>,
+    // Size of the problem formulation in the last solver call.
+    pub states: usize,
+    pub tokens: usize,
+    pub conditions: usize,
+    pub exclusions: usize,
+    pub pbs: usize,
+    pub hints: usize,
+    pub hints_dropped: usize,
+    pub iterations: Vec<IterationStatistics>,
+}
+
+// The states on each timeline in a solver call, and for unsatisfiable calls, the
+// size of the core and the number of core literals from each timeline.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IterationStatistics {
+    pub states: Vec<usize>,
+    pub core_size: Option<usize>,
+    pub core_timelines: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResourceEncoding {
+    // Pairwise exclusion for unit-capacity resources, pseudo-boolean overlap
+    // constraints for the others.
+    #[default]
+    Pairwise,
+    // Pseudo-boolean overlap constraints for all resources.
+    PseudoBoolean,
+    // Pseudo-boolean constraints on the usage at each user's start time, and energy
+    // constraints on the intervals between the start and end times of pairs of users.
+    TaskInterval,
+    // Pairwise exclusions (for unit-capacity resources) or constraints on the usage
+    // at a user's start time are only added where a candidate solution overloads the resource.
+    Lazy,
+}
+
+impl std::str::FromStr for ResourceEncoding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pairwise" => Ok(ResourceEncoding::Pairwise),
+            "pb" => Ok(ResourceEncoding::PseudoBoolean),
+            "taskinterval" => Ok(ResourceEncoding::TaskInterval),
+            "lazy" => Ok(ResourceEncoding::Lazy),
+            _ => Err(format!("Unknown resource encoding \"{}\"", s)),
+        }
+    }
+}
+
+impl SolverSettings {
+    pub fn resource_encoding_for(&self, timeline: &str, value: &str) -> ResourceEncoding {
+        self.resource_encoding_overrides
+            .get(&(timeline.to_string(), value.to_string()))
+            .copied()
+            .unwrap_or(self.resource_encoding)
+    }
+}
+
+fn is_cancelled(settings: &SolverSettings) -> bool {
+    settings
+        .cancel
+        .as_ref()
+        .map(|c| c.load(Ordering::SeqCst))
+        .unwrap_or(false)
+}
+
 // A state is a choice between several possible tokens
 // in the sequence of values that make up a timeline.
 #[derive(Debug)]
-struct State<'z> {
-    start_time: Real<'z>,
-    end_time: Real<'z>,
+struct State<B: Backend> {
+    start_time: B::Real,
+    end_time: B::Real,
     timeline: usize,
     tokens: Vec<usize>,
     state_seq: usize,
-    active: Bool<'z>,
-    activate_next: Bool<'z>,
+    active: B::Bool,
+    activate_next: B::Bool,
     can_expand: bool,
 }
 
 #[derive(Debug)]
-struct Token<'a, 'z> {
-    active: Option<Bool<'z>>,
+struct Token<B: Backend> {
+    active: Option<B::Bool>,
     state: usize,
-    value: &'a str,
+    value: ValueId,
     fact: bool,
     conditions: Vec<usize>,
 }
 
 #[derive(Debug)]
-struct Condition<'a, 'z3> {
-    active: Option<Bool<'z3>>,
+struct Condition<'a, B: Backend> {
+    active: Option<B::Bool>,
     token_idx: usize,
-    cond_spec: &'a problem::Condition,
+    cond_spec: &'a CompiledCondition,
+    // Indices of the condition group and the alternative in the token's conditions.
+    group: usize,
+    alternative: usize,
     token_queue: usize,
-    alternatives_extension: Option<Bool<'z3>>,
+    alternatives_extension: Option<B::Bool>,
+    // Target tokens, with the literal that chooses each of them (or none if the link is constant).
+    links: Vec<(Option<B::Bool>, usize)>,
 }
 
-struct Timeline<'z> {
+struct Timeline<B: Backend> {
     states: Vec<usize>,
-    goal_state_extension: Option<Bool<'z>>,
+    goal_state_extension: Option<B::Bool>,
     facts_only: bool,
 }
 
 pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, SolverError> {
+    solve_with_statistics(problem, settings).0
+}
+
+pub fn solve_with_statistics(
+    problem: &Problem,
+    settings: &SolverSettings,
+) -> (Result<Solution, SolverError>, SolverStatistics) {
+    let mut statistics = SolverStatistics::default();
+    let result = solve_problem(problem, settings, &mut statistics);
+
+    // Debug builds check each solution independently of the encoding.
+    if cfg!(debug_assertions) {
+        if let Ok(solution) = result.as_ref() {
+            let violations = validate_solution(problem, solution);
+            if !violations.is_empty() {
+                println!("Invalid solution: {:?}", violations);
+                return (Err(SolverError::InvalidSolution(violations)), statistics);
+            }
+        }
+    }
+    (result, statistics)
+}
+
+fn solve_problem(
+    problem: &Problem,
+    settings: &SolverSettings,
+    statistics: &mut SolverStatistics,
+) -> Result<Solution, SolverError> {
+    if settings.symmetry_reduction {
+        let symmetry = SymmetryReduction::analyze(problem);
+        if !symmetry.is_empty() {
+            let reduced = symmetry.reduced_problem(problem);
+            let compiled = CompiledProblem::compile(&reduced);
+            return solve_compiled(&compiled, settings, &symmetry, statistics, None)
+                .map(|solution| symmetry.expand_solution(problem, solution));
+        }
+    }
+
+    let compiled = CompiledProblem::compile(problem);
+    solve_compiled(
+        &compiled,
+        settings,
+        &SymmetryReduction::default(),
+        statistics,
+        None,
+    )
+}
+
+// A diagnosis constraint in compiled ids: the goal value of a timeline, a
+// condition group of a token type (by value) or of a fact (by fact index), or
+// the capacity of a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Selector {
+    Goal(TimelineId, ValueId),
+    Conditions(TimelineId, ValueId, usize),
+    FactConditions(TimelineId, usize, usize),
+    Capacity(TimelineId, ValueId),
+}
+
+struct SubsetChecks {
+    selectors: Vec<Selector>,
+    subsets: Receiver<Vec<usize>>,
+    results: Sender<Option<bool>>,
+}
+
+impl SubsetChecks {
+    // Answer the current subset and wait for the next one.
+    fn next(&self, result: Option<bool>) -> Result<Vec<usize>, SolverError> {
+        let _ = self.results.send(result);
+        self.subsets.recv().map_err(|_| SolverError::Cancelled)
+    }
+}
+
+// Check subsets of the diagnosis constraints with a single encoding, where each
+// constraint only holds when a selector literal for it is assumed. Each subset
+// that is received is answered with whether it is satisfiable, or None if the
+// solver could not decide. Returns when no more subsets are sent.
+pub fn check_subsets(
+    problem: &Problem,
+    settings: &SolverSettings,
+    constraints: &[DiagnosisConstraint],
+    subsets: Receiver<Vec<usize>>,
+    results: Sender<Option<bool>>,
+) -> Result<(), SolverError> {
+    let _p = hprof::enter("check_subsets");
+    let mut compiled = CompiledProblem::compile(problem);
+    compiled.unrestrict_transitions();
+    let selectors = constraints
+        .iter()
+        .map(|c| {
+            let timeline = &problem.timelines[c.timeline];
+            let value = |name: &str| compiled.timelines[c.timeline].values_by_name[name];
+            match &c.kind {
+                ConstraintKind::Goal { static_token } => Selector::Goal(
+                    c.timeline,
+                    value(&timeline.static_tokens[*static_token].value),
+                ),
+                ConstraintKind::TokenTypeConditions { token_type, group } => Selector::Conditions(
+                    c.timeline,
+                    value(&timeline.token_types[*token_type].value),
+                    *group,
+                ),
+                ConstraintKind::StaticTokenConditions {
+                    static_token,
+                    group,
+                } => {
+                    let fact = timeline.static_tokens[..*static_token]
+                        .iter()
+                        .filter(|t| matches!(t.const_time, TokenTime::Fact(_, _)))
+                        .count();
+                    Selector::FactConditions(c.timeline, fact, *group)
+                }
+                ConstraintKind::Capacity { value: name } => {
+                    Selector::Capacity(c.timeline, value(name))
+                }
+            }
+        })
+        .collect();
+
+    let checks = SubsetChecks {
+        selectors,
+        subsets,
+        results,
+    };
+    let settings = SolverSettings {
+        hint: None,
+        ..settings.clone()
+    };
+    let result = solve_compiled(
+        &compiled,
+        &settings,
+        &SymmetryReduction::default(),
+        &mut SolverStatistics::default(),
+        Some(&checks),
+    );
+    match result {
+        // The solver stops with `Cancelled` when there are no more subsets.
+        Ok(_) | Err(SolverError::Cancelled) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+fn solve_compiled(
+    problem: &CompiledProblem,
+    settings: &SolverSettings,
+    symmetry: &SymmetryReduction,
+    statistics: &mut SolverStatistics,
+    subsets: Option<&SubsetChecks>,
+) -> Result<Solution, SolverError> {
+    // The solver is interrupted from a watcher thread when the solve is cancelled.
+    match &settings.backend {
+        #[cfg(feature = "z3")]
+        SolverBackend::Z3 => {
+            let z3_config = z3::Config::new();
+            let ctx = z3::Context::new(&z3_config);
+            let handle = ctx.handle();
+            let backend = Z3Backend::new(&ctx, settings);
+            run_interruptible(
+                settings,
+                || handle.interrupt(),
+                || solve_with_backend(problem, settings, symmetry, statistics, subsets, &backend),
+            )
+        }
+        SolverBackend::SmtLibProcess { command } => {
+            let backend = SmtLibProcess::new(command).map_err(|e| {
+                SolverError::Backend(format!("Could not start {}: {}", command.join(" "), e))
+            })?;
+            run_interruptible(settings, backend.interrupter(), || {
+                solve_with_backend(problem, settings, symmetry, statistics, subsets, &backend)
+            })
+        }
+        #[cfg(feature = "native")]
+        SolverBackend::Native => {
+            // The task interval encoding multiplies times, which is not difference logic.
+            let task_interval = problem.conditions.iter().any(|c| {
+                let timeline = &problem.timelines[c.timeline];
+                c.amount > 0
+                    && settings.resource_encoding_for(&timeline.name, &timeline.values[c.value])
+                        == ResourceEncoding::TaskInterval
+            });
+            if task_interval {
+                return Err(SolverError::Backend(
+                    "TaskInterval is not supported by the native backend".to_string(),
+                ));
+            }
+            let backend = NativeBackend::new();
+            run_interruptible(settings, backend.interrupter(), || {
+                solve_with_backend(problem, settings, symmetry, statistics, subsets, &backend)
+            })
+        }
+        #[allow(unreachable_patterns)]
+        backend => Err(SolverError::Backend(format!(
+            "The {:?} backend is not available in this build",
+            backend
+        ))),
+    }
+}
+
+fn solve_with_backend<B: Backend>(
+    problem: &CompiledProblem,
+    settings: &SolverSettings,
+    symmetry: &SymmetryReduction,
+    statistics: &mut SolverStatistics,
+    subsets: Option<&SubsetChecks>,
+    backend: &B,
+) -> Result<Solution, SolverError> {
     let _p = hprof::enter("solve");
     let p1 = hprof::enter("prepare");
     // println!("Starting transition-and-pocl solver.");
-    let z3_config = z3::Config::new();
-    let ctx = z3::Context::new(&z3_config);
-    let solver = z3::Solver::new(&ctx);
-
-    let mut params = z3::Params::new(&ctx);
-    params.set_bool("auto_config", false);
-    params.set_u32("smt.arith.solver", 6);
-    // println!("Z3 params:\n{}", params.to_string());
-    solver.set_params(&params);
+    println!("{}", backend.describe());
+    let mut dump = settings
+        .smt_dump_dir
+        .as_ref()
+        .map(|dir| SmtDump::new(dir))
+        .transpose()
+        .map_err(|e| SolverError::Io(e.to_string()))?;
+
+    let end_of_time = backend.fresh_real("endoftime");
+    if let Some(t) = settings.end_of_time {
+        // A float is a binary fraction, so this is exact.
+        let mut den = 1;
+        while (t * den as f32).fract() != 0.0 && den < 1 << 30 {
+            den *= 2;
+        }
+        let fixed = backend.real_const((t * den as f32) as i64, den);
+        backend.assert(&backend.eq(&end_of_time, &fixed));
+    }
 
-    let end_of_time = Real::fresh_const(&ctx, "endoftime");
+    // Selector literals of the diagnosis constraints, and the subset to check.
+    let mut selected = HashMap::new();
+    let mut selector_lits = Vec::new();
+    let mut subset = Vec::new();
+    if let Some(checks) = subsets {
+        for selector in checks.selectors.iter() {
+            let lit = selected
+                .entry(*selector)
+                .or_insert_with(|| backend.fresh_bool("select"));
+            selector_lits.push(lit.clone());
+        }
+        subset = checks.subsets.recv().map_err(|_| SolverError::Cancelled)?;
+    }
 
     let mut timelines = problem
         .timelines
@@ -82,7 +464,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
         .map(|tl| Timeline {
             states: Vec::new(),
             goal_state_extension: None,
-            facts_only: tl.token_types.is_empty(),
+            facts_only: tl.facts_only,
         })
         .collect::<Vec<_>>();
 
@@ -96,24 +478,28 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
     let mut states_queue = 0;
     let mut tokens = Vec::new();
     let mut tokens_queue = 0;
-    let mut conds: Vec<Condition> = Vec::new();
+    let mut conds: Vec<Condition<B>> = Vec::new();
     let mut conds_queue = 0;
 
-    let mut goal_lits: HashMap<(&str, isize), Bool> = HashMap::new();
+    // Tokens by (timeline, value) and conditions by the (timeline, value) they refer to.
+    let mut tokens_by_value: Vec<Vec<Vec<usize>>> = problem
+        .timelines
+        .iter()
+        .map(|tl| vec![Vec::new(); tl.values.len()])
+        .collect();
+    let mut indexed_tokens = 0;
+    let mut conds_by_target: Vec<Vec<Vec<usize>>> = tokens_by_value.clone();
+
+    let mut goal_lits: HashMap<(TimelineId, isize), B::Bool> = HashMap::new();
 
     let mut expand_links_queue: Vec<(bool, usize)> = Vec::new();
 
-    let mut expand_links_lits: HashMap<Bool, usize> = HashMap::new();
-    let mut expand_goal_state_lits: HashMap<Bool, (usize, &str)> = HashMap::new();
+    let mut expand_links_lits: HashMap<B::Bool, usize> = HashMap::new();
+    let mut expand_goal_state_lits: HashMap<B::Bool, (TimelineId, ValueId)> = HashMap::new();
 
-    let mut resource_constraints: HashMap<usize, ResourceConstraint> = Default::default(); // token to resourceconstraint
+    let mut resource_constraints: BTreeMap<usize, ResourceConstraint<B>> = Default::default(); // token to resourceconstraint
 
-    let mut timelines_by_name = problem
-        .timelines
-        .iter()
-        .enumerate()
-        .map(|(i, t)| (t.name.as_str(), i))
-        .collect::<HashMap<_, _>>();
+    let pooled_timelines = symmetry.pooled_timelines();
 
     // STATIC TOKENS
 
@@ -123,96 +509,106 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
         .zip(problem.timelines.iter())
         .enumerate()
     {
-        for static_token in tl_spec.static_tokens.iter() {
-            if let crate::problem::TokenTime::Fact(start_time, end_time) = static_token.const_time {
-                if !tl.states.is_empty() {
-                    // todo!("Multiple facts.");
-                }
-
-                // if end_time.is_some() {
-                //     tl.fixed_end_time = true;
-                // }
-
-                let token_idx = tokens.len();
-                let state_idx = states.len();
-                let state_seq = tl.states.len();
-                tokens.push(Token {
-                    active: None,
-                    value: &static_token.value,
-                    state: state_idx,
-                    fact: true,
-                    conditions: Vec::new(),
-                });
-                states.push(State {
-                    state_seq,
-                    tokens: vec![token_idx],
-                    start_time: start_time
-                        .map(|t| Real::from_real(&ctx, t as i32, 1))
-                        .unwrap_or_else(|| {
-                            Real::fresh_const(&ctx, &format!("t_{}_s_", tl_spec.name))
-                        }),
-                    end_time: end_time
-                        .map(|t| Real::from_real(&ctx, t as i32, 1))
-                        .unwrap_or_else(|| {
-                            Real::fresh_const(&ctx, &format!("t_{}_e_", tl_spec.name))
-                        }),
-                    timeline: tl_idx,
-                    active: Bool::from_bool(&ctx, true),
-                    activate_next: Bool::fresh_const(&ctx, "nxstate"),
-                    can_expand: false,
-                });
-                tl.states.push(state_idx);
-
-                // Facts can have capacities
-                resource_constraints.entry(token_idx).or_default().capacity =
-                    Some(static_token.capacity);
+        for (fact_idx, static_token) in tl_spec.facts.iter().enumerate() {
+            let (start_time, end_time) = (static_token.start_time, static_token.end_time);
+            if !tl.states.is_empty() {
+                // todo!("Multiple facts.");
+            }
 
-                // Facts can have conditions
-                for alternatives in static_token.conditions.iter() {
-                    let mut conditions_clause = Vec::new();
-                    if let Some(active) = tokens[token_idx].active.as_ref() {
-                        conditions_clause.push(Bool::not(&active));
-                    }
+            // if end_time.is_some() {
+            //     tl.fixed_end_time = true;
+            // }
 
-                    assert!(alternatives.len() > 0);
-                    for cond_spec in alternatives.iter() {
-                        let active = if alternatives.len() == 1 {
-                            tokens[token_idx].active.clone()
-                        } else {
-                            let active = Bool::fresh_const(&ctx, "condactive");
-                            conditions_clause.push(active.clone());
-                            Some(active)
-                        };
+            let token_idx = tokens.len();
+            let state_idx = states.len();
+            let state_seq = tl.states.len();
+            tokens.push(Token {
+                active: None,
+                value: static_token.value,
+                state: state_idx,
+                fact: true,
+                conditions: Vec::new(),
+            });
+            states.push(State {
+                state_seq,
+                tokens: vec![token_idx],
+                start_time: start_time
+                    .map(|t| backend.real_const(t as i64, 1))
+                    .unwrap_or_else(|| backend.fresh_real(&format!("t_{}_s_", tl_spec.name))),
+                end_time: end_time
+                    .map(|t| backend.real_const(t as i64, 1))
+                    .unwrap_or_else(|| backend.fresh_real(&format!("t_{}_e_", tl_spec.name))),
+                timeline: tl_idx,
+                active: backend.bool_const(true),
+                activate_next: backend.fresh_bool("nxstate"),
+                can_expand: false,
+            });
+            tl.states.push(state_idx);
+
+            // Facts can have capacities
+            let rc = resource_constraints.entry(token_idx).or_default();
+            rc.capacity = Some(static_token.capacity);
+            rc.pooled = pooled_timelines.contains(&tl_idx);
+            rc.encoding = settings
+                .resource_encoding_for(&tl_spec.name, tl_spec.value_name(static_token.value));
+            rc.selector = selected
+                .get(&Selector::Capacity(tl_idx, static_token.value))
+                .cloned();
+
+            // Facts can have conditions
+            for (group, alternatives) in static_token.conditions.iter().enumerate() {
+                let mut conditions_clause = Vec::new();
+                if let Some(active) = tokens[token_idx].active.as_ref() {
+                    conditions_clause.push(backend.not(active));
+                }
+                let selector = selected.get(&Selector::FactConditions(tl_idx, fact_idx, group));
+                if let Some(selector) = selector {
+                    conditions_clause.push(backend.not(selector));
+                }
 
-                        tokens[token_idx].conditions.push(conds.len());
-                        conds.push(Condition {
-                            token_idx,
-                            token_queue: 0,
-                            cond_spec,
-                            alternatives_extension: None,
-                            active,
-                        });
-                    }
+                assert!(!alternatives.is_empty());
+                for (alternative, cond_spec) in alternatives
+                    .iter()
+                    .map(|c| &problem.conditions[*c])
+                    .enumerate()
+                {
+                    let active = if alternatives.len() == 1 && selector.is_none() {
+                        tokens[token_idx].active.clone()
+                    } else {
+                        let active = backend.fresh_bool("condactive");
+                        conditions_clause.push(active.clone());
+                        Some(active)
+                    };
 
-                    if conditions_clause.len() >= 2 {
-                        let clause_refs = conditions_clause.iter().collect::<Vec<_>>();
-                        solver.assert(&Bool::or(&ctx, &clause_refs));
-                    }
+                    tokens[token_idx].conditions.push(conds.len());
+                    conds_by_target[cond_spec.timeline][cond_spec.value].push(conds.len());
+                    conds.push(Condition {
+                        token_idx,
+                        token_queue: 0,
+                        cond_spec,
+                        group,
+                        alternative,
+                        alternatives_extension: None,
+                        active,
+                        links: Vec::new(),
+                    });
                 }
 
-                // Minimum duration of state.
-                let prec = &Real::le(
-                    &Real::add(
-                        &ctx,
-                        &[
-                            &states[tokens[token_idx].state].start_time,
-                            &Real::from_real(&ctx, 1_i32, 1), // TODO configurable epsilon
-                        ],
-                    ),
-                    &states[tokens[token_idx].state].end_time,
-                );
-                solver.assert(prec);
+                if conditions_clause.len() >= 2 {
+                    let clause_refs = conditions_clause.iter().collect::<Vec<_>>();
+                    backend.assert(&backend.or(&clause_refs));
+                }
             }
+
+            // Minimum duration of state.
+            let prec = &backend.le(
+                &backend.add(&[
+                    &states[tokens[token_idx].state].start_time,
+                    &backend.real_const(1, 1), // TODO configurable epsilon
+                ]),
+                &states[tokens[token_idx].state].end_time,
+            );
+            backend.assert(prec);
         }
     }
 
@@ -224,8 +620,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
             println!("EXPANDING");
             let expanded = expand_until(
                 problem,
-                &ctx,
-                &solver,
+                backend,
                 timeline,
                 &mut timelines,
                 &mut states,
@@ -236,6 +631,16 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
         }
     }
 
+    // Interchangeable timelines are ordered by the end time of their first state.
+    for group in symmetry.interchangeable_timelines() {
+        for pair in group.windows(2) {
+            backend.assert(&backend.le(
+                &states[timelines[pair[0]].states[0]].end_time,
+                &states[timelines[pair[1]].states[0]].end_time,
+            ));
+        }
+    }
+
     // TODO :: this gives a perf boost on GOAC isntances
     // because we don't need to find so many UNSAT.
     // Could do a pidgeonhole argument for all the constant links to the same timeline,
@@ -245,7 +650,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
     //     if  timeline_names[timeline] == "loc" {
     //         let expanded = expand_n(
     //             problem,
-    //             &ctx,
+    //             ctx,
     //             &solver,
     //             timeline,
     //             &mut timelines,
@@ -256,6 +661,36 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
     //     }
     // }
 
+    // WARM START
+
+    let mut hint_lits = Vec::new();
+    if let Some(hint) = settings.hint.as_ref() {
+        hint_lits = add_hint(
+            problem,
+            backend,
+            hint,
+            &mut timelines,
+            &mut states,
+            &mut tokens,
+        );
+        println!("Warm start with {} hint literals", hint_lits.len());
+        if let Some(dump) = dump.as_mut() {
+            for h in hint_lits.iter() {
+                let state = timelines[h.timeline].states[h.state_seq];
+                let token_idx = states[state].tokens[0];
+                let kind = if h.time { "hinttime" } else { "hint" };
+                dump.record(
+                    backend.bool_name(&h.lit),
+                    LiteralInfo {
+                        value: None,
+                        ..token_literal_info(problem, &states, &tokens, kind, token_idx)
+                    },
+                );
+            }
+        }
+        statistics.hints = hint_lits.len();
+    }
+
     #[allow(unused)]
     let mut n_smt_calls = 0;
 
@@ -267,6 +702,10 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
     // REFINEMENT LOOP
     '_refinement: loop {
+        if is_cancelled(settings) {
+            return Err(SolverError::Cancelled);
+        }
+
         // EXPAND PROBLEM FORMULATION
 
         while states_queue < states.len()
@@ -290,101 +729,111 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                 let state = &states[state_idx];
                 if !timelines[state.timeline].facts_only {
                     // If this is the last state, it has to last until the end of time.
-                    solver.assert(&Bool::implies(
-                        &state.activate_next.not(),
-                        &Real::ge(&state.end_time, &end_of_time),
+                    backend.assert(&backend.implies(
+                        &backend.not(&state.activate_next),
+                        &backend.ge(&state.end_time, &end_of_time),
                     ));
                 }
-                solver.assert(&Real::le(&state.end_time, &end_of_time));
+                backend.assert(&backend.le(&state.end_time, &end_of_time));
 
                 // There are no goals for facts only timelines.
                 if !facts_only {
                     let timeline_idx = states[state_idx].timeline;
-                    let timeline_name = timeline_names[timeline_idx];
                     let tl_spec = &problem.timelines[timeline_idx];
 
-                    if let Some(goal) = tl_spec
-                        .static_tokens
-                        .iter()
-                        .find(|const_token| matches!(const_token.const_time, TokenTime::Goal))
-                    {
+                    if let Some(goal) = tl_spec.goals.first().copied() {
                         // Is this a potential final/goal state?
                         if let Some(&token_idx) = states[state_idx]
                             .tokens
                             .iter()
-                            .find(|t| tokens[**t].value == goal.value)
+                            .find(|t| tokens[**t].value == goal)
                         {
                             let can_expand = {
                                 let timeline = &timelines[timeline_idx];
                                 !timeline.facts_only
                                     && can_expand(
-                                        &problem.timelines[timeline_idx],
+                                        problem,
+                                        timeline_idx,
                                         &states[state_idx]
                                             .tokens
                                             .iter()
                                             .map(|t| tokens[*t].value)
                                             .collect::<Vec<_>>(),
-                                        &goal.value,
+                                        goal,
                                     )
                             };
 
                             states[state_idx].can_expand = can_expand;
 
-                            let goal_lit = Bool::fresh_const(&ctx, "goal");
+                            let goal_lit = backend.fresh_bool("goal");
                             if let Some(active) = tokens[token_idx].active.as_ref() {
-                                solver.assert(&Bool::implies(&goal_lit, active));
+                                backend.assert(&backend.implies(&goal_lit, active));
                             }
                             assert!(goal_lits
                                 .insert(
-                                    (timeline_name, states[state_idx].state_seq as isize),
+                                    (timeline_idx, states[state_idx].state_seq as isize),
                                     goal_lit.clone()
                                 )
                                 .is_none());
 
                             // Select at least one goal (at most one goal is implied by the disabling of tokens below)
                             let mut clause = Vec::new();
-                            if let Some(prev_extension) = timelines
-                                [timelines_by_name[timeline_name]]
-                                .goal_state_extension
-                                .as_ref()
+                            if let Some(prev_extension) =
+                                timelines[timeline_idx].goal_state_extension.as_ref()
                             {
                                 assert!(expand_goal_state_lits.remove(prev_extension).is_some());
-                                clause.push(Bool::not(prev_extension));
+                                clause.push(backend.not(prev_extension));
                             }
                             clause.push(goal_lit);
+                            if let Some(selector) =
+                                selected.get(&Selector::Goal(timeline_idx, goal))
+                            {
+                                clause.push(backend.not(selector));
+                            }
 
                             if can_expand {
-                                let extension = Bool::fresh_const(&ctx, "addgoal");
+                                let extension = backend.fresh_bool("addgoal");
+                                if let Some(dump) = dump.as_mut() {
+                                    dump.record(
+                                        backend.bool_name(&extension),
+                                        LiteralInfo {
+                                            kind: "addgoal".to_string(),
+                                            timeline: Some(tl_spec.name.clone()),
+                                            state: Some(states[state_idx].state_seq),
+                                            value: Some(tl_spec.value_name(goal).to_string()),
+                                            ..Default::default()
+                                        },
+                                    );
+                                }
                                 clause.push(extension.clone());
-                                expand_goal_state_lits.insert(
-                                    extension.clone(),
-                                    (timelines_by_name[timeline_name], &goal.value),
-                                );
-                                timelines[timelines_by_name[timeline_name]].goal_state_extension =
-                                    Some(extension);
+                                expand_goal_state_lits
+                                    .insert(extension.clone(), (timeline_idx, goal));
+                                timelines[timeline_idx].goal_state_extension = Some(extension);
                             }
 
                             let clause_refs = clause.iter().collect::<Vec<_>>();
-                            solver.assert(&Bool::or(&ctx, &clause_refs));
+                            backend.assert(&backend.or(&clause_refs));
                         }
                     }
 
                     // Does the previous state have a goal lit?
                     if let Some(goal_in_prev_state) =
-                        goal_lits.get(&(timeline_name, states[state_idx].state_seq as isize - 1))
+                        goal_lits.get(&(timeline_idx, states[state_idx].state_seq as isize - 1))
                     {
                         // Disable each possible token, if the previous state was a goal state.
-                        solver.assert(&Bool::implies(
-                            goal_in_prev_state,
-                            &Bool::not(&states[state_idx].active),
-                        ));
+                        backend.assert(
+                            &backend.implies(
+                                goal_in_prev_state,
+                                &backend.not(&states[state_idx].active),
+                            ),
+                        );
                     }
 
                     // Did we imply that the next state has to be active (from the previous one)
                     if states[state_idx].state_seq > 0 {
                         let prev_state_idx = timelines[states[state_idx].timeline].states
                             [states[state_idx].state_seq - 1];
-                        solver.assert(&Bool::implies(
+                        backend.assert(&backend.implies(
                             &states[prev_state_idx].activate_next,
                             &states[state_idx].active,
                         ));
@@ -399,16 +848,14 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                             for cond_idx in tokens[source_token_idx].conditions.iter().copied() {
                                 if let Some(next_value) =
                                     conds[cond_idx].cond_spec.is_timeline_transition_to(
-                                        &problem.timelines
-                                            [states[tokens[source_token_idx].state].timeline]
-                                            .name,
+                                        states[tokens[source_token_idx].state].timeline,
                                     )
                                 {
                                     // If condition from previous state is active...
 
                                     let mut clause = Vec::new();
                                     if let Some(active) = conds[cond_idx].active.as_ref() {
-                                        clause.push(Bool::not(active));
+                                        clause.push(backend.not(active));
                                     }
 
                                     // ... then the current state must have the given value.
@@ -428,7 +875,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                                         clause.push(active.clone());
 
                                         let clause_refs = clause.iter().collect::<Vec<_>>();
-                                        solver.assert(&Bool::or(&ctx, &clause_refs));
+                                        backend.assert(&backend.or(&clause_refs));
                                     }
                                 }
                             }
@@ -451,95 +898,110 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                     continue;
                 }
 
+                let timeline_idx = states[tokens[token_idx].state].timeline;
                 println!(
                     "EXPANDING TOKEN {}.{}",
-                    timeline_names[states[tokens[token_idx].state].timeline],
-                    tokens[token_idx].value
+                    timeline_names[timeline_idx],
+                    problem.timelines[timeline_idx].value_name(tokens[token_idx].value)
                 );
-                let token_type = problem.timelines[states[tokens[token_idx].state].timeline]
-                    .token_types
-                    .iter()
-                    .find(|s| s.value == tokens[token_idx].value)
+                let token_type = problem.timelines[timeline_idx].token_types
+                    [tokens[token_idx].value]
+                    .as_ref()
                     .unwrap();
 
-                resource_constraints.entry(token_idx).or_default().capacity =
-                    Some(token_type.capacity);
+                let rc = resource_constraints.entry(token_idx).or_default();
+                rc.capacity = Some(token_type.capacity);
+                rc.encoding = settings.resource_encoding_for(
+                    timeline_names[timeline_idx],
+                    problem.timelines[timeline_idx].value_name(token_type.value),
+                );
+                rc.selector = selected
+                    .get(&Selector::Capacity(timeline_idx, token_type.value))
+                    .cloned();
 
                 // If there are old links pointing to this value, we need to update them.
                 // println!("Adding links for {}.{}", token.timeline_name, token.value);
-                for (cond_idx, cond) in conds.iter().enumerate() {
-                    if cond.cond_spec.value == tokens[token_idx].value {
-                        expand_links_queue.push((false, cond_idx));
-                    }
+                for cond_idx in conds_by_target[timeline_idx][tokens[token_idx].value]
+                    .iter()
+                    .copied()
+                {
+                    expand_links_queue.push((false, cond_idx));
                 }
 
                 // Minimum duration of state.
-                let prec = &Real::le(
-                    &Real::add(
-                        &ctx,
-                        &[
-                            &states[tokens[token_idx].state].start_time,
-                            &Real::from_real(&ctx, token_type.duration_limits.0 as i32, 1),
-                        ],
-                    ),
+                let prec = &backend.le(
+                    &backend.add(&[
+                        &states[tokens[token_idx].state].start_time,
+                        &backend.real_const(token_type.duration_limits.0 as i64, 1),
+                    ]),
                     &states[tokens[token_idx].state].end_time,
                 );
                 println!("prec {:?}", prec);
                 if let Some(cond) = tokens[token_idx].active.as_ref() {
-                    solver.assert(&Bool::implies(cond, prec))
+                    backend.assert(&backend.implies(cond, prec))
                 } else {
-                    solver.assert(prec);
+                    backend.assert(prec);
                 }
 
                 // Maximum duration
                 if let Some(max_dur) = token_type.duration_limits.1 {
-                    let prec = &Real::ge(
-                        &Real::add(
-                            &ctx,
-                            &[
-                                &states[tokens[token_idx].state].start_time,
-                                &Real::from_real(&ctx, max_dur as i32, 1),
-                            ],
-                        ),
+                    let prec = &backend.ge(
+                        &backend.add(&[
+                            &states[tokens[token_idx].state].start_time,
+                            &backend.real_const(max_dur as i64, 1),
+                        ]),
                         &states[tokens[token_idx].state].end_time,
                     );
 
                     if let Some(cond) = tokens[token_idx].active.as_ref() {
-                        solver.assert(&Bool::implies(cond, prec))
+                        backend.assert(&backend.implies(cond, prec))
                     } else {
-                        solver.assert(prec);
+                        backend.assert(prec);
                     }
                 }
 
-                for alternatives in token_type.conditions.iter() {
+                for (group, alternatives) in token_type.conditions.iter().enumerate() {
                     let mut conditions_clause = Vec::new();
                     if let Some(active) = tokens[token_idx].active.as_ref() {
-                        conditions_clause.push(Bool::not(&active));
+                        conditions_clause.push(backend.not(active));
+                    }
+                    let selector =
+                        selected.get(&Selector::Conditions(timeline_idx, token_type.value, group));
+                    if let Some(selector) = selector {
+                        conditions_clause.push(backend.not(selector));
                     }
 
                     assert!(alternatives.len() > 0);
-                    for cond_spec in alternatives.iter() {
-                        let active = if alternatives.len() == 1 {
+                    for (alternative, cond_spec) in alternatives
+                        .iter()
+                        .map(|c| &problem.conditions[*c])
+                        .enumerate()
+                    {
+                        let active = if alternatives.len() == 1 && selector.is_none() {
                             tokens[token_idx].active.clone()
                         } else {
-                            let active = Bool::fresh_const(&ctx, "condactive");
+                            let active = backend.fresh_bool("condactive");
                             conditions_clause.push(active.clone());
                             Some(active)
                         };
 
                         tokens[token_idx].conditions.push(conds.len());
+                        conds_by_target[cond_spec.timeline][cond_spec.value].push(conds.len());
                         conds.push(Condition {
                             token_idx,
                             token_queue: 0,
                             cond_spec,
+                            group,
+                            alternative,
                             alternatives_extension: None,
                             active,
+                            links: Vec::new(),
                         });
                     }
 
                     if conditions_clause.len() >= 2 {
                         let clause_refs = conditions_clause.iter().collect::<Vec<_>>();
-                        solver.assert(&Bool::or(&ctx, &clause_refs));
+                        backend.assert(&backend.or(&clause_refs));
                     }
                 }
             }
@@ -560,9 +1022,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                 // is this a timeline transition?
                 if cond_spec
-                    .is_timeline_transition_from(
-                        &problem.timelines[states[tokens[token_idx].state].timeline].name,
-                    )
+                    .is_timeline_transition_from(states[tokens[token_idx].state].timeline)
                     .is_some()
                 {
                     if states[tokens[token_idx].state].state_seq > 0 {
@@ -577,7 +1037,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                         let mut clause = vec![];
                         if let Some(l) = conds[cond_idx].active.as_ref() {
-                            clause.push(Bool::not(l));
+                            clause.push(backend.not(l));
                         }
 
                         let mut any_const = false;
@@ -595,8 +1055,14 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                         if !any_const {
                             let clause_refs = clause.iter().collect::<Vec<_>>();
-                            solver.assert(&Bool::or(&ctx, &clause_refs));
+                            backend.assert(&backend.or(&clause_refs));
                         }
+                    } else if subsets.is_some() && !tokens[token_idx].fact {
+                        // Without the transition graph restrictions, a token that needs
+                        // a previous value can be the first one on its timeline.
+                        let clause = conds[cond_idx].active.iter().map(|l| backend.not(l));
+                        let clause = clause.collect::<Vec<_>>();
+                        backend.assert(&backend.or(&clause.iter().collect::<Vec<_>>()));
                     } else {
                         // println!(
                         //     "No transition condition for initial state for {}",
@@ -606,9 +1072,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                     continue;
                 } else if cond_spec
-                    .is_timeline_transition_to(
-                        &problem.timelines[states[tokens[token_idx].state].timeline].name,
-                    )
+                    .is_timeline_transition_to(states[tokens[token_idx].state].timeline)
                     .is_some()
                 {
                     // Pass, this is handled when adding the next state.
@@ -628,8 +1092,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                 //     }
                 // };
 
-                let target_tl = cond_spec.timeline_ref.as_str();
-                let target_timeline_idx = timelines_by_name[target_tl];
+                let target_timeline_idx = cond_spec.timeline;
 
                 // let mut all_target_tokens = Vec::new();
                 // println!("Finding tokens for object set {:?}", &conds[cond_idx].cond_spec.object);
@@ -637,19 +1100,17 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                 let _pr1 = hprof::enter("iter potential target tokens");
                 // println!("Finding tokens for {}.{}", obj, conds[cond_idx].cond_spec.value);
 
-                let matching_tokens = tokens.iter().enumerate().filter(|(_, t)| {
-                    states[t.state].timeline == target_timeline_idx
-                        && t.value == conds[cond_idx].cond_spec.value
-                });
-                for (token, _) in matching_tokens {
-                    // all_target_tokens.push(token);
-
-                    if token >= conds[cond_idx].token_queue {
-                        // println!("  new token {:?}", tokens[token].value);
-                        new_target_tokens.push(token);
-                    }
+                while indexed_tokens < tokens.len() {
+                    let token = &tokens[indexed_tokens];
+                    tokens_by_value[states[token.state].timeline][token.value].push(indexed_tokens);
+                    indexed_tokens += 1;
                 }
 
+                let matching_tokens = &tokens_by_value[target_timeline_idx][cond_spec.value];
+                let first_new =
+                    matching_tokens.partition_point(|t| *t < conds[cond_idx].token_queue);
+                new_target_tokens.extend_from_slice(&matching_tokens[first_new..]);
+
                 drop(_pr1);
                 let _pr2 = hprof::enter("add target tokens");
 
@@ -662,18 +1123,17 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                     let prev_tokens_len = tokens.len();
                     if expand_until(
                         problem,
-                        &ctx,
-                        &solver,
+                        backend,
                         target_timeline_idx,
                         &mut timelines,
                         &mut states,
                         &mut tokens,
-                        Some(&conds[cond_idx].cond_spec.value),
+                        Some(cond_spec.value),
                     ) {
                         assert!(
                             tokens[prev_tokens_len..]
                                 .iter()
-                                .filter(|t| t.value == conds[cond_idx].cond_spec.value.as_str())
+                                .filter(|t| t.value == cond_spec.value)
                                 .count()
                                 == 1
                         );
@@ -682,9 +1142,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                             prev_tokens_len
                                 + tokens[prev_tokens_len..]
                                     .iter()
-                                    .position(|t| {
-                                        t.value == conds[cond_idx].cond_spec.value.as_str()
-                                    })
+                                    .position(|t| t.value == cond_spec.value)
                                     .unwrap(),
                         );
 
@@ -709,7 +1167,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                         // );
 
                         if let Some(active) = conds[cond_idx].active.as_ref() {
-                            solver.assert(&active.not());
+                            backend.assert(&backend.not(active));
                         } else {
                             println!("Unsatisfiable condition {:?}!", cond_spec);
                             return Err(SolverError::NoSolution);
@@ -718,7 +1176,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                 } else {
                     let mut alternatives = Vec::new();
 
-                    let old_expansion_lit: Option<Bool> =
+                    let old_expansion_lit: Option<B::Bool> =
                         conds[cond_idx].alternatives_extension.take();
 
                     if let Some(b) = old_expansion_lit.as_ref() {
@@ -730,13 +1188,14 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                     let can_expand = {
                         !timelines[target_timeline_idx].facts_only
                             && can_expand(
-                                &problem.timelines[target_timeline_idx],
+                                problem,
+                                target_timeline_idx,
                                 &states[*timelines[target_timeline_idx].states.last().unwrap()]
                                     .tokens
                                     .iter()
                                     .map(|t| tokens[*t].value)
                                     .collect::<Vec<_>>(),
-                                &conds[cond_idx].cond_spec.value,
+                                cond_spec.value,
                             )
                     };
                     drop(_pr3);
@@ -747,7 +1206,15 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                     // );
 
                     if can_expand {
-                        let expand_lit = Bool::fresh_const(&ctx, "exp");
+                        let expand_lit = backend.fresh_bool("exp");
+                        if let Some(dump) = dump.as_mut() {
+                            dump.record(
+                                backend.bool_name(&expand_lit),
+                                condition_literal_info(
+                                    problem, &states, &tokens, &conds, "exp", cond_idx,
+                                ),
+                            );
+                        }
                         expand_links_lits.insert(expand_lit.clone(), cond_idx);
                         conds[cond_idx].alternatives_extension = Some(expand_lit.clone());
                         // println!("added expand lit");
@@ -760,13 +1227,33 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                     if let Some(cond) = need_alternatives {
                         // println!("added need alternatives {:?}", old_expansion_lit);
-                        alternatives.push(Bool::not(&cond));
+                        alternatives.push(backend.not(&cond));
                     }
 
                     let const_link = alternatives.len() + new_target_tokens.len() == 1;
                     for token_idx in new_target_tokens.iter().copied() {
                         // Represents the usage of the causal link.
-                        let choose_link = (!const_link).then(|| Bool::fresh_const(&ctx, "cl"));
+                        let choose_link = (!const_link).then(|| backend.fresh_bool("cl"));
+                        if let (Some(dump), Some(choose_link)) =
+                            (dump.as_mut(), choose_link.as_ref())
+                        {
+                            let target =
+                                token_literal_info(problem, &states, &tokens, "", token_idx);
+                            dump.record(
+                                backend.bool_name(choose_link),
+                                LiteralInfo {
+                                    target: Some(format!(
+                                        "{}[{}]={}",
+                                        target.timeline.unwrap(),
+                                        target.state.unwrap(),
+                                        target.value.unwrap()
+                                    )),
+                                    ..condition_literal_info(
+                                        problem, &states, &tokens, &conds, "cl", cond_idx,
+                                    )
+                                },
+                            );
+                        }
 
                         let this_state = &states[tokens[conds[cond_idx].token_idx].state];
                         let target_state = &states[tokens[token_idx].state];
@@ -776,54 +1263,48 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                                 // // The target token should have a next value to transition to.
                                 vec![
                                     target_state.activate_next.clone(),
-                                    Real::_eq(&target_state.end_time, &this_state.start_time),
+                                    backend.eq(&target_state.end_time, &this_state.start_time),
                                 ]
                             }
                             TemporalRelationship::MetBy => {
-                                vec![Real::_eq(&target_state.end_time, &this_state.start_time)]
+                                vec![backend.eq(&target_state.end_time, &this_state.start_time)]
                             }
                             TemporalRelationship::Starts => {
-                                vec![Real::_eq(&target_state.start_time, &this_state.start_time)]
+                                vec![backend.eq(&target_state.start_time, &this_state.start_time)]
                             }
                             TemporalRelationship::StartsAfter => {
-                                vec![Real::le(&target_state.start_time, &this_state.start_time)]
+                                vec![backend.le(&target_state.start_time, &this_state.start_time)]
                             }
                             TemporalRelationship::Cover => vec![
-                                Real::le(&target_state.start_time, &this_state.start_time),
-                                Real::le(&this_state.end_time, &target_state.end_time),
+                                backend.le(&target_state.start_time, &this_state.start_time),
+                                backend.le(&this_state.end_time, &target_state.end_time),
                             ],
                             TemporalRelationship::StartPrecond => vec![
-                                Real::le(
-                                    &Real::add(
-                                        &ctx,
-                                        &[
-                                            &target_state.start_time,
-                                            &Real::from_real(&ctx, 1_i32, 1), // TODO configurable epsilon
-                                        ],
-                                    ),
+                                backend.le(
+                                    &backend.add(&[
+                                        &target_state.start_time,
+                                        &backend.real_const(1, 1), // TODO configurable epsilon
+                                    ]),
                                     &this_state.start_time,
                                 ),
-                                Real::le(&this_state.start_time, &target_state.end_time),
+                                backend.le(&this_state.start_time, &target_state.end_time),
                             ],
                             TemporalRelationship::StartEffect => vec![
-                                Real::le(&target_state.start_time, &this_state.start_time),
-                                Real::le(
-                                    &Real::add(
-                                        &ctx,
-                                        &[
-                                            &this_state.start_time,
-                                            &Real::from_real(&ctx, 1_i32, 1), // TODO configurable epsilon
-                                        ],
-                                    ),
+                                backend.le(&target_state.start_time, &this_state.start_time),
+                                backend.le(
+                                    &backend.add(&[
+                                        &this_state.start_time,
+                                        &backend.real_const(1, 1), // TODO configurable epsilon
+                                    ]),
                                     &target_state.end_time,
                                 ),
                             ],
                             TemporalRelationship::Equal => vec![
-                                Real::_eq(&this_state.start_time, &target_state.start_time),
-                                Real::_eq(&this_state.end_time, &target_state.end_time),
+                                backend.eq(&this_state.start_time, &target_state.start_time),
+                                backend.eq(&this_state.end_time, &target_state.end_time),
                             ],
                             TemporalRelationship::Meets => {
-                                vec![Real::_eq(&target_state.start_time, &this_state.end_time)]
+                                vec![backend.eq(&target_state.start_time, &this_state.end_time)]
                             }
                         };
 
@@ -834,7 +1315,6 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                         if conds[cond_idx].cond_spec.amount > 0 {
                             let rc = resource_constraints.entry(token_idx).or_default();
-                            assert!(!rc.closed);
                             rc.users.push((
                                 choose_link.clone(),
                                 conds[cond_idx].token_idx,
@@ -852,16 +1332,17 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                         for cond in clause {
                             if let Some(choose_link) = choose_link.as_ref() {
-                                solver.assert(&Bool::implies(choose_link, &cond));
+                                backend.assert(&backend.implies(choose_link, &cond));
                                 // alternatives.push(choose_link.clone());
                             } else {
-                                solver.assert(&cond);
+                                backend.assert(&cond);
                             }
                         }
 
                         if let Some(choose_link) = choose_link.as_ref() {
                             alternatives.push(choose_link.clone());
                         }
+                        conds[cond_idx].links.push((choose_link, token_idx));
                     }
 
                     // println!(
@@ -877,7 +1358,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                     if !alternatives.is_empty() {
                         let alternatives_refs = alternatives.iter().collect::<Vec<_>>();
-                        solver.assert(&Bool::or(&ctx, &alternatives_refs));
+                        backend.assert(&backend.or(&alternatives_refs));
                     }
                 }
                 conds[cond_idx].token_queue = tokens.len();
@@ -886,36 +1367,39 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
             // every time we touch something, make sure that the timeline transitions are extended all the way to a goal state.
 
             for tl_idx in 0..timelines.len() {
-                for static_token in problem.timelines[tl_idx].static_tokens.iter() {
-                    if let crate::problem::TokenTime::Goal = static_token.const_time {
-                        let last_state = timelines[tl_idx].states.last().unwrap();
-                        let has_goal = states[*last_state]
-                            .tokens
-                            .iter()
-                            .any(|t| tokens[*t].value == static_token.value);
-                        if !has_goal {
-                            // println!(
-                            //     "Timeline {} has no final goal state. Adding.",
-                            //     const_token.timeline_name
-                            // );
-                            let expanded = expand_until(
-                                problem,
-                                &ctx,
-                                &solver,
-                                tl_idx,
-                                &mut timelines,
-                                &mut states,
-                                &mut tokens,
-                                Some(static_token.value.as_str()),
-                            );
+                for goal in problem.timelines[tl_idx].goals.iter().copied() {
+                    let last_state = timelines[tl_idx].states.last().unwrap();
+                    let has_goal = states[*last_state]
+                        .tokens
+                        .iter()
+                        .any(|t| tokens[*t].value == goal);
+                    if !has_goal {
+                        // println!(
+                        //     "Timeline {} has no final goal state. Adding.",
+                        //     const_token.timeline_name
+                        // );
+                        let expanded = expand_until(
+                            problem,
+                            backend,
+                            tl_idx,
+                            &mut timelines,
+                            &mut states,
+                            &mut tokens,
+                            Some(goal),
+                        );
 
-                            if !expanded {
-                                println!(
-                                    "could not expand timeline {} until goal {}.",
-                                    problem.timelines[tl_idx].name, static_token.value
-                                );
-                                panic!();
+                        if !expanded {
+                            // An unreachable goal only rules out the subsets that select it.
+                            if let Some(selector) = selected.get(&Selector::Goal(tl_idx, goal)) {
+                                backend.assert(&backend.not(selector));
+                                continue;
                             }
+                            println!(
+                                "could not expand timeline {} until goal {}.",
+                                problem.timelines[tl_idx].name,
+                                problem.timelines[tl_idx].value_name(goal)
+                            );
+                            return Err(SolverError::GoalStateMissing);
                         }
                     }
                 }
@@ -926,130 +1410,9 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
         for (_token_idx, rc) in resource_constraints.iter_mut() {
             if rc.users.len() > rc.integrated {
                 // We need to update the constraint.
-
-                if rc.integrated != 0 {
-                    // println!("WARNING: resource constraint users has been extended.");
-                }
-
-                if !rc.closed {
-                    // TODO: make an extension point in the pseudo-boolean constraint for adding more usages later.
-                }
-
-                // println!(
-                //     "Adding resource constraint for {}.{} with size {} capacity {:?}",
-                //     timeline_names[states[tokens[*_token_idx].state].timeline],
-                //     tokens[*_token_idx].value,
-                //     rc.users.len(),
-                //     rc.capacity
-                // );
-
-                // TASK-INDEXED RESOURCE CONSTRAINT
-
-                // for i in 0..rc.users.len() {
-                //     let j0 = if i > rc.integrated {
-                //         0
-                //     } else {
-                //         i+1
-                //     };
-
-                //     for j in j0..rc.users.len() {
-
-                //     }
-                // }
-
-                const USE_PAIRWISE_RESOURCE_CONSTRAINT: bool = true;
-
-                if USE_PAIRWISE_RESOURCE_CONSTRAINT && rc.capacity.unwrap() == 1 {
-                    // Special-case parwise exclusion, which is probably faster than
-                    // the long pseudo-boolean constraint needed for capacity >=2
-
-                    // println!("Cap1 exclusion {}", rc.users.len());
-                    for i in 0..rc.users.len() {
-                        let start_from = (i + 1).max(rc.integrated);
-                        for j in start_from..rc.users.len() {
-                            let (link1, token1, amount1) = &rc.users[i];
-                            let (link2, token2, amount2) = &rc.users[j];
-
-                            assert!(*amount1 == 1);
-                            assert!(*amount2 == 1);
-
-                            let mut alts = vec![
-                                Real::le(
-                                    &states[tokens[*token1].state].end_time,
-                                    &states[tokens[*token2].state].start_time,
-                                ),
-                                Real::le(
-                                    &states[tokens[*token2].state].end_time,
-                                    &states[tokens[*token1].state].start_time,
-                                ),
-                            ];
-
-                            if let Some(link1) = link1 {
-                                alts.push(link1.not());
-                            }
-                            if let Some(link2) = link2 {
-                                alts.push(link2.not());
-                            }
-
-                            let alts_refs = alts.iter().collect::<Vec<_>>();
-                            solver.assert(&Bool::or(&ctx, &alts_refs));
-                            n_exclusions += 1;
-                        }
-                    }
-                } else {
-                    // println!("Cap >=2 PB");
-                    for (link1, token1, _) in rc.users.iter() {
-                        // println!("link1 const {:?}", link1);
-                        let overlaps = rc
-                            .users
-                            .iter()
-                            .map(|(link2, token2, amount2)| {
-                                // println!("   link2 const {:?}", link2);
-                                let overlap = Bool::and(
-                                    &ctx,
-                                    &[
-                                        // &link1.clone().unwrap_or_else(|| Bool::from_bool(&ctx, true)),
-                                        &link2
-                                            .clone()
-                                            .unwrap_or_else(|| Bool::from_bool(&ctx, true)),
-                                        &Real::lt(
-                                            &states[tokens[*token1].state].start_time,
-                                            &states[tokens[*token2].state].end_time,
-                                        ),
-                                        &Real::lt(
-                                            &states[tokens[*token2].state].start_time,
-                                            &states[tokens[*token1].state].end_time,
-                                        ),
-                                    ],
-                                );
-
-                                (overlap, *amount2)
-                            })
-                            .collect::<Vec<_>>();
-
-                        let overlaps_refs = overlaps
-                            .iter()
-                            .map(|(o, c)| (o, *c as i32))
-                            .collect::<Vec<_>>();
-
-                        // println!(
-                        //     "Adding resource constraint for {}.{} with size {} cap {}",
-                        //     timeline_names[states[tokens[*_token_idx].state].timeline],
-                        //     tokens[*_token_idx].value,
-                        //     overlaps.len(),
-                        //     rc.capacity.unwrap()
-                        // );
-
-                        let pb = Bool::pb_le(&ctx, &overlaps_refs, rc.capacity.unwrap() as i32);
-                        if let Some(link1) = link1 {
-                            solver.assert(&Bool::implies(link1, &pb));
-                        } else {
-                            solver.assert(&pb);
-                        }
-                        n_pbs += 1;
-                    }
-                }
-
+                let (exclusions, pbs) = encode_resource(backend, &states, &tokens, rc);
+                n_exclusions += exclusions;
+                n_pbs += pbs;
                 rc.integrated = rc.users.len();
             }
         }
@@ -1064,19 +1427,49 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
         drop(p);
         let p = hprof::enter("solve_smt");
 
-        let expand_state_seq_lits: HashMap<Bool, usize> = timelines
+        let expand_state_seq_lits: HashMap<B::Bool, usize> = timelines
             .iter()
             .map(|tl| *tl.states.last().unwrap())
             .filter(|s_idx| states[*s_idx].can_expand)
             .map(|s_idx| (states[s_idx].activate_next.clone(), s_idx))
             .collect();
 
-        let neg_expansions = expand_links_lits
-            .keys()
-            .chain(expand_goal_state_lits.keys())
-            .chain(expand_state_seq_lits.keys())
-            .map(|l| (Bool::not(l), l.clone()))
+        // The maps above are iterated in random order, so sort the expansion literals
+        // by what they expand to keep the solver calls reproducible.
+        let mut ordered_expansions = expand_links_lits
+            .iter()
+            .map(|(l, cond_idx)| (0, *cond_idx, 0, l))
+            .chain(
+                expand_goal_state_lits
+                    .iter()
+                    .map(|(l, (tl, value))| (1, *tl, *value, l)),
+            )
+            .chain(
+                expand_state_seq_lits
+                    .iter()
+                    .map(|(l, state_idx)| (2, *state_idx, 0, l)),
+            )
+            .collect::<Vec<_>>();
+        ordered_expansions.sort_by_key(|(kind, a, b, _)| (*kind, *a, *b));
+        let mut assumptions = ordered_expansions
+            .iter()
+            .map(|(_, _, _, l)| backend.not(l))
+            .collect::<Vec<_>>();
+        let neg_expansions = assumptions
+            .iter()
+            .cloned()
+            .zip(ordered_expansions.iter().map(|(_, _, _, l)| (*l).clone()))
             .collect::<HashMap<_, _>>();
+        // Hints are phase preferences where the backend supports them, and otherwise
+        // assumptions that are dropped as soon as they appear in a core.
+        assumptions.extend(subset.iter().map(|c| selector_lits[*c].clone()));
+        let n_expansions = assumptions.len();
+        let hints = hint_lits.iter().map(|h| h.lit.clone()).collect::<Vec<_>>();
+        if backend.supports_phase() {
+            backend.prefer(&hints);
+        } else {
+            assumptions.extend(hints);
+        }
 
         // for (i, timeline) in timelines.iter().enumerate() {
         //     println!("Timeline {} has {} states", timeline_names[i], timeline.states.len());
@@ -1094,39 +1487,139 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
             n_pbs,
         );
 
-        println!("{}", solver.to_string());
+        println!("{}", backend.formula());
         // panic!();
 
         n_smt_calls += 1;
-        println!(
-            "ASSUMPTIONS {:?}",
-            neg_expansions.keys().cloned().collect::<Vec<_>>()
-        );
-        let result = solver.check_assumptions(&neg_expansions.keys().cloned().collect::<Vec<_>>());
+        statistics.solver_calls = n_smt_calls;
+        statistics.states = states.len();
+        statistics.tokens = tokens.len();
+        statistics.conditions = conds.len();
+        statistics.exclusions = n_exclusions;
+        statistics.pbs = n_pbs;
+        let mut timeline_states = vec![0; timelines.len()];
+        for state in states.iter() {
+            timeline_states[state.timeline] += 1;
+        }
+        statistics.iterations.push(IterationStatistics {
+            states: timeline_states,
+            ..Default::default()
+        });
+        println!("ASSUMPTIONS {:?}", assumptions);
+        if let Some(dump) = dump.as_mut() {
+            record_literals(backend, dump, problem, &states, &tokens, &conds, &goal_lits);
+        }
+        let result = loop {
+            if let Some(dump) = dump.as_mut() {
+                let assumptions = assumptions
+                    .iter()
+                    .map(|a| backend.display(a))
+                    .collect::<Vec<_>>();
+                dump.write_iteration(
+                    &backend.options(),
+                    &backend.formula(),
+                    &backend.describe(),
+                    &assumptions,
+                )
+                .map_err(|e| SolverError::Io(e.to_string()))?;
+            }
+            let result = backend.check(&assumptions);
+            if result != CheckResult::Unsat || backend.supports_phase() {
+                break result;
+            }
+
+            // Conflicting hints are dropped before anything is expanded. A conflicting
+            // time hint also drops the later time hints on the same timeline.
+            let core = backend.unsat_core();
+            let n_hints = hint_lits.len();
+            let conflicting = hint_lits
+                .iter()
+                .filter(|h| core.contains(&h.lit))
+                .map(|h| (h.timeline, h.state_seq, h.time))
+                .collect::<Vec<_>>();
+            hint_lits.retain(|h| {
+                !conflicting.iter().any(|(tl, seq, time)| {
+                    h.timeline == *tl
+                        && h.time == *time
+                        && (h.state_seq == *seq || (*time && h.state_seq > *seq))
+                })
+            });
+            if hint_lits.len() == n_hints {
+                break result;
+            }
+            println!("Dropping {} conflicting hints", n_hints - hint_lits.len());
+            statistics.hints_dropped += n_hints - hint_lits.len();
+            assumptions.truncate(n_expansions);
+            assumptions.extend(hint_lits.iter().map(|h| h.lit.clone()));
+            n_smt_calls += 1;
+            statistics.solver_calls = n_smt_calls;
+        };
         drop(p);
 
         match result {
-            z3::SatResult::Unsat => {
+            CheckResult::Unsat => {
                 let _p = hprof::enter("unsat_core");
-                let mut core = solver.get_unsat_core();
+                let mut core = backend.unsat_core();
+                if let Some(error) = backend.error() {
+                    return Err(SolverError::Backend(error));
+                }
                 if core.is_empty() {
-                    return Err(SolverError::NoSolution);
+                    match subsets {
+                        Some(checks) => {
+                            subset = checks.next(Some(false))?;
+                            continue;
+                        }
+                        None => return Err(SolverError::NoSolution),
+                    }
                 }
+                // Z3 does not return the core in a reproducible order.
+                let assumption_idx = assumptions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| (a, i))
+                    .collect::<HashMap<_, _>>();
+                core.sort_by_key(|c| assumption_idx[c]);
 
                 if settings.minimize_cores {
-                    let use_trim_core = true;
-                    let use_minimize_core = true;
                     println!("Minmizing core...");
-                    if use_trim_core {
-                        crate::cores::trim_core(&mut core, &solver, |_| {});
-                    }
+                    crate::cores::minimize_core_with(
+                        &mut core,
+                        backend,
+                        settings.core_minimization,
+                        &settings.core_budget,
+                        |_| {},
+                    );
+                }
 
-                    if use_minimize_core {
-                        crate::cores::minimize_core(&mut core, &solver, |_| {});
+                // When checking a subset, the core is only about expansions if
+                // it is not made of selected constraints alone.
+                if let Some(checks) = subsets {
+                    core.retain(|c| neg_expansions.contains_key(c));
+                    if core.is_empty() {
+                        subset = checks.next(Some(false))?;
+                        continue;
                     }
                 }
-
-                // core_sizes.push(core.len());
+                let sizes = (states.len(), tokens.len(), expand_links_queue.len());
+
+                statistics.core_sizes.push(core.len());
+                let mut core_timelines = vec![0; timelines.len()];
+                for c in core.iter() {
+                    let timeline = neg_expansions.get(c).and_then(|nc| {
+                        let goal = expand_goal_state_lits.get(nc).map(|(tl, _)| *tl);
+                        let link = expand_links_lits
+                            .get(nc)
+                            .map(|c| states[tokens[conds[*c].token_idx].state].timeline);
+                        let state_seq = expand_state_seq_lits.get(nc).map(|s| states[*s].timeline);
+                        goal.or(link).or(state_seq)
+                    });
+                    if let Some(timeline) = timeline {
+                        core_timelines[timeline] += 1;
+                    }
+                }
+                let iteration = statistics.iterations.last_mut().unwrap();
+                iteration.core_size = Some(core.len());
+                iteration.core_timelines = core_timelines;
 
                 let expandstate_only = core.iter().all(|c| {
                     if let Some(nc) = neg_expansions.get(c) {
@@ -1155,23 +1648,29 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                                 println!("Expand goals in timleine {}", timeline_names[*timeline]);
                                 println!(
                                     "  -expand goal value {} for {}",
-                                    goalvalue, timeline_names[*timeline]
+                                    problem.timelines[*timeline].value_name(*goalvalue),
+                                    timeline_names[*timeline]
                                 );
 
                                 let expanded = expand_until(
                                     problem,
-                                    &ctx,
-                                    &solver,
+                                    backend,
                                     *timeline,
                                     &mut timelines,
                                     &mut states,
                                     &mut tokens,
-                                    Some(goalvalue),
+                                    Some(*goalvalue),
                                 );
                                 println!("     expanded={}", expanded);
 
                                 if !expanded && coresize == 1 {
-                                    return Err(SolverError::NoSolution);
+                                    match subsets {
+                                        Some(checks) => {
+                                            subset = checks.next(Some(false))?;
+                                            continue '_refinement;
+                                        }
+                                        None => return Err(SolverError::NoSolution),
+                                    }
                                 }
                             } else {
                                 // Don't expand states unless we have to.
@@ -1183,7 +1682,8 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                             println!(
                                 "  -expand LINK {}.{} {:?}",
                                 timeline_names[states[token.state].timeline],
-                                token.value,
+                                problem.timelines[states[token.state].timeline]
+                                    .value_name(token.value),
                                 cond.cond_spec
                             );
 
@@ -1195,7 +1695,10 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                             let values = states[state_idx]
                                 .tokens
                                 .iter()
-                                .map(|t| tokens[*t].value)
+                                .map(|t| {
+                                    problem.timelines[states[state_idx].timeline]
+                                        .value_name(tokens[*t].value)
+                                })
                                 .collect::<Vec<_>>();
 
                             if coresize <= 5 || expandstateseq_only {
@@ -1212,8 +1715,7 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                                     expand_n(
                                         problem,
-                                        &ctx,
-                                        &solver,
+                                        backend,
                                         states[state_idx].timeline,
                                         &mut timelines,
                                         &mut states,
@@ -1229,57 +1731,143 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
                         panic!("didn't find negated core lit");
                     }
                 }
+
+                // Nothing could be expanded, so the subset can not be decided.
+                if let Some(checks) = subsets {
+                    if sizes == (states.len(), tokens.len(), expand_links_queue.len()) {
+                        subset = checks.next(None)?;
+                    }
+                }
             }
 
-            z3::SatResult::Sat => {
+            CheckResult::Sat => {
+                let (violated_exclusions, violated_pbs) = add_violated_resource_constraints(
+                    backend,
+                    &states,
+                    &tokens,
+                    &mut resource_constraints,
+                );
+                if let Some(error) = backend.error() {
+                    return Err(SolverError::Backend(error));
+                }
+                if violated_exclusions + violated_pbs > 0 {
+                    println!(
+                        "Added {} violated pairwise exclusions and {} pbs",
+                        violated_exclusions, violated_pbs
+                    );
+                    n_exclusions += violated_exclusions;
+                    n_pbs += violated_pbs;
+                    continue;
+                }
+                if let Some(checks) = subsets {
+                    subset = checks.next(Some(true))?;
+                    continue;
+                }
+
                 let _p = hprof::enter("extract_solution");
                 // println!("SAT after {} solver calls", n_smt_calls);
-                let model = solver.get_model().unwrap();
                 // println!("{}", model.to_string());
 
-                let mut timelines: Vec<SolutionTimeline> = problem
-                    .timelines
-                    .iter()
-                    .map(|t| SolutionTimeline {
-                        name: t.name.clone(),
-                        tokens: Vec::new(),
-                    })
-                    .collect::<Vec<_>>();
+                let is_true = |lit: &Option<B::Bool>| {
+                    lit.as_ref().map(|l| backend.bool_value(l)).unwrap_or(true)
+                };
 
-                for v in tokens.iter() {
+                // Active tokens by timeline, with their token index.
+                let mut timeline_tokens = vec![Vec::new(); problem.timelines.len()];
+                for (token_idx, v) in tokens.iter().enumerate() {
                     let state = &states[v.state];
                     let tl_idx = state.timeline;
 
-                    let active = v
-                        .active
-                        .as_ref()
-                        .map(|a| model.eval(a, true).unwrap().as_bool().unwrap())
-                        .unwrap_or(true);
-
-                    if !active {
+                    if !is_true(&v.active) {
                         // println!("token {} ({:?}) not active", v.value, v.active);
                         continue;
                     }
 
-                    let start_time =
-                        z3real_value(&model.eval(&states[v.state].start_time, true).unwrap());
-                    let end_time =
-                        z3real_value(&model.eval(&states[v.state].end_time, true).unwrap());
+                    let start_time = real_value(backend, &states[v.state].start_time);
+                    let end_time = real_value(backend, &states[v.state].end_time);
 
                     // println!("value {:?}", v.value);
 
-                    timelines[tl_idx].tokens.push(SolutionToken {
-                        value: v.value.to_string(),
-                        start_time,
-                        end_time,
-                    })
+                    timeline_tokens[tl_idx].push((
+                        token_idx,
+                        SolutionToken {
+                            id: 0,
+                            value: problem.timelines[tl_idx].value_name(v.value).to_string(),
+                            start_time,
+                            end_time,
+                            conditions: Vec::new(),
+                        },
+                    ))
+                }
+
+                // Token ids follow the timelines and the order of the tokens within them.
+                let mut ids = HashMap::new();
+                for (tl_idx, tl) in timeline_tokens.iter_mut().enumerate() {
+                    tl.sort_by_key(|(_, t)| ordered_float::OrderedFloat(t.start_time));
+                    for (pos, (token_idx, token)) in tl.iter_mut().enumerate() {
+                        token.id = ids.len();
+                        ids.insert(*token_idx, (token.id, tl_idx, pos));
+                    }
                 }
 
-                for tl in timelines.iter_mut() {
-                    tl.tokens
-                        .sort_by_key(|t| ordered_float::OrderedFloat(t.start_time));
+                // The first satisfied alternative in each condition group, and the chosen link.
+                let mut satisfactions = HashMap::new();
+                for (token_idx, (_, tl_idx, pos)) in ids.iter() {
+                    let tl_tokens = &timeline_tokens[*tl_idx];
+                    let adjacent = |pos: Option<usize>, value: ValueId| {
+                        pos.and_then(|pos| tl_tokens.get(pos))
+                            .filter(|(t, _)| tokens[*t].value == value)
+                            .map(|(_, t)| t.id)
+                    };
+                    let mut token_satisfactions: Vec<ConditionSatisfaction> = Vec::new();
+                    for cond in tokens[*token_idx].conditions.iter().map(|c| &conds[*c]) {
+                        if token_satisfactions.iter().any(|s| s.group == cond.group)
+                            || !is_true(&cond.active)
+                        {
+                            continue;
+                        }
+                        let target = if let Some(value) =
+                            cond.cond_spec.is_timeline_transition_from(*tl_idx)
+                        {
+                            adjacent(pos.checked_sub(1), value)
+                        } else if let Some(value) =
+                            cond.cond_spec.is_timeline_transition_to(*tl_idx)
+                        {
+                            adjacent(Some(pos + 1), value)
+                        } else {
+                            cond.links
+                                .iter()
+                                .find(|(link, t)| is_true(link) && ids.contains_key(t))
+                                .map(|(_, t)| ids[t].0)
+                        };
+                        if let Some(target) = target {
+                            token_satisfactions.push(ConditionSatisfaction {
+                                group: cond.group,
+                                alternative: cond.alternative,
+                                target,
+                                amount: cond.cond_spec.amount,
+                            });
+                        }
+                    }
+                    satisfactions.insert(*token_idx, token_satisfactions);
                 }
 
+                let timelines = problem
+                    .timelines
+                    .iter()
+                    .zip(timeline_tokens)
+                    .map(|(t, tokens)| SolutionTimeline {
+                        name: t.name.clone(),
+                        tokens: tokens
+                            .into_iter()
+                            .map(|(token_idx, mut token)| {
+                                token.conditions = satisfactions.remove(&token_idx).unwrap();
+                                token
+                            })
+                            .collect(),
+                    })
+                    .collect::<Vec<_>>();
+
                 // for tl in 0..timelines.len() {
                 //     println!("Timeline {}", timeline_names[tl]);
                 //     for state in timelines[tl].states.iter().copied() {
@@ -1297,29 +1885,235 @@ pub fn solve(problem: &Problem, settings: &SolverSettings) -> Result<Solution, S
 
                 // println!("SOLUTION {:#?}", timelines);
 
+                let end_of_time = real_value(backend, &end_of_time);
+                if let Some(error) = backend.error() {
+                    return Err(SolverError::Backend(error));
+                }
                 return Ok(Solution {
                     timelines,
-                    end_of_time: z3real_value(&model.eval(&end_of_time, true).unwrap()),
+                    end_of_time,
                 });
             }
 
-            z3::SatResult::Unknown => {
-                panic!("Z3 is undecided.")
+            CheckResult::Unknown => {
+                if is_cancelled(settings) {
+                    return Err(SolverError::Cancelled);
+                }
+                if let Some(error) = backend.error() {
+                    return Err(SolverError::Backend(error));
+                }
+                match subsets {
+                    Some(checks) => subset = checks.next(None)?,
+                    None => return Err(SolverError::Unknown),
+                }
             }
         }
     }
 }
 
+fn real_value<B: Backend>(backend: &B, real: &B::Real) -> f32 {
+    let (num, den) = backend.real_value(real);
+    num as f32 / den as f32
+}
+
+fn token_literal_info<B: Backend>(
+    problem: &CompiledProblem,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    kind: &str,
+    token_idx: usize,
+) -> LiteralInfo {
+    let state = &states[tokens[token_idx].state];
+    let tl_spec = &problem.timelines[state.timeline];
+    LiteralInfo {
+        kind: kind.to_string(),
+        timeline: Some(tl_spec.name.clone()),
+        state: Some(state.state_seq),
+        value: Some(tl_spec.value_name(tokens[token_idx].value).to_string()),
+        ..Default::default()
+    }
+}
+
+fn condition_literal_info<B: Backend>(
+    problem: &CompiledProblem,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    conds: &[Condition<B>],
+    kind: &str,
+    cond_idx: usize,
+) -> LiteralInfo {
+    let spec = conds[cond_idx].cond_spec;
+    let target = &problem.timelines[spec.timeline];
+    LiteralInfo {
+        condition: Some(cond_idx),
+        condition_spec: Some(format!(
+            "{:?} {}.{} amount={}",
+            spec.temporal_relationship,
+            target.name,
+            target.value_name(spec.value),
+            spec.amount
+        )),
+        ..token_literal_info(problem, states, tokens, kind, conds[cond_idx].token_idx)
+    }
+}
+
+// Index the literals that are kept in the states, tokens and conditions.
+fn record_literals<B: Backend>(
+    backend: &B,
+    dump: &mut SmtDump,
+    problem: &CompiledProblem,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    conds: &[Condition<B>],
+    goal_lits: &HashMap<(TimelineId, isize), B::Bool>,
+) {
+    for state in states.iter() {
+        let info = |kind: &str| LiteralInfo {
+            kind: kind.to_string(),
+            timeline: Some(problem.timelines[state.timeline].name.clone()),
+            state: Some(state.state_seq),
+            ..Default::default()
+        };
+        dump.record(backend.real_name(&state.start_time), info("start"));
+        dump.record(backend.real_name(&state.end_time), info("end"));
+        dump.record(backend.bool_name(&state.activate_next), info("nxstate"));
+    }
+    for (token_idx, token) in tokens.iter().enumerate() {
+        if let Some(active) = token.active.as_ref() {
+            dump.record(
+                backend.bool_name(active),
+                token_literal_info(problem, states, tokens, "token", token_idx),
+            );
+        }
+    }
+    for (cond_idx, cond) in conds.iter().enumerate() {
+        if let Some(active) = cond.active.as_ref() {
+            dump.record(
+                backend.bool_name(active),
+                condition_literal_info(problem, states, tokens, conds, "condactive", cond_idx),
+            );
+        }
+    }
+    for ((tl_idx, state_seq), goal_lit) in goal_lits.iter() {
+        dump.record(
+            backend.bool_name(goal_lit),
+            LiteralInfo {
+                kind: "goal".to_string(),
+                timeline: Some(problem.timelines[*tl_idx].name.clone()),
+                state: Some(*state_seq as usize),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+struct HintLit<B: Backend> {
+    lit: B::Bool,
+    timeline: usize,
+    state_seq: usize,
+    time: bool,
+}
+
+// Expand the timelines to the length of the hint's timelines, and return literals that
+// select the hint's value and end time for each state.
 #[allow(clippy::too_many_arguments)]
-fn expand_until<'a, 'z>(
-    problem: &'a Problem,
-    ctx: &'z z3::Context,
-    solver: &z3::Solver,
+fn add_hint<B: Backend>(
+    problem: &CompiledProblem,
+    backend: &B,
+    hint: &Solution,
+    timelines: &mut [Timeline<B>],
+    states: &mut Vec<State<B>>,
+    tokens: &mut Vec<Token<B>>,
+) -> Vec<HintLit<B>> {
+    let mut hint_lits = Vec::new();
+    for hint_timeline in hint.timelines.iter() {
+        let tl_idx = match problem.timelines_by_name.get(&hint_timeline.name) {
+            Some(tl_idx) if !timelines[*tl_idx].facts_only => *tl_idx,
+            _ => continue,
+        };
+
+        let n_states = timelines[tl_idx].states.len();
+        if hint_timeline.tokens.len() > n_states {
+            expand_n(
+                problem,
+                backend,
+                tl_idx,
+                timelines,
+                states,
+                tokens,
+                hint_timeline.tokens.len() - n_states,
+            );
+        }
+
+        for (state_seq, hint_token) in hint_timeline.tokens.iter().enumerate() {
+            let state = &states[timelines[tl_idx].states[state_seq]];
+            let value = problem.timelines[tl_idx]
+                .values_by_name
+                .get(&hint_token.value)
+                .copied();
+            let active = state
+                .tokens
+                .iter()
+                .find(|t| Some(tokens[**t].value) == value)
+                .and_then(|t| tokens[*t].active.as_ref());
+            if let Some(active) = active {
+                let lit = backend.fresh_bool("hint");
+                backend.assert(&backend.implies(&lit, active));
+                hint_lits.push(HintLit {
+                    lit,
+                    timeline: tl_idx,
+                    state_seq,
+                    time: false,
+                });
+            }
+
+            let lit = backend.fresh_bool("hinttime");
+            backend.assert(&backend.implies(
+                &lit,
+                &backend.eq(&state.end_time, &real_approx(backend, hint_token.end_time)),
+            ));
+            hint_lits.push(HintLit {
+                lit,
+                timeline: tl_idx,
+                state_seq,
+                time: true,
+            });
+        }
+    }
+    hint_lits
+}
+
+// The fraction with a small denominator that is closest to the given time.
+fn real_approx<B: Backend>(backend: &B, t: f32) -> B::Real {
+    let (mut h0, mut h1, mut k0, mut k1) = (0_i64, 1_i64, 1_i64, 0_i64);
+    let mut x = t as f64;
+    loop {
+        let a = x.floor();
+        let (h2, k2) = match (
+            (a as i64).checked_mul(h1).and_then(|h| h.checked_add(h0)),
+            (a as i64).checked_mul(k1).and_then(|k| k.checked_add(k0)),
+        ) {
+            (Some(h2), Some(k2)) if k2 <= 10_000 => (h2, k2),
+            _ => break,
+        };
+        (h0, h1, k0, k1) = (h1, h2, k1, k2);
+        if (x - a).abs() < 1e-9 || ((h1 as f64 / k1 as f64) - t as f64).abs() < 1e-6 {
+            break;
+        }
+        x = 1.0 / (x - a);
+    }
+    backend.real_const(h1, k1)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn expand_until<B: Backend>(
+    problem: &CompiledProblem,
+    backend: &B,
     timeline_idx: usize,
-    timelines: &mut Vec<Timeline<'z>>,
-    states: &mut Vec<State<'z>>,
-    tokens: &mut Vec<Token<'a, 'z>>,
-    value: Option<&str>,
+    timelines: &mut [Timeline<B>],
+    states: &mut Vec<State<B>>,
+    tokens: &mut Vec<Token<B>>,
+    value: Option<ValueId>,
 ) -> bool {
     let n = if let Some(value) = value {
         assert!(!timelines[timeline_idx].states.is_empty());
@@ -1330,7 +2124,7 @@ fn expand_until<'a, 'z>(
             .map(|t| tokens[*t].value)
             .collect::<Vec<_>>();
 
-        if let Some(n) = distance_to(&problem.timelines[timeline_idx], &prev_values, value) {
+        if let Some(n) = problem.transitions[timeline_idx].distance(&prev_values, value) {
             n
         } else {
             return false;
@@ -1340,59 +2134,46 @@ fn expand_until<'a, 'z>(
     };
 
     assert!(n > 0);
-    expand_n(
-        problem,
-        ctx,
-        solver,
-        timeline_idx,
-        timelines,
-        states,
-        tokens,
-        n,
-    );
+    expand_n(problem, backend, timeline_idx, timelines, states, tokens, n);
     true
 }
 
 #[allow(clippy::too_many_arguments)]
-fn expand_n<'a, 'z>(
-    problem: &'a Problem,
-    ctx: &'z z3::Context,
-    solver: &z3::Solver,
+fn expand_n<B: Backend>(
+    problem: &CompiledProblem,
+    backend: &B,
     timeline_idx: usize,
-    timelines: &mut Vec<Timeline<'z>>,
-    states: &mut Vec<State<'z>>,
-    tokens: &mut Vec<Token<'a, 'z>>,
+    timelines: &mut [Timeline<B>],
+    states: &mut Vec<State<B>>,
+    tokens: &mut Vec<Token<B>>,
     n: usize,
 ) {
     for _ in 0..n {
-        let (state_seq, start_time, prev_values) =
-            if let Some(prev_state_idx) = timelines[timeline_idx].states.last().copied() {
-                let prev_state = &states[prev_state_idx];
-                let prev_values = prev_state
-                    .tokens
-                    .iter()
-                    .map(|t| tokens[*t].value)
-                    .collect::<Vec<_>>();
-                let seq = prev_state.state_seq + 1;
+        let (state_seq, start_time, prev_values) = if let Some(prev_state_idx) =
+            timelines[timeline_idx].states.last().copied()
+        {
+            let prev_state = &states[prev_state_idx];
+            let prev_values = prev_state
+                .tokens
+                .iter()
+                .map(|t| tokens[*t].value)
+                .collect::<Vec<_>>();
+            let seq = prev_state.state_seq + 1;
 
-                (seq, prev_state.end_time.clone(), Some(prev_values))
-            } else {
-                (
-                    0,
-                    Real::fresh_const(
-                        ctx,
-                        &format!("t_{}_init_", problem.timelines[timeline_idx].name),
-                    ),
-                    None,
-                )
-            };
+            (seq, prev_state.end_time.clone(), Some(prev_values))
+        } else {
+            (
+                0,
+                backend.fresh_real(&format!("t_{}_init_", problem.timelines[timeline_idx].name)),
+                None,
+            )
+        };
 
-        let end_time =
-            Real::fresh_const(ctx, &format!("t_{}_", problem.timelines[timeline_idx].name));
+        let end_time = backend.fresh_real(&format!("t_{}_", problem.timelines[timeline_idx].name));
 
         let state_idx = states.len();
         let token_start_idx = tokens.len();
-        let values = next_values_from(&problem.timelines[timeline_idx], prev_values.as_deref());
+        let values = problem.transitions[timeline_idx].next_values(prev_values.as_deref());
 
         // println!(
         //     "adding tl:{} state:{} values{:?}",
@@ -1407,23 +2188,20 @@ fn expand_n<'a, 'z>(
                 // let active = if prev_unique && values.len() == 1 {
                 //     None // only one chocie heree
                 // } else {
-                //     Some(Bool::fresh_const(ctx, "x"))
+                //     Some(backend.fresh_bool("x"))
                 // };
 
-                let active = Some(Bool::fresh_const(
-                    ctx,
-                    &format!(
-                        "state_{}_{}_{}_",
-                        problem.timelines[timeline_idx].name,
-                        timelines[timeline_idx].states.len(),
-                        idx
-                    ),
-                ));
+                let active = Some(backend.fresh_bool(&format!(
+                    "state_{}_{}_{}_",
+                    problem.timelines[timeline_idx].name,
+                    timelines[timeline_idx].states.len(),
+                    idx
+                )));
 
                 Token {
                     active,
                     state: state_idx,
-                    value,
+                    value: *value,
                     fact: false,
                     conditions: Vec::new(),
                 }
@@ -1444,21 +2222,21 @@ fn expand_n<'a, 'z>(
             .filter_map(|t| t.active.as_ref().map(|b| (b, 1)))
             .collect::<Vec<_>>();
         if am1.len() > 1 {
-            solver.assert(&Bool::pb_le(ctx, &am1, 1));
+            backend.assert(&backend.pb_le(&am1, 1));
         }
 
         let tokens_active = state_tokens
             .iter()
             .map(|t| t.active.as_ref().unwrap())
             .collect::<Vec<_>>();
-        let state_active = Bool::or(ctx, &tokens_active);
+        let state_active = backend.or(&tokens_active);
 
         if state_seq > 0 {
             // for state_token in state_tokens.iter() {
             //     // If a token is active, the previous state must also be active.
             //     let mut clause = Vec::new();
             //     if let Some(active) = state_token.active.as_ref() {
-            //         clause.push(Bool::not(active));
+            //         clause.push(backend.not(active));
             //     }
 
             //     // any in the previous state
@@ -1474,15 +2252,12 @@ fn expand_n<'a, 'z>(
 
             //     if !any_const {
             //         let clause_refs = clause.iter().collect::<Vec<_>>();
-            //         solver.assert(&Bool::or(ctx, &clause_refs));
+            //         backend.assert(&backend.or(&clause_refs));
             //     }
             // }
 
             let prev_state_idx = timelines[timeline_idx].states[state_seq - 1];
-            solver.assert(&Bool::implies(
-                &state_active,
-                &states[prev_state_idx].active,
-            ))
+            backend.assert(&backend.implies(&state_active, &states[prev_state_idx].active))
         }
 
         let token_idxs = state_tokens
@@ -1499,161 +2274,342 @@ fn expand_n<'a, 'z>(
             end_time,
             timeline: timeline_idx,
             active: state_active,
-            activate_next: Bool::fresh_const(ctx, "nxstate"),
+            activate_next: backend.fresh_bool("nxstate"),
             can_expand: true,
         });
         timelines[timeline_idx].states.push(state_idx);
     }
 }
 
-fn next_values_from<'a>(
-    timeline: &'a problem::Timeline,
-    prev_values: Option<&[&'a str]>,
-) -> HashSet<&'a str> {
-    // Want to prune the set of possible token types for the next,
-    // based on the possible token types in the previous state and the
-    // transitions conditions, i.e. the conditions on the immediately previous
-    // and next (Allen relations MetBy and Meets) tokens on the same timeline.
-
-    fn set_from_ok<'a>(
-        timeline: &'a problem::Timeline,
-        prev_values: Option<&[&str]>,
-    ) -> HashSet<&'a str> {
-        let has_required_previous_values = |tt: &TokenType| -> bool {
-            !tt.conditions.iter().any(|cs| {
-                cs.iter().all(|c| {
-                    if let Some(v1) = c.is_timeline_transition_from(&timeline.name) {
-                        !prev_values
-                            .iter()
-                            .flat_map(|pvs| pvs.iter())
-                            .any(|v2| &v1 == v2)
-                    } else {
-                        false
-                    }
-                })
-            })
-        };
+fn can_expand(
+    problem: &CompiledProblem,
+    timeline_idx: TimelineId,
+    start_values: &[ValueId],
+    goal_value: ValueId,
+) -> bool {
+    problem.transitions[timeline_idx]
+        .distance(start_values, goal_value)
+        .is_some()
+}
 
-        // Remove token types where we have a condition where all alternatives are timeline transitions
-        // from missing values,
-        timeline
-            .token_types
-            .iter()
-            .filter_map(|tt| has_required_previous_values(tt).then(|| tt.value.as_str()))
-            .collect::<HashSet<_>>()
-    }
+struct ResourceConstraint<B: Backend> {
+    capacity: Option<u32>,
+    pooled: bool,
+    encoding: ResourceEncoding,
+    users: Vec<(Option<B::Bool>, usize, u32)>,
+    integrated: usize,
+    // Users whose start time has been found to overload the resource (lazy encoding).
+    lazy_points: Vec<usize>,
+    // When diagnosing, the capacity only holds if this literal is assumed.
+    selector: Option<B::Bool>,
+}
 
-    fn set_to_ok_value<'a>(
-        timeline: &'a problem::Timeline,
-        prev_value: &str,
-    ) -> Option<HashSet<&'a str>> {
-        timeline
-            .token_types
-            .iter()
-            .find(|v| v.value.as_str() == prev_value)
-            .and_then(|tt| {
-                tt.conditions
-                    .iter()
-                    .map(|cs| {
-                        let mut set = HashSet::new();
-                        for c in cs.iter() {
-                            if let Some(v) = c.is_timeline_transition_to(&timeline.name) {
-                                set.insert(v);
-                            } else {
-                                return None;
-                            }
-                        }
-                        Some(set)
-                    })
-                    .reduce(|a, b| match (a, b) {
-                        (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
-                        _ => None,
-                    })
-                    .flatten()
-            })
+impl<B: Backend> ResourceConstraint<B> {
+    fn assert(&self, backend: &B, constraint: &B::Bool) {
+        match self.selector.as_ref() {
+            Some(selector) => backend.assert(&backend.implies(selector, constraint)),
+            None => backend.assert(constraint),
+        }
     }
+}
 
-    fn set_to_ok<'a>(
-        timeline: &'a problem::Timeline,
-        prev_values: Option<&[&str]>,
-    ) -> Option<HashSet<&'a str>> {
-        if let Some(prev_values) = prev_values {
-            prev_values
-                .iter()
-                .map(|pv| set_to_ok_value(timeline, pv))
-                .fold(Some(HashSet::new()), |a, b| match (a, b) {
-                    (Some(a), Some(b)) => Some(a.union(&b).copied().collect()),
-                    _ => None,
-                })
-        } else {
-            None
+impl<B: Backend> Default for ResourceConstraint<B> {
+    fn default() -> Self {
+        ResourceConstraint {
+            capacity: None,
+            pooled: false,
+            encoding: ResourceEncoding::default(),
+            users: Vec::new(),
+            integrated: 0,
+            lazy_points: Vec::new(),
+            selector: None,
         }
     }
+}
 
-    let set1 = set_from_ok(timeline, prev_values);
-    let mut set = if let Some(set2) = set_to_ok(timeline, prev_values) {
-        set1.intersection(&set2).copied().collect()
-    } else {
-        set1
-    };
+// Returns the number of pairwise exclusions and pseudo-boolean constraints added.
+fn encode_resource<B: Backend>(
+    backend: &B,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    rc: &ResourceConstraint<B>,
+) -> (usize, usize) {
+    let capacity = rc.capacity.unwrap();
+    let mut n_exclusions = 0;
+    let mut n_pbs = 0;
+
+    if capacity == 1 && rc.encoding == ResourceEncoding::Lazy {
+        // Exclusions between pairs of users do not change when users are added.
+        return (n_exclusions, n_pbs);
+    }
 
-    // Cannot transition to the same value.
-    if let Some(prev_values) = prev_values {
-        if prev_values.len() == 1 {
-            set.remove(&prev_values[0]);
+    if capacity == 1 && rc.encoding == ResourceEncoding::Pairwise {
+        // Special-case parwise exclusion, which is probably faster than
+        // the long pseudo-boolean constraint needed for capacity >=2
+        for i in 0..rc.users.len() {
+            let start_from = (i + 1).max(rc.integrated);
+            for j in start_from..rc.users.len() {
+                rc.assert(
+                    backend,
+                    &pairwise_exclusion(backend, states, tokens, rc, i, j),
+                );
+                n_exclusions += 1;
+            }
         }
+        return (n_exclusions, n_pbs);
     }
 
-    set
+    // The sums in the pseudo-boolean constraints change when users are added, so they
+    // are added again for all users. The old constraints are subsumed by the new ones.
+    match rc.encoding {
+        ResourceEncoding::Pairwise | ResourceEncoding::PseudoBoolean => {
+            for i in 0..rc.users.len() {
+                rc.assert(
+                    backend,
+                    &overlap_pb(backend, states, tokens, rc, i, rc.pooled),
+                );
+                n_pbs += 1;
+            }
+        }
+        ResourceEncoding::TaskInterval => {
+            for i in 0..rc.users.len() {
+                rc.assert(backend, &overlap_pb(backend, states, tokens, rc, i, true));
+                n_pbs += 1;
+            }
+            // The energy constraints are redundant, so it is enough to add them for the new intervals.
+            for i in 0..rc.users.len() {
+                for j in 0..rc.users.len() {
+                    if i != j && (i >= rc.integrated || j >= rc.integrated) {
+                        rc.assert(
+                            backend,
+                            &task_interval_energy(backend, states, tokens, rc, i, j),
+                        );
+                    }
+                }
+            }
+        }
+        ResourceEncoding::Lazy => {
+            for i in rc.lazy_points.iter().copied() {
+                rc.assert(backend, &overlap_pb(backend, states, tokens, rc, i, true));
+                n_pbs += 1;
+            }
+        }
+    }
+
+    (n_exclusions, n_pbs)
 }
 
-fn can_expand(timeline: &problem::Timeline, start_values: &[&str], goal_value: &str) -> bool {
-    distance_to(timeline, start_values, goal_value).is_some()
+fn pairwise_exclusion<B: Backend>(
+    backend: &B,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    rc: &ResourceConstraint<B>,
+    i: usize,
+    j: usize,
+) -> B::Bool {
+    let (link1, token1, amount1) = &rc.users[i];
+    let (link2, token2, amount2) = &rc.users[j];
+
+    assert!(*amount1 == 1);
+    assert!(*amount2 == 1);
+
+    let mut alts = vec![
+        backend.le(
+            &states[tokens[*token1].state].end_time,
+            &states[tokens[*token2].state].start_time,
+        ),
+        backend.le(
+            &states[tokens[*token2].state].end_time,
+            &states[tokens[*token1].state].start_time,
+        ),
+    ];
+
+    if let Some(link1) = link1 {
+        alts.push(backend.not(link1));
+    }
+    if let Some(link2) = link2 {
+        alts.push(backend.not(link2));
+    }
+
+    let alts_refs = alts.iter().collect::<Vec<_>>();
+    backend.or(&alts_refs)
 }
 
-fn distance_to(
-    timeline: &problem::Timeline,
-    start_values: &[&str],
-    goal_value: &str,
-) -> Option<usize> {
-    println!(
-        "Finding distance from {:?} to  {:?}",
-        start_values, goal_value
-    );
-    let mut visited_values = HashSet::new();
-    let mut current_values = start_values.iter().copied().collect::<HashSet<_>>();
+// The total usage of the users overlapping user `i` is within the capacity.
+// With `event_point`, only the users that are active at the start time of `i` are counted,
+// which is exact for cumulative resources (but pairwise overlap is not).
+fn overlap_pb<B: Backend>(
+    backend: &B,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    rc: &ResourceConstraint<B>,
+    i: usize,
+    event_point: bool,
+) -> B::Bool {
+    let (link1, token1, _) = &rc.users[i];
+    let start1 = &states[tokens[*token1].state].start_time;
+    let end1 = &states[tokens[*token1].state].end_time;
+
+    let overlaps = rc
+        .users
+        .iter()
+        .map(|(link2, token2, amount2)| {
+            let start2 = &states[tokens[*token2].state].start_time;
+            let end2 = &states[tokens[*token2].state].end_time;
 
-    let mut steps = 1;
-    loop {
-        let mut next_values = HashSet::new();
-        let reachable = next_values_from(
-            timeline,
-            Some(&current_values.iter().copied().collect::<Vec<_>>()),
-        );
-        println!("Reachable {:?}", reachable);
-        for next in reachable {
-            if goal_value == next {
-                return Some(steps);
-            }
+            let overlap_start = if event_point {
+                backend.le(start2, start1)
+            } else {
+                backend.lt(start2, end1)
+            };
 
-            if visited_values.insert(next) {
-                next_values.insert(next);
-            }
+            let overlap = backend.and(&[
+                &link2.clone().unwrap_or_else(|| backend.bool_const(true)),
+                &backend.lt(start1, end2),
+                &overlap_start,
+            ]);
+
+            (overlap, *amount2)
+        })
+        .collect::<Vec<_>>();
+
+    let overlaps_refs = overlaps
+        .iter()
+        .map(|(o, c)| (o, *c as i32))
+        .collect::<Vec<_>>();
+
+    let pb = backend.pb_le(&overlaps_refs, rc.capacity.unwrap() as i32);
+    if let Some(link1) = link1 {
+        backend.implies(link1, &pb)
+    } else {
+        pb
+    }
+}
+
+// The energy (usage times duration) of the users that lie within the interval
+// from the start of user `i` to the end of user `j` fits in the interval.
+// This is implied by the event-point constraints, but helps to prune the search.
+fn task_interval_energy<B: Backend>(
+    backend: &B,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    rc: &ResourceConstraint<B>,
+    i: usize,
+    j: usize,
+) -> B::Bool {
+    let link = |k: usize| {
+        rc.users[k]
+            .0
+            .clone()
+            .unwrap_or_else(|| backend.bool_const(true))
+    };
+    let start = |k: usize| &states[tokens[rc.users[k].1].state].start_time;
+    let end = |k: usize| &states[tokens[rc.users[k].1].state].end_time;
+    let zero = backend.real_const(0, 1);
+
+    let energies = (0..rc.users.len())
+        .map(|k| {
+            let inside = backend.and(&[
+                &link(k),
+                &backend.le(start(i), start(k)),
+                &backend.le(end(k), end(j)),
+            ]);
+            let energy = backend.mul(&[
+                &backend.real_const(rc.users[k].2 as i64, 1),
+                &backend.sub(&[end(k), start(k)]),
+            ]);
+            backend.ite(&inside, &energy, &zero)
+        })
+        .collect::<Vec<_>>();
+    let energy_refs = energies.iter().collect::<Vec<_>>();
+
+    let available = backend.mul(&[
+        &backend.real_const(rc.capacity.unwrap() as i64, 1),
+        &backend.sub(&[end(j), start(i)]),
+    ]);
+
+    backend.implies(
+        &backend.and(&[&link(i), &link(j), &backend.lt(start(i), end(j))]),
+        &backend.le(&backend.add(&energy_refs), &available),
+    )
+}
+
+// Check the model for overloaded resources using the lazy encoding, and add
+// the constraints that are violated. Returns the number of pairwise exclusions
+// and pseudo-boolean constraints added.
+fn add_violated_resource_constraints<B: Backend>(
+    backend: &B,
+    states: &[State<B>],
+    tokens: &[Token<B>],
+    resource_constraints: &mut BTreeMap<usize, ResourceConstraint<B>>,
+) -> (usize, usize) {
+    let time = |real: &B::Real| backend.real_value(real);
+    let mut n_exclusions = 0;
+    let mut n_pbs = 0;
+    for rc in resource_constraints.values_mut() {
+        if rc.encoding != ResourceEncoding::Lazy
+            || rc.selector.as_ref().map(|s| !backend.bool_value(s)) == Some(true)
+        {
+            continue;
         }
 
-        if next_values.is_empty() {
-            return None;
+        let active_users = rc
+            .users
+            .iter()
+            .enumerate()
+            .filter(|(_, (link, _, _))| {
+                link.as_ref().map(|l| backend.bool_value(l)).unwrap_or(true)
+            })
+            .map(|(i, (_, token, amount))| {
+                let state = &states[tokens[*token].state];
+                (i, time(&state.start_time), time(&state.end_time), *amount)
+            })
+            .collect::<Vec<_>>();
+
+        if rc.capacity.unwrap() == 1 {
+            for (a, (i, start1, end1, _)) in active_users.iter().enumerate() {
+                for (j, start2, end2, _) in active_users[a + 1..].iter() {
+                    if time_lt(*start1, *end2) && time_lt(*start2, *end1) {
+                        rc.assert(
+                            backend,
+                            &pairwise_exclusion(backend, states, tokens, rc, *i, *j),
+                        );
+                        n_exclusions += 1;
+                    }
+                }
+            }
+            continue;
         }
 
-        current_values = next_values;
-        steps += 1;
+        for (i, start1, _, _) in active_users.iter() {
+            if rc.lazy_points.contains(i) {
+                continue;
+            }
+            let usage = active_users
+                .iter()
+                .filter(|(_, start2, end2, _)| time_le(*start2, *start1) && time_lt(*start1, *end2))
+                .map(|(_, _, _, amount)| *amount)
+                .sum::<u32>();
+
+            if usage > rc.capacity.unwrap() {
+                rc.lazy_points.push(*i);
+                rc.assert(backend, &overlap_pb(backend, states, tokens, rc, *i, true));
+                n_pbs += 1;
+            }
+        }
     }
+    (n_exclusions, n_pbs)
 }
 
-#[derive(Default)]
-struct ResourceConstraint<'z3> {
-    capacity: Option<u32>,
-    users: Vec<(Option<Bool<'z3>>, usize, u32)>,
-    integrated: usize,
-    closed: bool,
+// Compare rational numbers with positive denominators.
+fn time_cmp(a: (i64, i64), b: (i64, i64)) -> std::cmp::Ordering {
+    (a.0 as i128 * b.1 as i128).cmp(&(b.0 as i128 * a.1 as i128))
+}
+
+fn time_le(a: (i64, i64), b: (i64, i64)) -> bool {
+    time_cmp(a, b).is_le()
+}
+
+fn time_lt(a: (i64, i64), b: (i64, i64)) -> bool {
+    time_cmp(a, b).is_lt()
 }
@@ -0,0 +1,477 @@
+use crate::problem::{
+    Condition, Problem, Solution, SolutionToken, TemporalRelationship, TokenSpec, TokenTime,
+};
+use std::collections::HashMap;
+
+// Checks a solution against the problem definition directly, without the
+// compiled problem or the solver's encoding, so that it can catch errors in
+// either of them. Times are compared with a small tolerance, since solution
+// times are rounded to f32.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    // The problem has a timeline that is not in the solution.
+    MissingTimeline {
+        timeline: String,
+    },
+    // The solution has a timeline that is not in the problem.
+    UnknownTimeline {
+        timeline: String,
+    },
+    // A token does not start where the previous token ends.
+    Gap {
+        timeline: String,
+        token: usize,
+    },
+    // A token is not a fact, and has no token type.
+    UnknownValue {
+        timeline: String,
+        token: usize,
+    },
+    // A token's value cannot follow the previous token's value.
+    Transition {
+        timeline: String,
+        token: usize,
+    },
+    Duration {
+        timeline: String,
+        token: usize,
+    },
+    // None of the alternatives in a token's condition group are satisfied.
+    Condition {
+        timeline: String,
+        token: usize,
+        group: usize,
+    },
+    // The tokens using a resource token exceed its capacity.
+    Capacity {
+        timeline: String,
+        token: usize,
+    },
+    // A fact has no token with its value at its fixed times.
+    Fact {
+        timeline: String,
+        value: String,
+    },
+    // The timeline does not end in a goal value.
+    Goal {
+        timeline: String,
+        value: String,
+    },
+    // Two tokens have the same id.
+    DuplicateId {
+        id: usize,
+    },
+    // A recorded condition satisfaction does not refer to a token that satisfies the condition.
+    Satisfaction {
+        timeline: String,
+        token: usize,
+        group: usize,
+    },
+}
+
+const EPSILON: f32 = 1e-3;
+
+fn tolerance(a: f32, b: f32) -> f32 {
+    EPSILON * (1.0 + a.abs().max(b.abs()))
+}
+
+pub(crate) fn time_eq(a: f32, b: f32) -> bool {
+    (a - b).abs() <= tolerance(a, b)
+}
+
+pub(crate) fn time_le(a: f32, b: f32) -> bool {
+    a <= b + tolerance(a, b)
+}
+
+pub(crate) fn time_lt(a: f32, b: f32) -> bool {
+    a < b - tolerance(a, b)
+}
+
+// Token indices by timeline index.
+type TokenRef = (usize, usize);
+
+pub fn validate_solution(problem: &Problem, solution: &Solution) -> Vec<Violation> {
+    let _p = hprof::enter("validate_solution");
+    let mut violations = Vec::new();
+
+    for tl in solution.timelines.iter() {
+        if !problem.timelines.iter().any(|t| t.name == tl.name) {
+            violations.push(Violation::UnknownTimeline {
+                timeline: tl.name.clone(),
+            });
+        }
+    }
+
+    // The solution's tokens, in the order of the problem's timelines.
+    let empty = Vec::new();
+    let tokens = problem
+        .timelines
+        .iter()
+        .map(
+            |tl| match solution.timelines.iter().find(|t| t.name == tl.name) {
+                Some(t) => &t.tokens,
+                None => {
+                    violations.push(Violation::MissingTimeline {
+                        timeline: tl.name.clone(),
+                    });
+                    &empty
+                }
+            },
+        )
+        .collect::<Vec<&Vec<SolutionToken>>>();
+    let timelines_by_name = problem
+        .timelines
+        .iter()
+        .enumerate()
+        .map(|(i, tl)| (tl.name.as_str(), i))
+        .collect::<HashMap<_, _>>();
+
+    // FACTS, TOKEN TYPES AND DURATIONS
+
+    let mut specs: Vec<Vec<Option<TokenSpec>>> = Vec::new();
+    for (tl_idx, tl) in problem.timelines.iter().enumerate() {
+        let mut tl_specs = tokens[tl_idx].iter().map(|_| None).collect::<Vec<_>>();
+
+        for static_token in tl.static_tokens.iter() {
+            let (start_time, end_time) = match static_token.const_time {
+                TokenTime::Fact(start_time, end_time) => (start_time, end_time),
+                TokenTime::Goal => continue,
+            };
+            let fixed = |time: Option<usize>, t: f32| time.map(|x| time_eq(x as f32, t));
+            let matching = tokens[tl_idx].iter().enumerate().position(|(i, t)| {
+                tl_specs[i].is_none()
+                    && t.value == static_token.value
+                    && fixed(start_time, t.start_time).unwrap_or(true)
+                    && fixed(end_time, t.end_time).unwrap_or(true)
+            });
+            match matching {
+                Some(i) => tl_specs[i] = Some(TokenSpec::of_fact(static_token)),
+                None => violations.push(Violation::Fact {
+                    timeline: tl.name.clone(),
+                    value: static_token.value.clone(),
+                }),
+            }
+        }
+
+        for (i, token) in tokens[tl_idx].iter().enumerate() {
+            if tl_specs[i].is_some() {
+                continue;
+            }
+            // The first token type with a given value is the one that is used.
+            match tl.token_types.iter().find(|tt| tt.value == token.value) {
+                Some(token_type) => {
+                    let (min, max) = token_type.duration_limits;
+                    let duration = token.end_time - token.start_time;
+                    if !time_le(min as f32, duration)
+                        || max
+                            .map(|max| !time_le(duration, max as f32))
+                            .unwrap_or(false)
+                    {
+                        violations.push(Violation::Duration {
+                            timeline: tl.name.clone(),
+                            token: i,
+                        });
+                    }
+                    tl_specs[i] = Some(TokenSpec::of_token_type(token_type));
+                }
+                None => violations.push(Violation::UnknownValue {
+                    timeline: tl.name.clone(),
+                    token: i,
+                }),
+            }
+        }
+        specs.push(tl_specs);
+    }
+
+    // CONTIGUITY, TRANSITIONS AND GOALS
+
+    for (tl_idx, tl) in problem.timelines.iter().enumerate() {
+        // Timelines without token types only have facts, which do not need to be contiguous.
+        if tl.token_types.is_empty() {
+            continue;
+        }
+        let tl_tokens = tokens[tl_idx];
+        for i in 1..tl_tokens.len() {
+            let (prev, token) = (&tl_tokens[i - 1], &tl_tokens[i]);
+            if !time_eq(prev.end_time, token.start_time) {
+                violations.push(Violation::Gap {
+                    timeline: tl.name.clone(),
+                    token: i,
+                });
+            }
+
+            // Condition groups where all alternatives are transitions on this timeline.
+            let conditions = |i: usize| specs[tl_idx][i].iter().flat_map(|s| s.conditions.iter());
+            let allowed_previous = conditions(i).all(|group| {
+                group
+                    .iter()
+                    .map(|c| c.is_timeline_transition_from(&tl.name))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|values| values.contains(&prev.value.as_str()))
+                    .unwrap_or(true)
+            });
+            let allowed_next = conditions(i - 1).all(|group| {
+                group
+                    .iter()
+                    .map(|c| c.is_timeline_transition_to(&tl.name))
+                    .collect::<Option<Vec<_>>>()
+                    .map(|values| values.contains(&token.value.as_str()))
+                    .unwrap_or(true)
+            });
+            if prev.value == token.value || !allowed_previous || !allowed_next {
+                violations.push(Violation::Transition {
+                    timeline: tl.name.clone(),
+                    token: i,
+                });
+            }
+        }
+
+        for goal in tl
+            .static_tokens
+            .iter()
+            .filter(|t| matches!(t.const_time, TokenTime::Goal))
+        {
+            if tl_tokens.last().map(|t| &t.value) != Some(&goal.value) {
+                violations.push(Violation::Goal {
+                    timeline: tl.name.clone(),
+                    value: goal.value.clone(),
+                });
+            }
+        }
+    }
+
+    // RECORDED CONDITION SATISFACTIONS
+
+    // Solutions without recorded satisfactions do not need token ids.
+    let recorded = tokens
+        .iter()
+        .any(|tl| tl.iter().any(|t| !t.conditions.is_empty()));
+    let mut tokens_by_id: HashMap<usize, TokenRef> = HashMap::new();
+    for (tl_idx, tl_tokens) in tokens.iter().enumerate() {
+        for (i, token) in tl_tokens.iter().enumerate() {
+            if tokens_by_id.insert(token.id, (tl_idx, i)).is_some() && recorded {
+                violations.push(Violation::DuplicateId { id: token.id });
+            }
+        }
+    }
+    for (tl_idx, tl) in problem.timelines.iter().enumerate() {
+        for (i, token) in tokens[tl_idx].iter().enumerate() {
+            let spec = match specs[tl_idx][i].as_ref() {
+                Some(spec) => spec,
+                None => continue,
+            };
+            for satisfaction in token.conditions.iter() {
+                let condition = spec
+                    .conditions
+                    .get(satisfaction.group)
+                    .and_then(|group| group.get(satisfaction.alternative));
+                let target = tokens_by_id.get(&satisfaction.target);
+                let holds = match (condition, target) {
+                    (Some(condition), Some((target_tl, j))) => {
+                        let target_tokens = tokens[*target_tl];
+                        problem.timelines[*target_tl].name == condition.timeline_ref
+                            && target_tokens[*j].value == condition.value
+                            && satisfaction.amount == condition.amount
+                            && holds(
+                                &condition.temporal_relationship,
+                                token,
+                                &target_tokens[*j],
+                                *j + 1 < target_tokens.len(),
+                            )
+                    }
+                    _ => false,
+                };
+                if !holds {
+                    violations.push(Violation::Satisfaction {
+                        timeline: tl.name.clone(),
+                        token: i,
+                        group: satisfaction.group,
+                    });
+                }
+            }
+        }
+    }
+
+    // CONDITIONS
+
+    let candidates = |condition: &Condition, this: &SolutionToken| -> Vec<TokenRef> {
+        let target_tl = match timelines_by_name.get(condition.timeline_ref.as_str()) {
+            Some(tl) => *tl,
+            None => return Vec::new(),
+        };
+        let target_tokens = tokens[target_tl];
+        (0..target_tokens.len())
+            .filter(|j| {
+                let target = &target_tokens[*j];
+                target.value == condition.value
+                    && holds(
+                        &condition.temporal_relationship,
+                        this,
+                        target,
+                        *j + 1 < target_tokens.len(),
+                    )
+            })
+            .map(|j| (target_tl, j))
+            .collect()
+    };
+
+    // Condition groups that use resources, as alternatives of (amount, target tokens).
+    let mut resource_groups: Vec<(TokenRef, Vec<(u32, TokenRef)>)> = Vec::new();
+    for (tl_idx, tl) in problem.timelines.iter().enumerate() {
+        for (i, token) in tokens[tl_idx].iter().enumerate() {
+            let spec = match specs[tl_idx][i].as_ref() {
+                Some(spec) => spec,
+                None => continue,
+            };
+            for (group_idx, group) in spec.conditions.iter().enumerate() {
+                let mut alternatives = Vec::new();
+                let mut satisfied = false;
+                for condition in group.iter() {
+                    let targets = candidates(condition, token);
+                    if condition.amount == 0 && !targets.is_empty() {
+                        satisfied = true;
+                        break;
+                    }
+                    alternatives.extend(targets.into_iter().map(|t| (condition.amount, t)));
+                }
+                if satisfied {
+                    continue;
+                }
+                if alternatives.is_empty() {
+                    violations.push(Violation::Condition {
+                        timeline: tl.name.clone(),
+                        token: i,
+                        group: group_idx,
+                    });
+                    continue;
+                }
+                // A recorded satisfaction fixes the alternative that the solver used.
+                let used = token
+                    .conditions
+                    .iter()
+                    .filter(|s| s.group == group_idx)
+                    .filter_map(|s| Some((s.amount, *tokens_by_id.get(&s.target)?)))
+                    .find(|a| alternatives.contains(a));
+                if let Some(used) = used {
+                    alternatives = vec![used];
+                }
+                resource_groups.push(((tl_idx, i), alternatives));
+            }
+        }
+    }
+
+    // CAPACITIES
+
+    // Each resource group uses one of its alternatives, so search for a choice that
+    // respects all capacities, starting from the first alternative in each group.
+    // The search is exact for solutions with recorded satisfactions, and otherwise
+    // nothing is reported if it runs out of budget.
+    let capacity = |(tl, i): TokenRef| specs[tl][i].as_ref().map(|s| s.capacity).unwrap_or(0);
+    let token = |(tl, i): TokenRef| &tokens[tl][i];
+    let exceeds = |users: &[(TokenRef, u32)], target: TokenRef| {
+        users.iter().any(|(user, _)| {
+            let t = token(*user).start_time;
+            let usage = users
+                .iter()
+                .filter(|(other, _)| {
+                    time_le(token(*other).start_time, t) && time_lt(t, token(*other).end_time)
+                })
+                .map(|(_, amount)| *amount)
+                .sum::<u32>();
+            usage > capacity(target)
+        })
+    };
+
+    let mut usage: HashMap<TokenRef, Vec<(TokenRef, u32)>> = HashMap::new();
+    let mut choices = vec![0; resource_groups.len()];
+    let mut group = 0;
+    let mut budget = 100_000;
+    let found = loop {
+        if group == resource_groups.len() {
+            break Some(true);
+        }
+        if budget == 0 {
+            println!("The capacity check ran out of budget");
+            break None;
+        }
+        budget -= 1;
+
+        let (user, alternatives) = &resource_groups[group];
+        if choices[group] < alternatives.len() {
+            let (amount, target) = alternatives[choices[group]];
+            let users = usage.entry(target).or_default();
+            users.push((*user, amount));
+            if exceeds(users, target) {
+                users.pop();
+                choices[group] += 1;
+            } else {
+                group += 1;
+            }
+        } else {
+            if group == 0 {
+                break Some(false);
+            }
+            choices[group] = 0;
+            group -= 1;
+            let (_, target) = resource_groups[group].1[choices[group]];
+            usage.get_mut(&target).unwrap().pop();
+            choices[group] += 1;
+        }
+    };
+
+    if found == Some(false) {
+        // Report the resources that are exceeded when every group uses its first alternative.
+        let mut usage: HashMap<TokenRef, Vec<(TokenRef, u32)>> = HashMap::new();
+        for (user, alternatives) in resource_groups.iter() {
+            let (amount, target) = alternatives[0];
+            usage.entry(target).or_default().push((*user, amount));
+        }
+        let mut exceeded = usage
+            .iter()
+            .filter(|(target, users)| exceeds(users, **target))
+            .map(|(target, _)| *target)
+            .collect::<Vec<_>>();
+        exceeded.sort_unstable();
+        for (tl, i) in exceeded {
+            violations.push(Violation::Capacity {
+                timeline: problem.timelines[tl].name.clone(),
+                token: i,
+            });
+        }
+    }
+
+    violations
+}
+
+// Does the temporal relationship from `this` token to the `target` token hold?
+fn holds(
+    relationship: &TemporalRelationship,
+    this: &SolutionToken,
+    target: &SolutionToken,
+    target_has_next: bool,
+) -> bool {
+    match relationship {
+        TemporalRelationship::MetByTransitionFrom => {
+            target_has_next && time_eq(target.end_time, this.start_time)
+        }
+        TemporalRelationship::MetBy => time_eq(target.end_time, this.start_time),
+        TemporalRelationship::Meets => time_eq(target.start_time, this.end_time),
+        TemporalRelationship::Starts => time_eq(target.start_time, this.start_time),
+        TemporalRelationship::StartsAfter => time_le(target.start_time, this.start_time),
+        TemporalRelationship::Cover => {
+            time_le(target.start_time, this.start_time) && time_le(this.end_time, target.end_time)
+        }
+        TemporalRelationship::StartPrecond => {
+            time_le(target.start_time + 1.0, this.start_time)
+                && time_le(this.start_time, target.end_time)
+        }
+        TemporalRelationship::StartEffect => {
+            time_le(target.start_time, this.start_time)
+                && time_le(this.start_time + 1.0, target.end_time)
+        }
+        TemporalRelationship::Equal => {
+            time_eq(this.start_time, target.start_time) && time_eq(this.end_time, target.end_time)
+        }
+    }
+}
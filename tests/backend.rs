@@ -0,0 +1,197 @@
+#[cfg(feature = "z3")]
+use paraspace::backend::Z3Backend;
+#[cfg(feature = "native")]
+use paraspace::native::NativeBackend;
+use paraspace::{
+    backend::{Backend, CheckResult, SmtLibProcess},
+    transitionsolver::{self, SolverBackend, SolverSettings},
+    SolverError,
+};
+
+mod common;
+use common::oven_problem;
+
+fn check_backend<B: Backend>(backend: &B) {
+    let x = backend.fresh_real("x");
+    let y = backend.fresh_real("y");
+    let one = backend.real_const(1, 1);
+    backend.assert(&backend.le(&backend.add(&[&x, &one]), &y));
+
+    // y <= 3/2 and x >= 1 conflict with the precedence.
+    let a = backend.fresh_bool("a");
+    let b = backend.fresh_bool("b");
+    let c = backend.fresh_bool("c");
+    backend.assert(&backend.implies(&a, &backend.le(&y, &backend.real_const(3, 2))));
+    backend.assert(&backend.implies(&b, &backend.le(&one, &x)));
+    backend.assert(&backend.pb_le(&[(&c, 1), (&backend.not(&c), 1)], 1));
+
+    let assumptions = vec![a.clone(), b.clone(), c.clone()];
+    assert!(backend.check(&assumptions) == CheckResult::Unsat);
+    let core = backend.unsat_core();
+    assert!(core.len() == 2 && core.contains(&a) && core.contains(&b));
+
+    assert!(backend.check(&[a, c.clone()]) == CheckResult::Sat);
+    assert!(backend.bool_value(&c));
+    let (num, den) = backend.real_value(&y);
+    assert!(num * 2 <= 3 * den);
+    let (x_num, x_den) = backend.real_value(&x);
+    assert!((x_num * den + x_den * den) <= num * x_den);
+}
+
+#[cfg(feature = "z3")]
+#[test]
+pub fn z3_backend() {
+    let ctx = z3::Context::new(&z3::Config::new());
+    check_backend(&Z3Backend::new(&ctx, &SolverSettings::default()));
+}
+
+#[cfg(feature = "native")]
+#[test]
+pub fn native_backend() {
+    check_backend(&NativeBackend::new());
+    solve_oven(SolverBackend::Native);
+}
+
+// Runs only when an SMT-LIB2 solver command is given, e.g.
+// PARASPACE_SMTLIB="cvc5 --incremental" cargo test
+#[test]
+pub fn smtlib_process_backend() {
+    let command = match std::env::var("PARASPACE_SMTLIB") {
+        Ok(command) => command
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>(),
+        Err(_) => {
+            println!("PARASPACE_SMTLIB is not set, skipping.");
+            return;
+        }
+    };
+
+    check_backend(&SmtLibProcess::new(&command).unwrap());
+    solve_oven(SolverBackend::SmtLibProcess { command });
+}
+
+// The two pots cannot be in the oven at the same time.
+fn solve_oven(backend: SolverBackend) {
+    let settings = SolverSettings {
+        backend,
+        ..Default::default()
+    };
+    let solution = transitionsolver::solve(&oven_problem(), &settings).unwrap();
+    let heating = solution
+        .timelines
+        .iter()
+        .flat_map(|tl| tl.tokens.iter())
+        .filter(|t| t.value == "Heating")
+        .collect::<Vec<_>>();
+    assert!(heating.len() == 2);
+    assert!(
+        heating[0].end_time <= heating[1].start_time
+            || heating[1].end_time <= heating[0].start_time
+    );
+}
+
+// A stand-in for a solver process that gives the same reply to every check.
+fn fake_solver(reply: &str) -> Vec<String> {
+    let script = format!(
+        "while read -r l; do case \"$l\" in \"(check-sat\"*) echo '{}';; esac; done",
+        reply
+    );
+    vec!["sh".to_string(), "-c".to_string(), script]
+}
+
+// A stand-in for a solver process that gives the same replies to every check
+// and every other query.
+fn fake_model_solver(check_reply: &str, query_reply: &str) -> Vec<String> {
+    let script = format!(
+        "while read -r l; do case \"$l\" in \"(check-sat\"*) echo '{}';; \"(get-\"*) echo '{}';; esac; done",
+        check_reply, query_reply
+    );
+    vec!["sh".to_string(), "-c".to_string(), script]
+}
+
+#[test]
+pub fn smtlib_process_replies() {
+    // The parenthesis in the error message does not start an s-expression.
+    for (reply, expected) in [
+        ("unknown", "Unknown"),
+        ("(error \"expected ( here\")", "Backend"),
+        ("|unbalanced (|", "Backend"),
+    ] {
+        let settings = SolverSettings {
+            backend: SolverBackend::SmtLibProcess {
+                command: fake_solver(reply),
+            },
+            ..Default::default()
+        };
+        let result = transitionsolver::solve(&oven_problem(), &settings);
+        println!("{}: {:?}", reply, result);
+        match result {
+            Err(SolverError::Unknown) => assert!(expected == "Unknown"),
+            Err(SolverError::Backend(_)) => assert!(expected == "Backend"),
+            _ => panic!("Unexpected result"),
+        }
+    }
+
+    let settings = SolverSettings {
+        backend: SolverBackend::SmtLibProcess {
+            command: vec!["paraspace-no-such-solver".to_string()],
+        },
+        ..Default::default()
+    };
+    let result = transitionsolver::solve(&oven_problem(), &settings);
+    assert!(matches!(result, Err(SolverError::Backend(_))));
+
+    let error = SmtLibProcess::new(&[]).err().unwrap();
+    assert!(error.kind() == std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+pub fn smtlib_process_bad_queries() {
+    // Values that are not booleans, and cores with literals that were never assumed.
+    for (check_reply, query_reply) in [
+        ("sat", "((x seven))"),
+        ("sat", "()"),
+        ("unsat", "(no_such_literal)"),
+        ("unsat", "unsat"),
+    ] {
+        let settings = SolverSettings {
+            backend: SolverBackend::SmtLibProcess {
+                command: fake_model_solver(check_reply, query_reply),
+            },
+            ..Default::default()
+        };
+        let result = transitionsolver::solve(&oven_problem(), &settings);
+        println!("{} {}: {:?}", check_reply, query_reply, result);
+        assert!(matches!(result, Err(SolverError::Backend(_))));
+    }
+
+    // Real values that are not numbers, or do not fit in 64 bits.
+    for (value, expected) in [
+        ("(- (/ 3.0 2.0))", Some((-3, 2))),
+        ("seven", None),
+        ("123456789012345678901234567890.5", None),
+        ("(/ 1.0 0.000000000000000000001)", None),
+    ] {
+        let backend =
+            SmtLibProcess::new(&fake_model_solver("sat", &format!("((x {}))", value))).unwrap();
+        let x = backend.fresh_real("x");
+        assert!(backend.check(&[]) == CheckResult::Sat);
+        let rational = backend.real_value(&x);
+        println!("{}: {:?} {:?}", value, rational, backend.error());
+        assert!(backend.error().is_none() == expected.is_some());
+        assert!(expected.is_none() || expected == Some(rational));
+    }
+}
+
+#[test]
+pub fn smtlib_process_budget() {
+    let backend = SmtLibProcess::new(&fake_solver("unknown")).unwrap();
+    backend.set_budget(Some(100), None);
+    assert!(backend.check(&[]) == CheckResult::Unknown);
+    assert!(backend.error().is_none());
+    backend.set_budget(None, None);
+    let formula = backend.formula();
+    assert!(formula.contains("(set-option :timeout 100)\n"));
+    assert!(formula.contains(&format!("(set-option :timeout {})\n", u32::MAX)));
+}
@@ -0,0 +1,99 @@
+// Problems shared by the integration tests. Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use paraspace::problem::*;
+
+// An oven that is available from time 0, and two pots that must be heated in it
+// for 5 time units, one at a time, to be hot at the end.
+pub fn oven_problem() -> Problem {
+    pots_problem(1, Some(0), &[5, 5])
+}
+
+// A solution timeline with the given (value, start time, end time) tokens.
+pub fn timeline(name: &str, tokens: &[(&str, f32, f32)]) -> SolutionTimeline {
+    SolutionTimeline {
+        name: name.to_string(),
+        tokens: tokens
+            .iter()
+            .map(|(value, start_time, end_time)| SolutionToken {
+                id: 0,
+                value: value.to_string(),
+                start_time: *start_time,
+                end_time: *end_time,
+                conditions: Vec::new(),
+            })
+            .collect(),
+    }
+}
+
+// Pots named pot1, pot2, ..., that are heated in an oven of the given capacity
+// for the given durations.
+pub fn pots_problem(capacity: u32, available_from: Option<usize>, durations: &[usize]) -> Problem {
+    pots_in(
+        vec![oven("oven", capacity, available_from)],
+        "Available",
+        durations,
+    )
+}
+
+// An oven timeline with a single Available fact.
+pub fn oven(name: &str, capacity: u32, available_from: Option<usize>) -> Timeline {
+    Timeline {
+        name: name.to_string(),
+        token_types: vec![],
+        static_tokens: vec![Token {
+            value: "Available".to_string(),
+            const_time: TokenTime::Fact(available_from, None),
+            capacity,
+            conditions: vec![],
+        }],
+    }
+}
+
+// Pots named pot1, pot2, ..., that are heated for the given durations while
+// covering the given value on any one of the heater timelines.
+pub fn pots_in(heaters: Vec<Timeline>, value: &str, durations: &[usize]) -> Problem {
+    let heating = heaters
+        .iter()
+        .map(|heater| Condition {
+            temporal_relationship: TemporalRelationship::Cover,
+            amount: 1,
+            timeline_ref: heater.name.clone(),
+            value: value.to_string(),
+        })
+        .collect::<Vec<_>>();
+    let pot = |name: &str, duration: usize| Timeline {
+        name: name.to_string(),
+        token_types: vec![
+            TokenType {
+                value: "Heating".to_string(),
+                conditions: vec![heating.clone()],
+                duration_limits: (duration, Some(duration)),
+                capacity: 0,
+            },
+            TokenType {
+                value: "Hot".to_string(),
+                conditions: vec![vec![Condition {
+                    temporal_relationship: TemporalRelationship::MetBy,
+                    amount: 0,
+                    timeline_ref: name.to_string(),
+                    value: "Heating".to_string(),
+                }]],
+                duration_limits: (1, None),
+                capacity: 0,
+            },
+        ],
+        static_tokens: vec![Token {
+            value: "Hot".to_string(),
+            const_time: TokenTime::Goal,
+            capacity: 0,
+            conditions: vec![],
+        }],
+    };
+
+    let mut timelines = heaters;
+    for (i, duration) in durations.iter().enumerate() {
+        timelines.push(pot(&format!("pot{}", i + 1), *duration));
+    }
+    Problem { timelines }
+}
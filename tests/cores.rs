@@ -0,0 +1,57 @@
+// Core minimization needs a backend with unsat cores built in.
+#![cfg(any(feature = "z3", feature = "native"))]
+
+#[cfg(feature = "native")]
+use paraspace::native::NativeBackend;
+use paraspace::{
+    backend::Backend,
+    cores::{minimize_core_with, CoreBudget, CoreMinimization},
+};
+#[cfg(feature = "z3")]
+use paraspace::{backend::Z3Backend, transitionsolver::SolverSettings};
+
+const ALGORITHMS: [CoreMinimization; 4] = [
+    CoreMinimization::LinearDeletion,
+    CoreMinimization::QuickXplain,
+    CoreMinimization::DivideAndConquer,
+    CoreMinimization::Progression,
+];
+
+fn check_minimal_core<B: Backend>(backend: &B, algorithm: CoreMinimization) {
+    let lits = (0..10).map(|_| backend.fresh_bool("x")).collect::<Vec<_>>();
+
+    // Two minimal conflicts: {x2, x5, x7} and {x1, x8}.
+    backend.assert(&backend.not(&backend.and(&[&lits[2], &lits[5], &lits[7]])));
+    backend.assert(&backend.not(&backend.and(&[&lits[1], &lits[8]])));
+
+    let mut core = lits.clone();
+    minimize_core_with(&mut core, backend, algorithm, &CoreBudget::default(), |s| {
+        println!("{}", s)
+    });
+
+    let mut idxs = core
+        .iter()
+        .map(|c| lits.iter().position(|l| l == c).unwrap())
+        .collect::<Vec<_>>();
+    idxs.sort();
+    println!("{:?}: {:?}", algorithm, idxs);
+    assert!(idxs == vec![2, 5, 7] || idxs == vec![1, 8]);
+}
+
+#[cfg(feature = "z3")]
+#[test]
+pub fn minimal_cores() {
+    for algorithm in ALGORITHMS {
+        let ctx = z3::Context::new(&z3::Config::new());
+        let backend = Z3Backend::new(&ctx, &SolverSettings::default());
+        check_minimal_core(&backend, algorithm);
+    }
+}
+
+#[cfg(feature = "native")]
+#[test]
+pub fn minimal_cores_native() {
+    for algorithm in ALGORITHMS {
+        check_minimal_core(&NativeBackend::new(), algorithm);
+    }
+}
@@ -0,0 +1,114 @@
+use paraspace::{decompose, problem::*, validate::validate_solution};
+
+fn two_step_timeline(name: &str, duration: usize) -> Timeline {
+    Timeline {
+        name: name.to_string(),
+        token_types: vec![
+            TokenType {
+                value: "s1".to_string(),
+                conditions: Vec::new(),
+                duration_limits: (duration, Some(duration)),
+                capacity: 0,
+            },
+            TokenType {
+                value: "s2".to_string(),
+                conditions: vec![vec![Condition {
+                    temporal_relationship: TemporalRelationship::MetBy,
+                    amount: 0,
+                    timeline_ref: name.to_string(),
+                    value: "s1".to_string(),
+                }]],
+                duration_limits: (1, None),
+                capacity: 0,
+            },
+        ],
+        static_tokens: vec![Token {
+            value: "s2".to_string(),
+            const_time: TokenTime::Goal,
+            capacity: 0,
+            conditions: vec![],
+        }],
+    }
+}
+
+#[test]
+pub fn independent_timelines() {
+    let mut problem = Problem {
+        timelines: vec![
+            two_step_timeline("a", 5),
+            two_step_timeline("b", 10),
+            two_step_timeline("c", 3),
+        ],
+    };
+    // c can only finish its first step while a is in its second step.
+    problem.timelines[2].token_types[1]
+        .conditions
+        .push(vec![Condition {
+            temporal_relationship: TemporalRelationship::Cover,
+            amount: 0,
+            timeline_ref: "a".to_string(),
+            value: "s2".to_string(),
+        }]);
+
+    let decomposition = decompose::Decomposition::analyze(&problem);
+    assert!(decomposition.components == vec![vec![0, 2], vec![1]]);
+
+    for parallel in [false, true] {
+        let solution =
+            decompose::solve_decomposed(&problem, &Default::default(), parallel).unwrap();
+        println!("SOLUTION {:#?}", solution);
+        assert!(solution.timelines.len() == 3);
+        for (timeline, solution_timeline) in problem.timelines.iter().zip(solution.timelines.iter())
+        {
+            assert!(timeline.name == solution_timeline.name);
+            assert!(solution_timeline.tokens.len() == 2);
+        }
+        let b1 = &solution.timelines[1].tokens[0];
+        assert!(b1.end_time - b1.start_time >= 10.0);
+        assert!(solution
+            .timelines
+            .iter()
+            .all(|tl| tl.tokens.last().unwrap().end_time == solution.end_of_time));
+    }
+}
+
+fn fact_timeline(name: &str, end_time: usize) -> Timeline {
+    Timeline {
+        name: name.to_string(),
+        token_types: vec![],
+        static_tokens: vec![Token {
+            value: "Open".to_string(),
+            const_time: TokenTime::Fact(Some(0), Some(end_time)),
+            capacity: 0,
+            conditions: vec![],
+        }],
+    }
+}
+
+#[test]
+pub fn different_makespans() {
+    // The second step of a can not last long enough to wait for d, and the
+    // fixed fact on c can not move to the end of time.
+    let mut problem = Problem {
+        timelines: vec![
+            two_step_timeline("a", 2),
+            two_step_timeline("b", 10),
+            fact_timeline("c", 4),
+            fact_timeline("d", 6),
+        ],
+    };
+    problem.timelines[0].token_types[1].duration_limits = (1, Some(1));
+
+    let decomposition = decompose::Decomposition::analyze(&problem);
+    assert!(decomposition.components.len() == 4);
+
+    let solution = decompose::solve_decomposed(&problem, &Default::default(), false).unwrap();
+    println!("SOLUTION {:#?}", solution);
+    let violations = validate_solution(&problem, &solution);
+    assert!(violations.is_empty(), "{:?}", violations);
+    assert!(solution.end_of_time >= 6.0);
+    assert!(solution.timelines[2].tokens[0].end_time == 4.0);
+    for timeline in solution.timelines[..2].iter() {
+        assert!(timeline.tokens.last().unwrap().end_time == solution.end_of_time);
+    }
+}
@@ -0,0 +1,37 @@
+use paraspace::{
+    problem::*,
+    transitionsolver::{self, ResourceEncoding, SolverSettings},
+};
+
+mod common;
+use common::{oven, pots_in};
+
+// Pots can be heated in either of two ovens.
+fn two_oven_problem(n_pots: usize) -> Problem {
+    let ovens = vec![oven("oven_a", 1, None), oven("oven_b", 1, None)];
+    pots_in(ovens, "Available", &(3..3 + n_pots).collect::<Vec<_>>())
+}
+
+#[test]
+pub fn reproducible_runs() {
+    let problem = two_oven_problem(5);
+    for encoding in [ResourceEncoding::Pairwise, ResourceEncoding::Lazy] {
+        let settings = SolverSettings {
+            resource_encoding: encoding,
+            deterministic: true,
+            ..Default::default()
+        };
+
+        let (first, first_statistics) =
+            transitionsolver::solve_with_statistics(&problem, &settings);
+        let first = serde_json::to_string(&first.unwrap()).unwrap();
+        println!("{:?}: {:?}", encoding, first_statistics);
+        assert!(first_statistics.solver_calls > 0);
+
+        for _ in 0..3 {
+            let (result, statistics) = transitionsolver::solve_with_statistics(&problem, &settings);
+            assert!(serde_json::to_string(&result.unwrap()).unwrap() == first);
+            assert!(statistics == first_statistics);
+        }
+    }
+}
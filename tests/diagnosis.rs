@@ -0,0 +1,123 @@
+mod common;
+use common::pots_problem;
+use paraspace::{
+    diagnosis::{self, ConstraintKind, DiagnosisLimits},
+    problem::*,
+};
+use std::time::Duration;
+
+fn fact(value: &str, time: (usize, usize), capacity: u32, conditions: Vec<Condition>) -> Token {
+    Token {
+        value: value.to_string(),
+        const_time: TokenTime::Fact(Some(time.0), Some(time.1)),
+        capacity,
+        conditions: conditions.into_iter().map(|c| vec![c]).collect(),
+    }
+}
+
+fn uses(timeline: &str, value: &str, amount: u32) -> Condition {
+    Condition {
+        temporal_relationship: TemporalRelationship::Cover,
+        amount,
+        timeline_ref: timeline.to_string(),
+        value: value.to_string(),
+    }
+}
+
+#[test]
+pub fn minimal_conflicts() {
+    let facts_timeline = |name: &str, static_tokens: Vec<Token>| Timeline {
+        name: name.to_string(),
+        token_types: vec![],
+        static_tokens,
+    };
+
+    // The door must be open while it is closed, and two pots are heated at the
+    // same time in an oven that has room for one.
+    let problem = Problem {
+        timelines: vec![
+            facts_timeline("door", vec![fact("closed", (0, 20), 0, vec![])]),
+            facts_timeline(
+                "cook",
+                vec![fact("waiting", (0, 5), 0, vec![uses("door", "open", 0)])],
+            ),
+            facts_timeline("oven", vec![fact("on", (0, 20), 1, vec![])]),
+            facts_timeline(
+                "pot1",
+                vec![fact("heating", (5, 15), 0, vec![uses("oven", "on", 1)])],
+            ),
+            facts_timeline(
+                "pot2",
+                vec![fact("heating", (5, 15), 0, vec![uses("oven", "on", 1)])],
+            ),
+        ],
+    };
+
+    let diagnosis = diagnosis::diagnose(&problem, &Default::default(), &Default::default());
+    assert!(diagnosis.complete);
+    assert!(diagnosis.constraints.len() == 4);
+
+    let mut conflicts = diagnosis
+        .conflicts
+        .iter()
+        .map(|conflict| {
+            let mut timelines = conflict
+                .iter()
+                .map(|c| {
+                    let constraint = &diagnosis.constraints[*c];
+                    let name = problem.timelines[constraint.timeline].name.as_str();
+                    match constraint.kind {
+                        ConstraintKind::Capacity { .. } => format!("capacity {}", name),
+                        _ => name.to_string(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            timelines.sort();
+            timelines
+        })
+        .collect::<Vec<_>>();
+    conflicts.sort();
+    println!("{:?}", conflicts);
+    assert!(conflicts == vec![vec!["capacity oven", "pot1", "pot2"], vec!["cook"]]);
+}
+
+#[test]
+pub fn token_type_conflicts() {
+    // The pots can be heated one after the other, but the cook needs an open door.
+    let mut problem = pots_problem(1, None, &[5, 5]);
+    problem.timelines.push(Timeline {
+        name: "door".to_string(),
+        token_types: vec![],
+        static_tokens: vec![fact("closed", (0, 20), 0, vec![])],
+    });
+    problem.timelines.push(Timeline {
+        name: "cook".to_string(),
+        token_types: vec![],
+        static_tokens: vec![fact("waiting", (0, 5), 0, vec![uses("door", "open", 0)])],
+    });
+
+    let diagnosis = diagnosis::diagnose(&problem, &Default::default(), &Default::default());
+    assert!(diagnosis.complete);
+    // Two goals, two condition groups on each pot, the cook's conditions and the oven capacity.
+    assert!(diagnosis.constraints.len() == 8);
+    assert!(diagnosis.conflicts.len() == 1);
+    let conflict = &diagnosis.conflicts[0];
+    assert!(conflict.len() == 1);
+    assert!(diagnosis.constraints[conflict[0]].timeline == 4);
+}
+
+#[test]
+pub fn time_limit() {
+    // Two pots need the oven for 5 each, but it is only available until 8. The
+    // solver keeps expanding the pot timelines, so this is never decided.
+    let mut problem = pots_problem(1, Some(0), &[5, 5]);
+    problem.timelines[0].static_tokens[0].const_time = TokenTime::Fact(Some(0), Some(8));
+
+    let limits = DiagnosisLimits {
+        time_limit: Duration::from_secs(1),
+        ..Default::default()
+    };
+    let diagnosis = diagnosis::diagnose(&problem, &Default::default(), &limits);
+    assert!(!diagnosis.complete);
+    assert!(diagnosis.conflicts.is_empty());
+}
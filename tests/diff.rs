@@ -0,0 +1,66 @@
+use paraspace::{
+    diff::{diff, diff_with_options, DiffOptions},
+    problem::*,
+};
+
+mod common;
+use common::timeline;
+
+#[test]
+pub fn solution_diff() {
+    let old = Solution {
+        timelines: vec![
+            timeline("oven", &[("Available", 0.0, 20.0)]),
+            timeline(
+                "pot",
+                &[
+                    ("Cold", 0.0, 5.0),
+                    ("Heating", 5.0, 10.0),
+                    ("Hot", 10.0, 20.0),
+                ],
+            ),
+        ],
+        end_of_time: 20.0,
+    };
+    assert!(diff(&old, &old).is_empty());
+
+    // The pot is stirred before heating, which is delayed by a bit more than a tolerable amount.
+    let new = Solution {
+        timelines: vec![
+            timeline("oven", &[("Available", 0.0, 20.5)]),
+            timeline(
+                "pot",
+                &[
+                    ("Cold", 0.0, 3.0),
+                    ("Stirring", 3.0, 5.2),
+                    ("Heating", 5.2, 10.2),
+                    ("Hot", 10.2, 20.5),
+                ],
+            ),
+        ],
+        end_of_time: 20.5,
+    };
+    let d = diff(&old, &new);
+    println!("{}", d);
+    assert!((d.makespan_change - 0.5).abs() < 1e-3);
+    assert!(d.timelines.len() == 2);
+    let pot = &d.timelines[1];
+    assert!(pot.removed.is_empty());
+    assert!(pot.inserted.len() == 1 && pot.inserted[0].value == "Stirring");
+    assert!(pot.retimed.len() == 3);
+    assert!((pot.retimed[1].start_shift - 0.2).abs() < 1e-3);
+
+    // With a larger tolerance, only the end of the cold pot and of the hot pot have moved.
+    let options = DiffOptions {
+        tolerance: 0.25,
+        ..Default::default()
+    };
+    let d = diff_with_options(&old, &new, &options);
+    let pot = &d.timelines[1];
+    assert!(pot.retimed.len() == 2 && pot.retimed[0].old.value == "Cold");
+    assert!(pot.retimed[1].old.value == "Hot");
+
+    // JSON output.
+    let json = serde_json::to_string(&d).unwrap();
+    assert!(serde_json::from_str::<paraspace::diff::SolutionDiff>(&json).unwrap() == d);
+}
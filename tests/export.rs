@@ -0,0 +1,85 @@
+use paraspace::{
+    export::{export, format_timestamp, parse_timestamp, ExportFormat, ExportedToken, TimeMapping},
+    problem::*,
+};
+
+fn solution() -> Solution {
+    let token = |id: usize, value: &str, start_time: f32, end_time: f32| SolutionToken {
+        id,
+        value: value.to_string(),
+        start_time,
+        end_time,
+        conditions: Vec::new(),
+    };
+    Solution {
+        timelines: vec![
+            SolutionTimeline {
+                name: "oven".to_string(),
+                tokens: vec![token(0, "Available", 0.0, 20.0)],
+            },
+            SolutionTimeline {
+                name: "pot".to_string(),
+                tokens: vec![
+                    token(1, "Heating, slowly", 2.5, 10.0),
+                    token(2, "Hot", 10.0, 20.0),
+                ],
+            },
+        ],
+        end_of_time: 20.0,
+    }
+}
+
+#[test]
+pub fn timestamps() {
+    assert!(format_timestamp(0) == "1970-01-01T00:00:00Z");
+    assert!(format_timestamp(951868799) == "2000-02-29T23:59:59Z");
+    assert!(format_timestamp(-5000000000) == "1811-07-23T15:06:40Z");
+    for s in [
+        "2000-02-29T23:59:59Z",
+        "1811-07-23T15:06:40Z",
+        "2026-10-19T08:00:00Z",
+    ] {
+        assert!(format_timestamp(parse_timestamp(s).unwrap()) == s);
+    }
+    assert!(parse_timestamp("2026-10-19") == parse_timestamp("2026-10-19T00:00:00"));
+    assert!(parse_timestamp("1234") == Ok(1234));
+    assert!(parse_timestamp("19 October").is_err());
+}
+
+#[test]
+pub fn export_formats() {
+    // Solver times are in minutes.
+    let mapping = TimeMapping {
+        epoch: parse_timestamp("2026-10-19T08:00:00Z").unwrap(),
+        seconds_per_unit: 60.0,
+    };
+    let solution = solution();
+
+    let csv = export(&solution, ExportFormat::Csv, &mapping);
+    println!("{}", csv);
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert!(lines.len() == 4);
+    assert!(lines[0] == "timeline,id,value,start_time,end_time,start,end");
+    assert!(
+        lines[2] == "pot,1,\"Heating, slowly\",2.5,10,2026-10-19T08:02:30Z,2026-10-19T08:10:00Z"
+    );
+
+    let jsonl = export(&solution, ExportFormat::JsonLines, &mapping);
+    let tokens = jsonl
+        .lines()
+        .map(|l| serde_json::from_str::<ExportedToken>(l).unwrap())
+        .collect::<Vec<_>>();
+    assert!(tokens.len() == 3);
+    assert!(tokens[2].timeline == "pot" && tokens[2].end == "2026-10-19T08:20:00Z");
+
+    let ics = export(&solution, ExportFormat::ICalendar, &mapping);
+    println!("{}", ics);
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\n") && ics.ends_with("END:VCALENDAR\r\n"));
+    assert!(ics.matches("BEGIN:VEVENT").count() == 3);
+    assert!(ics.contains("DTSTART:20261019T080230Z\r\n"));
+    assert!(ics.contains("SUMMARY:pot: Heating\\, slowly\r\n"));
+
+    let json = export(&solution, ExportFormat::Json, &mapping);
+    let parsed = serde_json::from_str::<Solution>(&json).unwrap();
+    assert!(parsed.end_of_time == solution.end_of_time && parsed.timelines.len() == 2);
+}
@@ -0,0 +1,86 @@
+use paraspace::{
+    flexible::{flexible_solution, FlexibleError},
+    transitionsolver::{self, SolverSettings},
+};
+
+mod common;
+use common::oven_problem;
+
+#[test]
+pub fn oven_windows() {
+    let problem = oven_problem();
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+    let flexible = flexible_solution(&problem, &solution).unwrap();
+    println!("{:#?}", flexible);
+
+    // The windows contain the times of the solution.
+    for (timeline, flexible_timeline) in solution.timelines.iter().zip(flexible.timelines.iter()) {
+        for (token, window) in timeline.tokens.iter().zip(flexible_timeline.tokens.iter()) {
+            assert!(token.id == window.id);
+            assert!(window.start.earliest <= token.start_time + 1e-3);
+            assert!(token.start_time <= window.start.latest + 1e-3);
+            assert!(window.end.earliest <= token.end_time + 1e-3);
+            assert!(token.end_time <= window.end.latest + 1e-3);
+        }
+    }
+
+    // The pots keep their order in the oven, so the second one can not start
+    // heating before the first one is done.
+    let heating = |i: usize| &flexible.timelines[i].tokens[0];
+    let (first, second) = if solution.timelines[1].tokens[0].start_time
+        < solution.timelines[2].tokens[0].start_time
+    {
+        (heating(1), heating(2))
+    } else {
+        (heating(2), heating(1))
+    };
+    assert!(second.start.earliest >= first.end.earliest - 1e-3);
+    assert!(second.start.earliest >= 5.0 - 1e-3);
+    assert!(first.end.latest <= second.start.latest + 1e-3);
+}
+
+#[test]
+pub fn invalid_solutions() {
+    let problem = oven_problem();
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+
+    let mut unknown_timeline = solution.clone();
+    unknown_timeline.timelines[1].name = "pan".to_string();
+    assert!(
+        flexible_solution(&problem, &unknown_timeline).unwrap_err()
+            == FlexibleError::UnknownTimeline("pan".to_string())
+    );
+
+    let mut unknown_value = solution.clone();
+    unknown_value.timelines[1].tokens[0].value = "Boiling".to_string();
+    assert!(matches!(
+        flexible_solution(&problem, &unknown_value),
+        Err(FlexibleError::UnknownValue { token: 0, .. })
+    ));
+
+    let mut duplicate_id = solution.clone();
+    let id = duplicate_id.timelines[1].tokens[0].id;
+    duplicate_id.timelines[2].tokens[1].id = id;
+    assert!(
+        flexible_solution(&problem, &duplicate_id).unwrap_err() == FlexibleError::DuplicateId(id)
+    );
+
+    let mut missing_satisfaction = solution.clone();
+    let heating = &mut missing_satisfaction.timelines[1].tokens[0];
+    heating.conditions.clear();
+    let id = heating.id;
+    assert!(
+        flexible_solution(&problem, &missing_satisfaction).unwrap_err()
+            == FlexibleError::MissingSatisfaction {
+                token: id,
+                group: 0
+            }
+    );
+
+    let mut unknown_target = solution;
+    unknown_target.timelines[1].tokens[0].conditions[0].target = 1000;
+    assert!(matches!(
+        flexible_solution(&problem, &unknown_target),
+        Err(FlexibleError::UnknownSatisfaction { group: 0, .. })
+    ));
+}
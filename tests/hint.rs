@@ -0,0 +1,50 @@
+use paraspace::transitionsolver::{self, SolverBackend, SolverSettings};
+use std::sync::Arc;
+
+mod common;
+use common::pots_problem;
+
+#[test]
+pub fn warm_start() {
+    let problem = pots_problem(1, None, &[3, 4, 5, 6]);
+    let (solution, cold_statistics) =
+        transitionsolver::solve_with_statistics(&problem, &Default::default());
+    let hint = Arc::new(solution.unwrap());
+
+    // The hint is a solution to the same problem.
+    let settings = SolverSettings {
+        hint: Some(hint.clone()),
+        ..Default::default()
+    };
+    let (solution, statistics) = transitionsolver::solve_with_statistics(&problem, &settings);
+    println!("cold {:?} warm {:?}", cold_statistics, statistics);
+    assert!(statistics.hints > 0);
+    assert!(statistics.hints_dropped == 0);
+    assert!(statistics.solver_calls == 1);
+    assert!(statistics.solver_calls <= cold_statistics.solver_calls);
+    let solution = solution.unwrap();
+    for (timeline, hint_timeline) in solution.timelines.iter().zip(hint.timelines.iter()) {
+        for (token, hint_token) in timeline.tokens.iter().zip(hint_timeline.tokens.iter()) {
+            assert!(token.value == hint_token.value);
+            assert!(token.end_time == hint_token.end_time);
+        }
+    }
+
+    // A longer heating time makes some of the hinted times infeasible. Z3 drops
+    // them, and the native solver only prefers them, so neither needs more
+    // expansion rounds than without hints.
+    let changed = pots_problem(1, None, &[3, 4, 7, 6]);
+    let (_, cold_statistics) =
+        transitionsolver::solve_with_statistics(&changed, &Default::default());
+    let (solution, statistics) = transitionsolver::solve_with_statistics(&changed, &settings);
+    println!("changed {:?}", statistics);
+    if SolverBackend::default() == SolverBackend::Z3 {
+        assert!(statistics.hints_dropped > 0);
+    } else {
+        assert!(statistics.hints_dropped == 0);
+    }
+    assert!(statistics.iterations.len() <= cold_statistics.iterations.len());
+    let pot = &solution.unwrap().timelines[3];
+    assert!(pot.tokens[0].value == "Heating");
+    assert!(pot.tokens[0].end_time - pot.tokens[0].start_time == 7.0);
+}
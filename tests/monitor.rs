@@ -0,0 +1,75 @@
+use paraspace::{
+    flexible::{flexible_solution, ConstraintKind, FlexibleError, ORIGIN},
+    monitor::{Monitor, MonitorError, Observation, TokenPoint},
+    transitionsolver::{self, SolverSettings},
+};
+
+mod common;
+use common::oven_problem;
+
+#[test]
+pub fn delayed_oven() {
+    let problem = oven_problem();
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+    let mut monitor = Monitor::from_solution(&problem, &solution).unwrap();
+
+    // The pot that heats first, and the one that waits for the oven.
+    let heating = |i: usize| &solution.timelines[i].tokens[0];
+    let (first, second) = if heating(1).start_time < heating(2).start_time {
+        (heating(1), heating(2))
+    } else {
+        (heating(2), heating(1))
+    };
+    let observe = |token: usize, point, time| Observation { token, point, time };
+
+    // Heating starts on time.
+    let start = first.start_time;
+    let delays = monitor
+        .observe(&observe(first.id, TokenPoint::Start, start))
+        .unwrap();
+    assert!(delays.is_empty());
+
+    // Heating starts 3 time units late, which pushes the other pot and the end of time.
+    let mut monitor = Monitor::from_solution(&problem, &solution).unwrap();
+    let delays = monitor
+        .observe(&observe(first.id, TokenPoint::Start, start + 3.0))
+        .unwrap();
+    println!("{:#?}", delays);
+    assert!(delays.iter().any(|d| d.token == second.id
+        && d.point == TokenPoint::Start
+        && (d.earliest - (start + 8.0)).abs() < 1e-3));
+    let window = monitor.window(second.id, TokenPoint::Start).unwrap();
+    assert!((window.earliest - (start + 8.0)).abs() < 1e-3);
+    assert!(monitor.end_of_time().earliest >= start + 14.0 - 1e-3);
+    assert!(monitor.is_consistent());
+
+    // Heating takes only two time units, which the plan does not allow.
+    assert!(
+        monitor.observe(&observe(first.id, TokenPoint::End, start + 5.0))
+            == Err(MonitorError::Inconsistent)
+    );
+    assert!(!monitor.is_consistent());
+}
+
+#[test]
+pub fn unusable_plans() {
+    let problem = oven_problem();
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+
+    // A plan with a timepoint that has to be both before and after time 1.
+    let mut plan = flexible_solution(&problem, &solution).unwrap();
+    plan.stn
+        .add_constraint(ORIGIN, 2, Some(1.0), Some(0.0), ConstraintKind::Observation);
+    assert!(matches!(
+        Monitor::new(plan),
+        Err(MonitorError::Inconsistent)
+    ));
+
+    // A solution that does not belong to the problem.
+    let mut wrong = solution.clone();
+    wrong.timelines[0].name = "stove".to_string();
+    assert!(matches!(
+        Monitor::from_solution(&problem, &wrong),
+        Err(MonitorError::Flexible(FlexibleError::UnknownTimeline(_)))
+    ));
+}
@@ -0,0 +1,98 @@
+use paraspace::{
+    portfolio,
+    problem::*,
+    transitionsolver::{self, SolverBackend, SolverSettings},
+    SolverError,
+};
+use std::sync::{atomic::AtomicBool, Arc};
+
+fn two_step_problem() -> Problem {
+    Problem {
+        timelines: vec![Timeline {
+            name: "obj".to_string(),
+            token_types: vec![
+                TokenType {
+                    value: "s1".to_string(),
+                    conditions: Vec::new(),
+                    duration_limits: (5, Some(6)),
+                    capacity: 0,
+                },
+                TokenType {
+                    value: "s2".to_string(),
+                    conditions: vec![vec![Condition {
+                        temporal_relationship: TemporalRelationship::MetBy,
+                        amount: 0,
+                        timeline_ref: "obj".to_string(),
+                        value: "s1".to_string(),
+                    }]],
+                    duration_limits: (1, None),
+                    capacity: 0,
+                },
+            ],
+            static_tokens: vec![Token {
+                value: "s2".to_string(),
+                const_time: TokenTime::Goal,
+                capacity: 0,
+                conditions: vec![],
+            }],
+        }],
+    }
+}
+
+#[test]
+pub fn portfolio_solves() {
+    let problem = two_step_problem();
+    let configurations = portfolio::default_portfolio();
+    let result = portfolio::solve_portfolio(&problem, &configurations).unwrap();
+    assert!(result.winner < configurations.len());
+    assert!(result.winner_name == configurations[result.winner].0);
+
+    let solution = result.result.unwrap();
+    assert!(solution.timelines[0].tokens.len() == 2);
+    assert!(solution.timelines[0].tokens[1].value == "s2");
+}
+
+#[test]
+pub fn cancelled_solve() {
+    let problem = two_step_problem();
+    let settings = SolverSettings {
+        cancel: Some(Arc::new(AtomicBool::new(true))),
+        ..Default::default()
+    };
+    let result = transitionsolver::solve(&problem, &settings);
+    assert!(matches!(result, Err(SolverError::Cancelled)));
+}
+
+#[test]
+pub fn portfolio_fails() {
+    // Every configuration panics on the unknown timeline.
+    let mut problem = two_step_problem();
+    problem.timelines[0].token_types[1].conditions[0][0].timeline_ref = "missing".to_string();
+    let configurations = portfolio::default_portfolio();
+    let result = portfolio::solve_portfolio(&problem, &configurations);
+    assert!(matches!(result, Err(SolverError::Panicked(_))));
+}
+
+#[test]
+pub fn portfolio_skips_failures() {
+    // A solver process that can not be started fails at once, and does not
+    // keep the portfolio from using the other configuration.
+    let configurations = vec![
+        (
+            "missing".to_string(),
+            SolverSettings {
+                backend: SolverBackend::SmtLibProcess {
+                    command: vec!["paraspace-no-such-solver".to_string()],
+                },
+                ..Default::default()
+            },
+        ),
+        ("default".to_string(), SolverSettings::default()),
+    ];
+    let result = portfolio::solve_portfolio(&two_step_problem(), &configurations).unwrap();
+    assert!(result.winner == 1);
+    assert!(result.result.is_ok());
+
+    let result = portfolio::solve_portfolio(&two_step_problem(), &configurations[..1]);
+    assert!(matches!(result, Err(SolverError::Backend(_))));
+}
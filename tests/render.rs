@@ -0,0 +1,43 @@
+use paraspace::{
+    render::{render_html, render_svg, RenderOptions},
+    transitionsolver::{self, SolverSettings},
+};
+
+mod common;
+use common::oven_problem;
+
+#[test]
+pub fn oven_chart() {
+    let problem = oven_problem();
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+    let n_tokens = solution
+        .timelines
+        .iter()
+        .map(|tl| tl.tokens.len())
+        .sum::<usize>();
+
+    // Without the problem, only the tokens are drawn.
+    let svg = render_svg(&solution, None, &RenderOptions::default());
+    assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+    assert!(svg.matches("<rect class=\"token").count() == n_tokens);
+    assert!(svg.matches("<title>").count() == n_tokens);
+    assert!(!svg.contains("token fact"));
+
+    // The oven is a fact, the pots heat on the critical path, and the oven is a resource.
+    let options = RenderOptions {
+        resource_profiles: true,
+        slack: true,
+        ..Default::default()
+    };
+    let svg = render_svg(&solution, Some(&problem), &options);
+    println!("{}", svg);
+    assert!(svg.matches("<rect class=\"token fact").count() == 1);
+    assert!(svg.contains("<title>oven Available [0, "));
+    assert!(svg.matches("critical").count() >= 2);
+    assert!(svg.contains("class=\"usage\""));
+    assert!(svg.contains("pot1 Heating"));
+
+    let html = render_html(&solution, Some(&problem), &options);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains(&svg));
+}
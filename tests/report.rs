@@ -0,0 +1,57 @@
+use paraspace::{
+    diagnosis,
+    problem::*,
+    report::SolveReport,
+    transitionsolver::{self, SolverSettings},
+};
+
+mod common;
+use common::oven_problem;
+
+#[test]
+pub fn solve_reports() {
+    let problem = oven_problem();
+    let (result, statistics) =
+        transitionsolver::solve_with_statistics(&problem, &SolverSettings::default());
+    assert!(statistics.iterations.len() == statistics.solver_calls);
+    let html = SolveReport {
+        name: "oven",
+        problem: &problem,
+        result: &result,
+        statistics: Some(&statistics),
+        diagnosis: None,
+        solve_time: None,
+    }
+    .to_html();
+    assert!(html.starts_with("<!DOCTYPE html>") && html.trim_end().ends_with("</html>"));
+    assert!(html.contains("<svg") && html.contains("<h3>Refinement</h3>"));
+    assert!(!html.contains("Infeasibility"));
+
+    // Both pots have to heat in the oven at the same time.
+    let mut infeasible = problem.clone();
+    for timeline in infeasible.timelines[1..].iter_mut() {
+        timeline.static_tokens.push(Token {
+            value: "Heating".to_string(),
+            const_time: TokenTime::Fact(Some(0), Some(5)),
+            capacity: 0,
+            conditions: timeline.token_types[0].conditions.clone(),
+        });
+    }
+    let (result, statistics) =
+        transitionsolver::solve_with_statistics(&infeasible, &SolverSettings::default());
+    assert!(result.is_err());
+    let diagnosis = diagnosis::diagnose(&infeasible, &Default::default(), &Default::default());
+    let html = SolveReport {
+        name: "oven",
+        problem: &infeasible,
+        result: &result,
+        statistics: Some(&statistics),
+        diagnosis: Some(&diagnosis),
+        solve_time: None,
+    }
+    .to_html();
+    println!("{}", html);
+    assert!(!html.contains("<svg"));
+    assert!(html.contains("<h2>Infeasibility</h2>"));
+    assert!(html.contains("capacity"));
+}
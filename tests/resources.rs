@@ -0,0 +1,165 @@
+use paraspace::{
+    problem::*,
+    resources::resource_profiles,
+    transitionsolver::{self, ResourceEncoding, SolverBackend, SolverSettings},
+    validate::validate_solution,
+    SolverError,
+};
+
+mod common;
+use common::pots_problem;
+
+// Pots that take 5, 6, ... time units to heat.
+fn oven_problem(capacity: u32, n_pots: usize) -> Problem {
+    pots_problem(capacity, None, &(5..5 + n_pots).collect::<Vec<_>>())
+}
+
+fn max_overlap(solution: &Solution) -> usize {
+    let heating = solution
+        .timelines
+        .iter()
+        .flat_map(|tl| tl.tokens.iter())
+        .filter(|t| t.value == "Heating")
+        .collect::<Vec<_>>();
+    heating
+        .iter()
+        .map(|t| {
+            heating
+                .iter()
+                .filter(|t2| t2.start_time <= t.start_time && t.start_time < t2.end_time)
+                .count()
+        })
+        .max()
+        .unwrap()
+}
+
+#[test]
+pub fn cumulative_encodings() {
+    for encoding in [
+        ResourceEncoding::Pairwise,
+        ResourceEncoding::PseudoBoolean,
+        ResourceEncoding::TaskInterval,
+        ResourceEncoding::Lazy,
+    ] {
+        for backend in backends() {
+            for capacity in [1, 2] {
+                let problem = oven_problem(capacity, 4);
+                let settings = SolverSettings {
+                    resource_encoding: encoding,
+                    backend: backend.clone(),
+                    ..Default::default()
+                };
+                // The task interval encoding is not a difference logic formula.
+                if backend == SolverBackend::Native && encoding == ResourceEncoding::TaskInterval {
+                    let result = transitionsolver::solve(&problem, &settings);
+                    assert!(matches!(result, Err(SolverError::Backend(_))));
+                    continue;
+                }
+                let solution = transitionsolver::solve(&problem, &settings).unwrap();
+                println!("{:?} capacity {}: {:#?}", encoding, capacity, solution);
+                assert!(max_overlap(&solution) <= capacity as usize);
+            }
+        }
+    }
+}
+
+fn backends() -> Vec<SolverBackend> {
+    let mut backends = Vec::new();
+    if cfg!(feature = "z3") {
+        backends.push(SolverBackend::Z3);
+    }
+    if cfg!(feature = "native") {
+        backends.push(SolverBackend::Native);
+    }
+    backends
+}
+
+#[test]
+pub fn encoding_override() {
+    let problem = oven_problem(2, 3);
+    let mut settings = SolverSettings {
+        resource_encoding: ResourceEncoding::TaskInterval,
+        ..Default::default()
+    };
+    settings.resource_encoding_overrides.insert(
+        ("oven".to_string(), "Available".to_string()),
+        ResourceEncoding::Lazy,
+    );
+    assert!(settings.resource_encoding_for("oven", "Available") == ResourceEncoding::Lazy);
+    assert!(settings.resource_encoding_for("pot1", "Heating") == ResourceEncoding::TaskInterval);
+
+    let solution = transitionsolver::solve(&problem, &settings).unwrap();
+    assert!(max_overlap(&solution) <= 2);
+
+    // The native backend only rejects the task interval encoding when a resource uses it.
+    if cfg!(feature = "native") {
+        settings.backend = SolverBackend::Native;
+        assert!(transitionsolver::solve(&problem, &settings).is_ok());
+        settings.resource_encoding = ResourceEncoding::Lazy;
+        settings.resource_encoding_overrides.insert(
+            ("oven".to_string(), "Available".to_string()),
+            ResourceEncoding::TaskInterval,
+        );
+        assert!(matches!(
+            transitionsolver::solve(&problem, &settings),
+            Err(SolverError::Backend(_))
+        ));
+    }
+}
+
+#[test]
+pub fn lazy_unit_exclusions() {
+    // Nothing keeps the pots from heating at the same time until the candidate
+    // solutions that overlap on the oven have added exclusions.
+    let problem = oven_problem(1, 3);
+    for backend in backends() {
+        let settings = SolverSettings {
+            resource_encoding: ResourceEncoding::Lazy,
+            backend,
+            ..Default::default()
+        };
+        let (result, statistics) = transitionsolver::solve_with_statistics(&problem, &settings);
+        let solution = result.unwrap();
+        println!("{:#?}", solution);
+        assert!(statistics.exclusions > 0);
+        assert!(statistics.solver_calls > 1);
+        assert!(max_overlap(&solution) == 1);
+        assert!(validate_solution(&problem, &solution).is_empty());
+    }
+}
+
+#[test]
+pub fn usage_profiles() {
+    let problem = oven_problem(2, 3);
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+    let profiles = resource_profiles(&problem, &solution);
+    println!("{:#?}", profiles);
+
+    assert!(profiles.len() == 1);
+    let oven = &profiles[0];
+    assert!(oven.timeline == "oven" && oven.capacity == 2);
+    assert!(oven.peak as usize == max_overlap(&solution));
+
+    // The total usage is the total heating time.
+    let heating_time = solution
+        .timelines
+        .iter()
+        .flat_map(|tl| tl.tokens.iter())
+        .filter(|t| t.value == "Heating")
+        .map(|t| t.end_time - t.start_time)
+        .sum::<f32>();
+    let usage_time = oven
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(i, (time, usage))| {
+            let until = oven.steps.get(i + 1).map(|s| s.0).unwrap_or(oven.end_time);
+            (until - time) * *usage as f32
+        })
+        .sum::<f32>();
+    assert!((heating_time - usage_time).abs() < 1e-3);
+
+    for (start, end) in oven.full.iter() {
+        assert!(start < end);
+    }
+}
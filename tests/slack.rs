@@ -0,0 +1,33 @@
+use paraspace::{
+    flexible::ConstraintKind,
+    slack::slack_analysis,
+    transitionsolver::{self, SolverSettings},
+};
+
+mod common;
+use common::oven_problem;
+
+#[test]
+pub fn oven_critical_path() {
+    let problem = oven_problem();
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+    let analysis = slack_analysis(&problem, &solution).unwrap();
+    println!("{:#?}", analysis);
+
+    // Both pots heat one after the other, and one of them is hot for one time unit.
+    assert!((analysis.makespan - 11.0).abs() < 1e-3);
+    assert!(analysis.makespan <= analysis.end_of_time + 1e-3);
+    for token in analysis.tokens.iter() {
+        assert!(token.start_slack >= 0.0 && token.end_slack >= 0.0);
+        if token.value == "Heating" {
+            assert!(token.critical);
+        }
+    }
+
+    let oven = solution.timelines[0].tokens[0].id;
+    let path = &analysis.critical_path;
+    assert!(path.last().unwrap().timepoint == "end of time");
+    assert!(path
+        .iter()
+        .any(|step| step.constraint == ConstraintKind::ResourceOrder { resource: oven }));
+}
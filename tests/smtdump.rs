@@ -0,0 +1,112 @@
+use paraspace::{
+    problem::*,
+    transitionsolver::{self, SolverBackend, SolverSettings},
+    SolverError,
+};
+
+mod common;
+use common::pots_in;
+
+// Pots heated on a stove with capacity for two of them, which must be off before it is on.
+fn pot_problem(n_pots: usize) -> Problem {
+    let stove = Timeline {
+        name: "stove".to_string(),
+        token_types: vec![
+            TokenType {
+                value: "Off".to_string(),
+                conditions: vec![],
+                duration_limits: (1, None),
+                capacity: 0,
+            },
+            TokenType {
+                value: "On".to_string(),
+                conditions: vec![vec![Condition {
+                    temporal_relationship: TemporalRelationship::MetBy,
+                    amount: 0,
+                    timeline_ref: "stove".to_string(),
+                    value: "Off".to_string(),
+                }]],
+                duration_limits: (1, None),
+                capacity: 2,
+            },
+        ],
+        static_tokens: vec![],
+    };
+    pots_in(vec![stove], "On", &vec![5; n_pots])
+}
+
+#[test]
+pub fn smt_dump() {
+    let dir = std::env::temp_dir().join(format!("paraspace_smtdump_{}", std::process::id()));
+    let settings = SolverSettings {
+        smt_dump_dir: Some(dir.clone()),
+        ..Default::default()
+    };
+    let (result, statistics) = transitionsolver::solve_with_statistics(&pot_problem(3), &settings);
+    result.unwrap();
+
+    let index: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("index.json")).unwrap()).unwrap();
+    let iterations = index["iterations"].as_array().unwrap();
+    let literals = index["literals"].as_object().unwrap();
+    assert!(iterations.len() == statistics.solver_calls);
+
+    for kind in ["exp", "cl", "nxstate", "token", "start", "end"] {
+        assert!(literals.values().any(|l| l["kind"] == kind));
+    }
+    let cl = literals.values().find(|l| l["kind"] == "cl").unwrap();
+    assert!(cl["condition_spec"]
+        .as_str()
+        .unwrap()
+        .starts_with("Cover stove.On"));
+    assert!(cl["target"].as_str().unwrap().starts_with("stove["));
+
+    for iteration in iterations {
+        let smt2 = std::fs::read_to_string(dir.join(iteration.as_str().unwrap())).unwrap();
+        let assumptions = smt2
+            .lines()
+            .last()
+            .unwrap()
+            .strip_prefix("(check-sat-assuming (")
+            .unwrap();
+        for name in assumptions
+            .split(['(', ')', ' '])
+            .filter(|n| !n.is_empty() && *n != "not")
+        {
+            assert!(literals.contains_key(name));
+            assert!(smt2.contains(&format!("(declare-fun {} () Bool)", name)));
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn smt_dump_options() {
+    let dir = std::env::temp_dir().join(format!("paraspace_smtoptions_{}", std::process::id()));
+    let settings = SolverSettings {
+        smt_dump_dir: Some(dir.clone()),
+        z3_params: vec![("timeout".to_string(), "100000".to_string())],
+        ..Default::default()
+    };
+    transitionsolver::solve(&pot_problem(1), &settings).unwrap();
+
+    // The Z3 parameters are replayed as options before the formula.
+    let smt2 = std::fs::read_to_string(dir.join("iteration_0001.smt2")).unwrap();
+    if settings.backend == SolverBackend::Z3 {
+        assert!(smt2.contains("(set-option :timeout 100000)\n"));
+        assert!(smt2.find("(set-option").unwrap() < smt2.find("(declare-fun").unwrap());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // A dump directory that cannot be created is an error, not a panic.
+    let file = std::env::temp_dir().join(format!("paraspace_smtfile_{}", std::process::id()));
+    std::fs::write(&file, "").unwrap();
+    let settings = SolverSettings {
+        smt_dump_dir: Some(file.join("dump")),
+        ..Default::default()
+    };
+    let result = transitionsolver::solve(&pot_problem(1), &settings);
+    assert!(matches!(result, Err(SolverError::Io(_))));
+    std::fs::remove_file(&file).unwrap();
+}
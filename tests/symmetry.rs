@@ -0,0 +1,141 @@
+use paraspace::{problem::*, symmetry::SymmetryReduction, transitionsolver};
+
+fn plates_problem(n_plates: usize, n_pots: usize) -> Problem {
+    let mut timelines = Vec::new();
+    for i in 0..n_plates {
+        timelines.push(Timeline {
+            name: format!("plate_{}", i),
+            token_types: vec![],
+            static_tokens: vec![Token {
+                value: "Available".to_string(),
+                const_time: TokenTime::Fact(None, None),
+                capacity: 1,
+                conditions: vec![],
+            }],
+        });
+    }
+
+    for i in 0..n_pots {
+        let name = format!("pot_{}", i);
+        timelines.push(Timeline {
+            name: name.clone(),
+            token_types: vec![
+                TokenType {
+                    value: "Heating".to_string(),
+                    conditions: vec![(0..n_plates)
+                        .map(|p| Condition {
+                            temporal_relationship: TemporalRelationship::Cover,
+                            amount: 1,
+                            timeline_ref: format!("plate_{}", p),
+                            value: "Available".to_string(),
+                        })
+                        .collect()],
+                    duration_limits: (10, Some(10)),
+                    capacity: 0,
+                },
+                TokenType {
+                    value: "Hot".to_string(),
+                    conditions: vec![vec![Condition {
+                        temporal_relationship: TemporalRelationship::MetBy,
+                        amount: 0,
+                        timeline_ref: name.clone(),
+                        value: "Heating".to_string(),
+                    }]],
+                    duration_limits: (1, None),
+                    capacity: 0,
+                },
+            ],
+            static_tokens: vec![Token {
+                value: "Hot".to_string(),
+                const_time: TokenTime::Goal,
+                capacity: 0,
+                conditions: vec![],
+            }],
+        });
+    }
+
+    Problem { timelines }
+}
+
+#[test]
+pub fn pooled_plates() {
+    let problem = plates_problem(2, 3);
+
+    let symmetry = SymmetryReduction::analyze(&problem);
+    assert!(symmetry.pools.len() == 2);
+    assert!(symmetry.pools[0].pooled_resource);
+    assert!(symmetry.pools[0].timelines == vec![0, 1]);
+    assert!(!symmetry.pools[1].pooled_resource);
+    assert!(symmetry.pools[1].timelines == vec![2, 3, 4]);
+
+    let reduced = symmetry.reduced_problem(&problem);
+    assert!(reduced.timelines.len() == 4);
+    assert!(reduced.timelines[0].static_tokens[0].capacity == 2);
+    assert!(reduced.timelines[1].token_types[0].conditions[0].len() == 1);
+
+    let solution = transitionsolver::solve(&problem, &Default::default()).unwrap();
+    println!("SOLUTION {:#?}", solution);
+    assert!(solution.timelines.len() == 5);
+    assert!(solution.timelines[1].name == "plate_1");
+    assert!(solution.timelines[1].tokens[0].value == "Available");
+
+    // At most two pots can be heating at the same time.
+    let heating = solution.timelines[2..]
+        .iter()
+        .map(|tl| &tl.tokens[0])
+        .collect::<Vec<_>>();
+    assert!(heating.iter().all(|t| t.value == "Heating"));
+    for t in heating.iter() {
+        let overlapping = heating
+            .iter()
+            .filter(|t2| t2.start_time <= t.start_time && t.start_time < t2.end_time)
+            .count();
+        assert!(overlapping <= 2);
+    }
+
+    // Each pot is assigned to a plate, and overlapping pots to different plates.
+    let uses = symmetry.pooled_uses(&problem, &solution);
+    assert!(uses.len() == 3);
+    for (i, u) in uses.iter().enumerate() {
+        assert!(u.member < 2 && u.group == 0);
+        let t = &solution.timelines[u.timeline].tokens[u.token];
+        for u2 in uses[i + 1..].iter() {
+            let t2 = &solution.timelines[u2.timeline].tokens[u2.token];
+            if t2.start_time < t.end_time && t.start_time < t2.end_time {
+                assert!(u.member != u2.member);
+            }
+        }
+
+        // The condition satisfaction refers to the assigned plate.
+        assert!(t.conditions.len() == 1 && t.conditions[0].amount == 1);
+        assert!(t.conditions[0].alternative == u.member);
+        assert!(t.conditions[0].target == solution.timelines[u.member].tokens[0].id);
+    }
+}
+
+#[test]
+pub fn asymmetric_references() {
+    let mut problem = plates_problem(2, 2);
+    // Only the first plate can be used by the second pot.
+    problem.timelines[3].token_types[0].conditions[0].truncate(1);
+
+    let symmetry = SymmetryReduction::analyze(&problem);
+    assert!(symmetry.is_empty());
+}
+
+#[test]
+pub fn mixed_alternatives() {
+    let mut problem = plates_problem(2, 2);
+    // A pot that can also heat on the stove cannot be assigned to a plate
+    // from its timeline alone, so the plates are not pooled.
+    let mut stove = problem.timelines[0].clone();
+    stove.name = "stove".to_string();
+    stove.static_tokens[0].capacity = 2;
+    problem.timelines.push(stove);
+    let mut on_stove = problem.timelines[2].token_types[0].conditions[0][0].clone();
+    on_stove.timeline_ref = "stove".to_string();
+    problem.timelines[2].token_types[0].conditions[0].push(on_stove);
+
+    let symmetry = SymmetryReduction::analyze(&problem);
+    assert!(symmetry.pools.iter().all(|p| !p.pooled_resource));
+}
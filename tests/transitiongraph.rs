@@ -0,0 +1,51 @@
+use paraspace::{compiled::CompiledProblem, problem::*};
+
+fn met_by(value: &str) -> Condition {
+    Condition {
+        temporal_relationship: TemporalRelationship::MetBy,
+        amount: 0,
+        timeline_ref: "obj".to_string(),
+        value: value.to_string(),
+    }
+}
+
+fn token_type(value: &str, conditions: Vec<Vec<Condition>>) -> TokenType {
+    TokenType {
+        value: value.to_string(),
+        conditions,
+        duration_limits: (1, None),
+        capacity: 0,
+    }
+}
+
+#[test]
+pub fn chain_distances() {
+    // s1 -> s2 -> s3, and s1 can follow any other value.
+    let problem = Problem {
+        timelines: vec![Timeline {
+            name: "obj".to_string(),
+            token_types: vec![
+                token_type("s1", vec![]),
+                token_type("s2", vec![vec![met_by("s1")]]),
+                token_type("s3", vec![vec![met_by("s2")]]),
+            ],
+            static_tokens: vec![],
+        }],
+    };
+
+    let compiled = CompiledProblem::compile(&problem);
+    let graph = &compiled.transitions[0];
+    let tl = &compiled.timelines[0];
+    let (s1, s2, s3) = (
+        tl.values_by_name["s1"],
+        tl.values_by_name["s2"],
+        tl.values_by_name["s3"],
+    );
+
+    assert!(graph.nodes[graph.initial] == vec![s1]);
+    assert!(graph.next_values(Some(&[s1])) == vec![s2]);
+    assert!(graph.next_values(Some(&[s2])) == vec![s1, s3]);
+    assert!(graph.distance(&[s1], s3) == Some(2));
+    assert!(graph.distance(&[s2], s2) == Some(2));
+    assert!(graph.reachable_values() == vec![s1, s2, s3]);
+}
@@ -0,0 +1,187 @@
+use paraspace::{
+    problem::*,
+    transitionsolver::{self, SolverSettings},
+    validate::{validate_solution, Violation},
+};
+
+mod common;
+use common::{oven_problem, timeline};
+
+fn oven_solution(pot2: &[(&str, f32, f32)]) -> Solution {
+    Solution {
+        timelines: vec![
+            timeline("oven", &[("Available", 0.0, 20.0)]),
+            timeline("pot1", &[("Heating", 0.0, 5.0), ("Hot", 5.0, 20.0)]),
+            timeline("pot2", pot2),
+        ],
+        end_of_time: 20.0,
+    }
+}
+
+#[test]
+pub fn solver_solutions_are_valid() {
+    let problem = oven_problem();
+    let solution = transitionsolver::solve(&problem, &SolverSettings::default()).unwrap();
+    assert!(validate_solution(&problem, &solution).is_empty());
+
+    // Each pot heats in the oven, and is hot after heating.
+    let oven = solution.timelines[0].tokens[0].id;
+    for pot in solution.timelines[1..].iter() {
+        let (heating, hot) = (&pot.tokens[0], &pot.tokens[1]);
+        assert!(heating.conditions.len() == 1 && heating.conditions[0].target == oven);
+        assert!(heating.conditions[0].amount == 1);
+        assert!(hot.conditions.len() == 1 && hot.conditions[0].target == heating.id);
+    }
+
+    // A link to a token that does not satisfy the condition.
+    let mut wrong = solution.clone();
+    wrong.timelines[2].tokens[1].conditions[0].target = wrong.timelines[1].tokens[0].id;
+    assert!(
+        validate_solution(&problem, &wrong)
+            == vec![Violation::Satisfaction {
+                timeline: "pot2".to_string(),
+                token: 1,
+                group: 0,
+            }]
+    );
+}
+
+#[test]
+pub fn invalid_solutions() {
+    let problem = oven_problem();
+    let pot2 = |token| Violation::Condition {
+        timeline: "pot2".to_string(),
+        token,
+        group: 0,
+    };
+
+    let valid = oven_solution(&[("Heating", 5.0, 10.0), ("Hot", 10.0, 20.0)]);
+    assert!(validate_solution(&problem, &valid).is_empty());
+
+    // The pots are in the oven at the same time.
+    let overlapping = oven_solution(&[("Heating", 2.0, 7.0), ("Hot", 7.0, 20.0)]);
+    assert!(
+        validate_solution(&problem, &overlapping)
+            == vec![Violation::Capacity {
+                timeline: "oven".to_string(),
+                token: 0,
+            }]
+    );
+
+    // Too short heating, and a gap before the pot is hot.
+    let short = oven_solution(&[("Heating", 5.0, 8.0), ("Hot", 9.0, 20.0)]);
+    let violations = validate_solution(&problem, &short);
+    println!("{:?}", violations);
+    assert!(violations.contains(&Violation::Duration {
+        timeline: "pot2".to_string(),
+        token: 0,
+    }));
+    assert!(violations.contains(&Violation::Gap {
+        timeline: "pot2".to_string(),
+        token: 1,
+    }));
+    assert!(violations.contains(&pot2(1)));
+
+    // Hot without heating, and heating after the goal.
+    let unordered = oven_solution(&[("Hot", 0.0, 15.0), ("Heating", 15.0, 20.0)]);
+    let violations = validate_solution(&problem, &unordered);
+    println!("{:?}", violations);
+    assert!(violations.contains(&pot2(0)));
+    assert!(violations.contains(&Violation::Goal {
+        timeline: "pot2".to_string(),
+        value: "Hot".to_string(),
+    }));
+}
+
+// A pot that can use the last oven, and pots that all heat at the same time and
+// can use any oven but the last one.
+fn ovens_problem(n_ovens: usize) -> Problem {
+    let oven = |i: usize| Timeline {
+        name: format!("oven{}", i),
+        token_types: vec![],
+        static_tokens: vec![Token {
+            value: "Available".to_string(),
+            const_time: TokenTime::Fact(Some(0), None),
+            capacity: 1,
+            conditions: vec![],
+        }],
+    };
+    let pot = |name: String, ovens: Vec<usize>| Timeline {
+        name,
+        token_types: vec![TokenType {
+            value: "Heating".to_string(),
+            conditions: vec![ovens
+                .iter()
+                .map(|i| Condition {
+                    temporal_relationship: TemporalRelationship::Cover,
+                    amount: 1,
+                    timeline_ref: format!("oven{}", i),
+                    value: "Available".to_string(),
+                })
+                .collect()],
+            duration_limits: (1, Some(1)),
+            capacity: 0,
+        }],
+        static_tokens: vec![],
+    };
+    let mut timelines = vec![pot("first".to_string(), vec![0, n_ovens - 1])];
+    for i in 1..n_ovens {
+        timelines.push(pot(format!("pot{}", i), (0..n_ovens - 1).collect()));
+    }
+    timelines.extend((0..n_ovens).map(oven));
+    Problem { timelines }
+}
+
+#[test]
+pub fn capacity_search_budget() {
+    let n_ovens = 11;
+    let problem = ovens_problem(n_ovens);
+    let mut solution = Solution {
+        timelines: problem
+            .timelines
+            .iter()
+            .map(|tl| {
+                let value = if tl.token_types.is_empty() {
+                    "Available"
+                } else {
+                    "Heating"
+                };
+                timeline(&tl.name, &[(value, 0.0, 1.0)])
+            })
+            .collect(),
+        end_of_time: 1.0,
+    };
+    for (id, tl) in solution.timelines.iter_mut().enumerate() {
+        tl.tokens[0].id = id;
+    }
+
+    // Putting the first pot in the first oven leaves too few ovens for the others,
+    // and the search runs out of budget before it tries the last oven. That is not
+    // a violation.
+    assert!(validate_solution(&problem, &solution).is_empty());
+
+    // The recorded ovens are checked exactly.
+    let oven_id = |i: usize| n_ovens + i;
+    let use_oven = |solution: &mut Solution, pot: usize, alternative: usize, oven: usize| {
+        solution.timelines[pot].tokens[0].conditions = vec![ConditionSatisfaction {
+            group: 0,
+            alternative,
+            target: oven_id(oven),
+            amount: 1,
+        }];
+    };
+    use_oven(&mut solution, 0, 1, n_ovens - 1);
+    for pot in 1..n_ovens {
+        use_oven(&mut solution, pot, pot - 1, pot - 1);
+    }
+    assert!(validate_solution(&problem, &solution).is_empty());
+
+    use_oven(&mut solution, 2, 0, 0);
+    assert!(
+        validate_solution(&problem, &solution)
+            == vec![Violation::Capacity {
+                timeline: "oven0".to_string(),
+                token: 0,
+            }]
+    );
+}